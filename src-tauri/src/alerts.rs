@@ -0,0 +1,138 @@
+use crate::markdown::escape_html;
+use comrak::arena_tree::Node;
+use comrak::nodes::{Ast, AstNode, NodeHtmlBlock, NodeValue};
+use comrak::Arena;
+use std::cell::RefCell;
+
+/// The GitHub alert types, `> [!NOTE]` through `> [!CAUTION]`.
+#[derive(Clone, Copy)]
+pub enum AlertKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AlertKind {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "note" => Some(AlertKind::Note),
+            "tip" => Some(AlertKind::Tip),
+            "important" => Some(AlertKind::Important),
+            "warning" => Some(AlertKind::Warning),
+            "caution" => Some(AlertKind::Caution),
+            _ => None,
+        }
+    }
+
+    /// Lowercase name used in CSS classes and Typst callouts.
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertKind::Note => "note",
+            AlertKind::Tip => "tip",
+            AlertKind::Important => "important",
+            AlertKind::Warning => "warning",
+            AlertKind::Caution => "caution",
+        }
+    }
+
+    fn default_title(self) -> &'static str {
+        match self {
+            AlertKind::Note => "Note",
+            AlertKind::Tip => "Tip",
+            AlertKind::Important => "Important",
+            AlertKind::Warning => "Warning",
+            AlertKind::Caution => "Caution",
+        }
+    }
+}
+
+pub struct Alert {
+    pub kind: AlertKind,
+    /// Text after the marker (`> [!NOTE] Heads up`), or the kind's name.
+    pub title: String,
+}
+
+/// If `node` is a blockquote opening with an alert marker, remove the marker
+/// line from its first paragraph and return the alert.
+pub fn take_alert_marker<'a>(node: &'a AstNode<'a>) -> Option<Alert> {
+    if !matches!(node.data.borrow().value, NodeValue::BlockQuote) {
+        return None;
+    }
+    let paragraph = node.first_child()?;
+    if !matches!(paragraph.data.borrow().value, NodeValue::Paragraph) {
+        return None;
+    }
+
+    // The marker line is the text up to the first line break.
+    let mut line = String::new();
+    let mut marker_nodes = Vec::new();
+    for child in paragraph.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(text) => line.push_str(text),
+            NodeValue::SoftBreak | NodeValue::LineBreak => {
+                marker_nodes.push(child);
+                break;
+            }
+            _ => return None,
+        }
+        marker_nodes.push(child);
+    }
+
+    let rest = line.trim().strip_prefix("[!")?;
+    let (name, title) = rest.split_once(']')?;
+    let kind = AlertKind::parse(name)?;
+    let title = match title.trim() {
+        "" => kind.default_title().to_string(),
+        custom => custom.to_string(),
+    };
+
+    for marker in marker_nodes {
+        marker.detach();
+    }
+    if paragraph.first_child().is_none() {
+        paragraph.detach();
+    }
+    Some(Alert { kind, title })
+}
+
+/// Rewrite alert blockquotes as GitHub-style `markdown-alert` containers.
+pub fn render_alerts<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let quotes: Vec<_> = root
+        .descendants()
+        .filter(|n| matches!(n.data.borrow().value, NodeValue::BlockQuote))
+        .collect();
+
+    for quote in quotes {
+        let alert = match take_alert_marker(quote) {
+            Some(alert) => alert,
+            None => continue,
+        };
+        let sourcepos = quote.data.borrow().sourcepos;
+        let open = format!(
+            "<div class=\"markdown-alert markdown-alert-{}\" data-sourcepos=\"{}\">\n\
+             <p class=\"markdown-alert-title\">{}</p>\n",
+            alert.kind.name(),
+            sourcepos,
+            escape_html(&alert.title)
+        );
+
+        // Splice the quote's children between the opening and closing tags.
+        let html_block = |literal: String| {
+            arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    block_type: 0,
+                    literal,
+                }),
+                sourcepos.start,
+            ))))
+        };
+        quote.insert_before(html_block(open));
+        while let Some(child) = quote.first_child() {
+            quote.insert_before(child);
+        }
+        quote.insert_before(html_block("</div>\n".to_string()));
+        quote.detach();
+    }
+}
@@ -0,0 +1,262 @@
+use crate::front_matter::strip_front_matter;
+use crate::links::{LinkResolver, LinkTarget, PageIndex};
+use crate::markdown::{markdown_options, preprocess_markdown};
+use crate::watcher::TreeDelta;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Arena};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Longest context snippet returned with a backlink, in characters.
+const SNIPPET_LEN: usize = 160;
+
+/// A document that links to the file being viewed.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Backlink {
+    pub source_path: String,
+    pub source_name: String,
+    pub line: usize,
+    /// Text of the block containing the link.
+    pub snippet: String,
+    /// Heading anchor the link points at, if any.
+    pub fragment: Option<String>,
+}
+
+enum RawLink {
+    Url(String),
+    Wiki(String),
+}
+
+struct OutgoingLink {
+    raw: RawLink,
+    target: Option<PathBuf>,
+    fragment: Option<String>,
+    line: usize,
+    snippet: String,
+}
+
+struct IndexedFile {
+    modified: Option<SystemTime>,
+    links: Vec<OutgoingLink>,
+}
+
+/// Which documents in a folder link to which. Built once when a folder is
+/// opened and kept up to date from folder watcher events.
+pub struct BacklinkIndex {
+    root: PathBuf,
+    pages: Arc<PageIndex>,
+    files: HashMap<PathBuf, IndexedFile>,
+}
+
+impl BacklinkIndex {
+    pub fn build(pages: Arc<PageIndex>) -> Self {
+        let mut index = BacklinkIndex {
+            root: pages.root().to_path_buf(),
+            pages: pages.clone(),
+            files: HashMap::new(),
+        };
+        for path in pages.files() {
+            index.index_file(path);
+        }
+        index
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Follow a batch of folder changes: re-parse edited files, drop removed
+    /// ones and index added ones. `pages` is the page index after the changes.
+    pub fn apply(&mut self, pages: Arc<PageIndex>, delta: &TreeDelta, edited: &[PathBuf]) {
+        let structure_changed = !Arc::ptr_eq(&pages, &self.pages);
+        self.pages = pages;
+        for removed in &delta.removed {
+            self.files.retain(|path, _| !path.starts_with(removed));
+        }
+        for renamed in &delta.renamed {
+            self.files.remove(Path::new(&renamed.from));
+        }
+        // Edited files the tree doesn't show were never indexed
+        let edited: Vec<&PathBuf> = edited
+            .iter()
+            .filter(|path| self.files.contains_key(*path))
+            .collect();
+        let added = delta.added.iter().map(PathBuf::from);
+        let moved = delta
+            .renamed
+            .iter()
+            .map(|renamed| PathBuf::from(&renamed.to));
+        for path in added.chain(moved).chain(edited.into_iter().cloned()) {
+            self.index_file(&path);
+        }
+        // Added or removed pages can change where any link resolves.
+        if structure_changed {
+            self.resolve_all();
+        }
+    }
+
+    /// Pick up what changed on disk while the index was being built, by
+    /// re-parsing files whose modification time differs. `pages` is the
+    /// folder's current page index.
+    pub fn catch_up(&mut self, pages: Arc<PageIndex>) {
+        let structure_changed = !Arc::ptr_eq(&pages, &self.pages);
+        let current: HashSet<PathBuf> = pages.files().cloned().collect();
+        if structure_changed {
+            self.pages = pages;
+        }
+
+        self.files.retain(|path, _| current.contains(path));
+        for path in &current {
+            let modified = modified_time(path);
+            let stale = match self.files.get(path) {
+                Some(file) => file.modified != modified,
+                None => true,
+            };
+            if stale {
+                self.index_file(path);
+            }
+        }
+
+        // Added or removed pages can change where any link resolves.
+        if structure_changed {
+            self.resolve_all();
+        }
+    }
+
+    /// Documents with a link resolving to `path`, in path order.
+    pub fn backlinks(&self, path: &Path) -> Vec<Backlink> {
+        let mut found = Vec::new();
+        for (source, file) in &self.files {
+            for link in &file.links {
+                if link.target.as_deref() == Some(path) {
+                    found.push(Backlink {
+                        source_path: source.to_string_lossy().to_string(),
+                        source_name: source
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        line: link.line,
+                        snippet: link.snippet.clone(),
+                        fragment: link.fragment.clone(),
+                    });
+                }
+            }
+        }
+        found.sort_by(|a, b| (&a.source_path, a.line).cmp(&(&b.source_path, b.line)));
+        found
+    }
+
+    fn index_file(&mut self, path: &Path) {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => {
+                self.files.remove(path);
+                return;
+            }
+        };
+        let resolver = LinkResolver::with_pages(path, self.pages.clone());
+        let mut links = extract_links(&content);
+        for link in &mut links {
+            resolve(link, &resolver);
+        }
+        self.files.insert(
+            path.to_path_buf(),
+            IndexedFile {
+                modified: modified_time(path),
+                links,
+            },
+        );
+    }
+
+    fn resolve_all(&mut self) {
+        for (path, file) in &mut self.files {
+            let resolver = LinkResolver::with_pages(path, self.pages.clone());
+            for link in &mut file.links {
+                resolve(link, &resolver);
+            }
+        }
+    }
+}
+
+fn resolve(link: &mut OutgoingLink, resolver: &LinkResolver) {
+    let target = match &link.raw {
+        RawLink::Url(url) => resolver.resolve_url(url),
+        RawLink::Wiki(name) => resolver.resolve_wiki(name),
+    };
+    (link.target, link.fragment) = match target {
+        LinkTarget::Document { path, fragment } => (Some(path), fragment),
+        _ => (None, None),
+    };
+}
+
+/// Collect every link and wiki-link in a document with its line and the
+/// text of the block it appears in.
+fn extract_links(content: &str) -> Vec<OutgoingLink> {
+    let input = preprocess_markdown(&strip_front_matter(content));
+    let options = markdown_options();
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &options);
+
+    let mut links = Vec::new();
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        let raw = match &ast.value {
+            NodeValue::Link(link) => RawLink::Url(link.url.clone()),
+            NodeValue::WikiLink(link) => RawLink::Wiki(link.url.clone()),
+            _ => continue,
+        };
+        links.push(OutgoingLink {
+            raw,
+            target: None,
+            fragment: None,
+            line: ast.sourcepos.start.line,
+            snippet: snippet_for(node),
+        });
+    }
+    links
+}
+
+/// Text of the nearest enclosing paragraph, heading or table cell.
+fn snippet_for<'a>(node: &'a AstNode<'a>) -> String {
+    let block = node
+        .ancestors()
+        .find(|n| {
+            matches!(
+                n.data.borrow().value,
+                NodeValue::Paragraph | NodeValue::Heading(_) | NodeValue::TableCell
+            )
+        })
+        .unwrap_or(node);
+    let mut text = String::new();
+    collect_text(block, &mut text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= SNIPPET_LEN {
+        return text;
+    }
+    let mut snippet: String = text.chars().take(SNIPPET_LEN).collect();
+    snippet.push('…');
+    snippet
+}
+
+fn collect_text<'a>(node: &'a AstNode<'a>, out: &mut String) {
+    match &node.data.borrow().value {
+        NodeValue::Text(text) => out.push_str(text),
+        NodeValue::Code(code) => out.push_str(&code.literal),
+        NodeValue::SoftBreak | NodeValue::LineBreak => out.push(' '),
+        _ => {
+            for child in node.children() {
+                collect_text(child, out);
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
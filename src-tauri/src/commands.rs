@@ -1,11 +1,25 @@
-use crate::markdown::render_markdown;
+use crate::backlinks::{Backlink, BacklinkIndex};
+use crate::front_matter::{extract_metadata, DocumentMetadata};
+use crate::include::include_scope;
+use crate::link_audit::{check_remote_links, LinkAuditor, LinkProblem};
+use crate::links::{shared_pages, LinkResolver, PageIndex};
+use crate::outline::{document_outline, OutlineEntry};
+use crate::render_cache::render_cached;
+use crate::sourcemap::{blocks_for_line, parse_sourcepos, SourceBlock};
 use crate::state::AppState;
+use crate::trust::is_trusted;
+use crate::typst_convert::markdown_to_typst;
 use crate::watcher::watch_file;
 use regex::Regex;
 use serde::Serialize;
 use std::fs;
-use std::path::Path;
-use tauri::{AppHandle, State};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Pause between streamed chunks so the webview can lay each one out.
+const CHUNK_INTERVAL: Duration = Duration::from_millis(16);
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -13,19 +27,28 @@ pub struct RenderResult {
     pub html: String,
     pub file_name: String,
     pub file_path: String,
+    pub metadata: Option<DocumentMetadata>,
+    /// Raw HTML was filtered because the file is outside trusted folders.
+    pub sanitized: bool,
+    /// Large documents return their first chunk in `html`; the others follow
+    /// as `render-chunk` events tagged with this id.
+    pub render_id: u64,
+    pub chunk_count: usize,
 }
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct FolderEntry {
-    pub name: String,
-    pub path: String,
-    pub is_dir: bool,
-    pub children: Option<Vec<FolderEntry>>,
-    pub md_count: usize,
+struct RenderChunk {
+    render_id: u64,
+    index: usize,
+    html: String,
 }
 
-#[tauri::command]
+// Runs off the main thread so large documents don't freeze the window. The
+// whole document is rendered before the first chunk goes out: streaming
+// spreads the webview's parsing and layout over frames, but doesn't make
+// the backend render any sooner.
+#[tauri::command(async)]
 pub fn open_and_render(
     path: String,
     app_handle: AppHandle,
@@ -40,14 +63,67 @@ pub fn open_and_render(
     let content =
         fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))?;
 
-    let html = render_markdown(&content);
-
-    // Rewrite relative image paths to asset:// protocol
+    let root = state.current_folder.lock().unwrap().clone();
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    let resolver = match root {
+        Some(root) if file_path.starts_with(&root) => LinkResolver::with_pages(
+            file_path,
+            shared_pages(&state.page_index, &root, &scan_options),
+        ),
+        _ => LinkResolver::new(file_path, None, &scan_options),
+    };
+    let sanitized = !is_trusted(&state, file_path);
+    let includes = include_scope(&state, file_path);
+    let options = state.render_options.lock().unwrap().clone();
+    let rendered = render_cached(
+        &state,
+        &content,
+        &options,
+        includes.as_ref(),
+        Some(&resolver),
+        sanitized,
+    );
+    let metadata = extract_metadata(&content);
+
+    // Rewrite relative image paths to asset:// protocol, which only serves
+    // the folders of opened documents
+    allow_assets(&app_handle, resolver.root());
     let parent_dir = file_path
         .parent()
         .unwrap_or(Path::new("/"))
         .to_string_lossy();
-    let html = rewrite_image_paths(&html, &parent_dir);
+    let mut chunks = rendered
+        .chunks()
+        .into_iter()
+        .map(|chunk| rewrite_image_paths(chunk, &parent_dir));
+    let html = chunks.next().unwrap_or_default();
+    let rest: Vec<String> = chunks.collect();
+    let chunk_count = rest.len() + 1;
+
+    // A newer render supersedes any chunks still streaming
+    let generation = state.render_generation.clone();
+    let render_id = {
+        let mut current = generation.lock().unwrap();
+        *current += 1;
+        *current
+    };
+    if !rest.is_empty() {
+        let app_handle = app_handle.clone();
+        std::thread::spawn(move || {
+            for (i, html) in rest.into_iter().enumerate() {
+                std::thread::sleep(CHUNK_INTERVAL);
+                if *generation.lock().unwrap() != render_id {
+                    return;
+                }
+                let chunk = RenderChunk {
+                    render_id,
+                    index: i + 1,
+                    html,
+                };
+                let _ = app_handle.emit("render-chunk", chunk);
+            }
+        });
+    }
 
     let file_name = file_path
         .file_name()
@@ -61,8 +137,8 @@ pub fn open_and_render(
         *current = Some(file_path.to_path_buf());
     }
 
-    // Start file watcher
-    match watch_file(app_handle, file_path) {
+    // Start file watcher, covering included files too
+    match watch_file(app_handle, file_path, &rendered.includes) {
         Ok(new_watcher) => {
             let mut watcher = state.watcher.lock().unwrap();
             *watcher = Some(new_watcher);
@@ -74,111 +150,104 @@ pub fn open_and_render(
         html,
         file_name,
         file_path: path,
+        metadata,
+        sanitized,
+        render_id,
+        chunk_count,
     })
 }
 
-#[tauri::command]
-pub fn scan_folder(path: String, state: State<'_, AppState>) -> Result<Vec<FolderEntry>, String> {
-    let folder_path = Path::new(&path);
-
-    if !folder_path.is_dir() {
-        return Err(format!("Not a directory: {}", path));
+/// Index the folder's links in the background. Rescanning the same folder
+/// keeps the existing index, which the folder watcher keeps up to date.
+pub(crate) fn build_backlink_index(folder: &Path, state: &AppState) {
+    let slot = state.backlinks.clone();
+    if let Some(index) = slot.lock().unwrap().as_ref() {
+        if index.root() == folder {
+            return;
+        }
     }
+    let folder = folder.to_path_buf();
+    let page_index = state.page_index.clone();
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    std::thread::spawn(move || {
+        let pages = shared_pages(&page_index, &folder, &scan_options);
+        let mut index = BacklinkIndex::build(pages);
+        // Watcher events during the build found no index to update. Catching
+        // up under the lock leaves later events waiting for the index.
+        let mut slot = slot.lock().unwrap();
+        index.catch_up(shared_pages(&page_index, &folder, &scan_options));
+        *slot = Some(index);
+    });
+}
 
-    // Update current folder in state
-    {
-        let mut current = state.current_folder.lock().unwrap();
-        *current = Some(folder_path.to_path_buf());
+/// Documents in the current folder that link to `path`.
+#[tauri::command(async)]
+pub fn get_backlinks(path: String, state: State<'_, AppState>) -> Vec<Backlink> {
+    let folder = state.current_folder.lock().unwrap().clone();
+    let slot = state.backlinks.lock().unwrap();
+    match slot.as_ref() {
+        // Ignore an index left over from a previously opened folder
+        Some(index) if folder.as_deref() == Some(index.root()) => {
+            index.backlinks(Path::new(&path))
+        }
+        _ => Vec::new(),
     }
-
-    let entries = scan_directory(folder_path, 0)?;
-    Ok(entries)
 }
 
-fn scan_directory(dir: &Path, depth: usize) -> Result<Vec<FolderEntry>, String> {
-    // Limit recursion depth to avoid very deep trees
-    if depth > 20 {
-        return Ok(vec![]);
+/// Check links, wiki-links and images in a file, or in every markdown file
+/// under a folder. Remote URLs are only requested when `check_remote` is set.
+#[tauri::command]
+pub async fn audit_links(
+    path: String,
+    check_remote: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<LinkProblem>, String> {
+    let target = PathBuf::from(&path);
+    if !target.exists() {
+        return Err(format!("File not found: {}", path));
     }
-
-    let mut entries: Vec<FolderEntry> = Vec::new();
-
-    let read_dir =
-        fs::read_dir(dir).map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
-
-    for entry in read_dir {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        let path = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-
-        // Skip hidden files/directories
-        if name.starts_with('.') {
-            continue;
-        }
-        // Skip common non-relevant directories
-        if path.is_dir() {
-            if matches!(
-                name.as_str(),
-                "node_modules" | "target" | ".git" | "__pycache__" | "dist" | "build" | ".next" | ".cache"
-            ) {
-                continue;
-            }
-
-            let children = scan_directory(&path, depth + 1).unwrap_or_default();
-            let md_count = count_md_files(&children);
-
-            // Only include directories that contain md files (directly or in subdirs)
-            if md_count > 0 {
-                entries.push(FolderEntry {
-                    name,
-                    path: path.to_string_lossy().to_string(),
-                    is_dir: true,
-                    children: Some(children),
-                    md_count,
-                });
+    let check_remote = check_remote.unwrap_or(false);
+    let folder = state.current_folder.lock().unwrap().clone();
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    let page_index = state.page_index.clone();
+
+    let (mut problems, remote) = tauri::async_runtime::spawn_blocking(move || {
+        let root = if target.is_dir() {
+            target.clone()
+        } else {
+            match &folder {
+                Some(folder) if target.starts_with(folder) => folder.clone(),
+                _ => target.parent().unwrap_or(Path::new("/")).to_path_buf(),
             }
-        } else if is_markdown_file(&name) {
-            entries.push(FolderEntry {
-                name,
-                path: path.to_string_lossy().to_string(),
-                is_dir: false,
-                children: None,
-                md_count: 0,
-            });
-        }
-    }
-
-    // Sort: directories first, then files, alphabetically
-    entries.sort_by(|a, b| {
-        if a.is_dir == b.is_dir {
-            a.name.to_lowercase().cmp(&b.name.to_lowercase())
-        } else if a.is_dir {
-            std::cmp::Ordering::Less
+        };
+        let pages = if folder.as_ref() == Some(&root) {
+            shared_pages(&page_index, &root, &scan_options)
         } else {
-            std::cmp::Ordering::Greater
-        }
-    });
-
-    Ok(entries)
-}
-
-fn count_md_files(entries: &[FolderEntry]) -> usize {
-    let mut count = 0;
-    for entry in entries {
-        if entry.is_dir {
-            count += entry.md_count;
+            Arc::new(PageIndex::build(&root, &scan_options))
+        };
+        let mut auditor = LinkAuditor::new(pages, check_remote);
+        let files = if target.is_dir() {
+            auditor.files()
         } else {
-            count += 1;
+            vec![target]
+        };
+        let mut problems = Vec::new();
+        for file in files {
+            problems.extend(auditor.audit_file(&file));
         }
+        (problems, std::mem::take(&mut auditor.remote))
+    })
+    .await
+    .map_err(|e| format!("Link audit failed: {}", e))?;
+
+    if !remote.is_empty() {
+        problems.extend(check_remote_links(remote).await);
+        problems.sort_by(|a, b| (&a.file_path, a.line).cmp(&(&b.file_path, b.line)));
     }
-    count
+    Ok(problems)
 }
 
-fn is_markdown_file(name: &str) -> bool {
+pub(crate) fn is_markdown_file(name: &str) -> bool {
     let lower = name.to_lowercase();
     lower.ends_with(".md")
         || lower.ends_with(".markdown")
@@ -205,7 +274,13 @@ pub fn get_initial_file(state: State<'_, AppState>) -> Option<String> {
 }
 
 #[tauri::command]
-pub fn export_pdf(source_path: String, output_path: String, font_size: f64) -> Result<(), String> {
+pub fn export_pdf(
+    source_path: String,
+    output_path: String,
+    font_size: f64,
+    theme: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
     let source = Path::new(&source_path);
     if !source.exists() {
         return Err(format!("File not found: {}", source_path));
@@ -213,7 +288,43 @@ pub fn export_pdf(source_path: String, output_path: String, font_size: f64) -> R
     let content =
         fs::read_to_string(source).map_err(|e| format!("Failed to read file: {}", e))?;
     let output = Path::new(&output_path);
-    crate::pdf_export::export_pdf(&content, source, output, font_size as f32)
+    let theme = theme.as_deref().unwrap_or("github");
+    let options = state.render_options.lock().unwrap().clone();
+    let includes = include_scope(&state, source);
+    let trusted = is_trusted(&state, source);
+    let body = markdown_to_typst(&content, source, includes.as_ref(), trusted, &options);
+    crate::pdf_export::export_pdf(&content, &body, output, font_size as f32, theme)
+}
+
+/// Heading tree for a markdown file, for the outline sidebar.
+#[tauri::command]
+pub fn get_outline(path: String, state: State<'_, AppState>) -> Result<Vec<OutlineEntry>, String> {
+    let content = fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let options = state.render_options.lock().unwrap().clone();
+    let includes = include_scope(&state, Path::new(&path));
+    Ok(document_outline(&content, &options, includes.as_ref()))
+}
+
+/// Map a source line to the preview blocks rendered from it, innermost first.
+#[tauri::command]
+pub fn source_line_to_blocks(
+    content: String,
+    line: usize,
+    state: State<'_, AppState>,
+) -> Vec<SourceBlock> {
+    let options = state.render_options.lock().unwrap().clone();
+    blocks_for_line(&content, line, &options)
+}
+
+/// Map a preview element's `data-sourcepos` back to its source range.
+#[tauri::command]
+pub fn block_to_source(sourcepos: String) -> Result<SourceBlock, String> {
+    parse_sourcepos(&sourcepos).ok_or_else(|| format!("Invalid sourcepos: {}", sourcepos))
+}
+
+#[tauri::command]
+pub fn highlight_theme_css(theme: String, dark: bool) -> String {
+    crate::highlight::theme_css(&theme, dark)
 }
 
 #[tauri::command]
@@ -238,111 +349,11 @@ pub fn open_path(path: String) -> Result<(), String> {
     Ok(())
 }
 
-#[derive(Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct SearchMatch {
-    pub file_path: String,
-    pub file_name: String,
-    pub line_number: usize,
-    pub line_content: String,
-    pub match_start: usize,
-    pub match_end: usize,
-}
-
-#[tauri::command]
-pub fn search_in_files(
-    folder: String,
-    query: String,
-    case_sensitive: bool,
-) -> Result<Vec<SearchMatch>, String> {
-    if query.is_empty() {
-        return Ok(vec![]);
-    }
-    let folder_path = Path::new(&folder);
-    if !folder_path.is_dir() {
-        return Err(format!("Not a directory: {}", folder));
-    }
-    let mut results = Vec::new();
-    search_directory(folder_path, &query, case_sensitive, &mut results, 0);
-    // Limit to 500 matches to avoid overwhelming the UI
-    results.truncate(500);
-    Ok(results)
-}
-
-fn search_directory(
-    dir: &Path,
-    query: &str,
-    case_sensitive: bool,
-    results: &mut Vec<SearchMatch>,
-    depth: usize,
-) {
-    if depth > 20 || results.len() >= 500 {
-        return;
-    }
-
-    let read_dir = match fs::read_dir(dir) {
-        Ok(rd) => rd,
-        Err(_) => return,
-    };
-
-    for entry in read_dir {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        let path = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-
-        if name.starts_with('.') {
-            continue;
-        }
-
-        if path.is_dir() {
-            if matches!(
-                name.as_str(),
-                "node_modules" | "target" | ".git" | "__pycache__" | "dist" | "build" | ".next" | ".cache"
-            ) {
-                continue;
-            }
-            search_directory(&path, query, case_sensitive, results, depth + 1);
-        } else if is_markdown_file(&name) {
-            if results.len() >= 500 {
-                return;
-            }
-            if let Ok(content) = fs::read_to_string(&path) {
-                let query_lower = if case_sensitive {
-                    String::new()
-                } else {
-                    query.to_lowercase()
-                };
-                for (line_idx, line) in content.lines().enumerate() {
-                    let hay = if case_sensitive {
-                        line.to_string()
-                    } else {
-                        line.to_lowercase()
-                    };
-                    let needle = if case_sensitive { query } else { &query_lower };
-                    let mut start = 0;
-                    while let Some(pos) = hay[start..].find(needle) {
-                        let match_start = start + pos;
-                        let match_end = match_start + query.len();
-                        results.push(SearchMatch {
-                            file_path: path.to_string_lossy().to_string(),
-                            file_name: name.clone(),
-                            line_number: line_idx + 1,
-                            line_content: line.to_string(),
-                            match_start,
-                            match_end,
-                        });
-                        if results.len() >= 500 {
-                            return;
-                        }
-                        start = match_start + 1;
-                    }
-                }
-            }
-        }
+/// Let the webview load images under `dir` through the asset protocol. The
+/// scope starts out empty so pages can't read arbitrary files.
+fn allow_assets(app_handle: &AppHandle, dir: &Path) {
+    if let Err(e) = app_handle.asset_protocol_scope().allow_directory(dir, true) {
+        eprintln!("Warning: Could not allow images in {}: {}", dir.display(), e);
     }
 }
 
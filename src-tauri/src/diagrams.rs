@@ -0,0 +1,276 @@
+use crate::pdf_export::parse_svg;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an external renderer may run before it is killed.
+const TOOL_TIMEOUT: Duration = Duration::from_secs(20);
+/// Rendered diagrams kept in memory; the cache is cleared when it fills up.
+const CACHE_CAPACITY: usize = 256;
+
+/// Mermaid labels default to HTML in `<foreignObject>`, which usvg can't draw.
+const MERMAID_CONFIG: &str = r#"{"htmlLabels": false, "flowchart": {"htmlLabels": false}}"#;
+
+/// Fenced code block languages rendered as diagrams.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagramKind {
+    Mermaid,
+    Graphviz,
+    PlantUml,
+}
+
+impl DiagramKind {
+    /// The diagram kind for a code fence's info string, if any.
+    pub fn from_info(info: &str) -> Option<Self> {
+        match info
+            .split_whitespace()
+            .next()?
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "mermaid" => Some(DiagramKind::Mermaid),
+            "dot" | "graphviz" => Some(DiagramKind::Graphviz),
+            "plantuml" | "puml" => Some(DiagramKind::PlantUml),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagramKind::Mermaid => "mermaid",
+            DiagramKind::Graphviz => "graphviz",
+            DiagramKind::PlantUml => "plantuml",
+        }
+    }
+}
+
+/// A diagram rendered to self-contained SVG: text is converted to paths and
+/// styles are resolved, so Typst and the webview draw it the same way.
+pub struct Diagram {
+    pub svg: String,
+    /// Natural size in CSS pixels.
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Render a diagram, or `None` when no renderer is available for its kind or
+/// the source doesn't render. External renderers only run for `trusted`
+/// documents; others get the built-in Graphviz layout and nothing else.
+/// Rendered diagrams are cached by source text; failures are retried, as a
+/// renderer may have been installed since.
+pub fn render_diagram(kind: DiagramKind, source: &str, trusted: bool) -> Option<Arc<Diagram>> {
+    static CACHE: OnceLock<Mutex<HashMap<u64, Arc<Diagram>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+
+    let mut hasher = DefaultHasher::new();
+    (kind, source, trusted).hash(&mut hasher);
+    let key = hasher.finish();
+    if let Some(cached) = cache.lock().unwrap().get(&key) {
+        return Some(cached.clone());
+    }
+
+    let diagram = match render_svg(kind, source, trusted).and_then(|svg| normalize(&svg)) {
+        Ok(diagram) => Arc::new(diagram),
+        Err(e) => {
+            eprintln!("Warning: Could not render {} diagram: {}", kind.name(), e);
+            return None;
+        }
+    };
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, diagram.clone());
+    Some(diagram)
+}
+
+fn render_svg(kind: DiagramKind, source: &str, trusted: bool) -> Result<String, String> {
+    if !trusted {
+        return match kind {
+            DiagramKind::Graphviz => layout_dot(source),
+            _ => Err("external renderers only run for trusted documents".to_string()),
+        };
+    }
+    match kind {
+        DiagramKind::Mermaid => {
+            let config = mermaid_config()?.to_string_lossy().to_string();
+            let mut args = vec!["-i", "-", "-o", "-", "-e", "svg"];
+            args.extend(["-b", "transparent", "-q", "-c", &config]);
+            run_tool("mmdc", &args, &[], source)
+        }
+        // Prefer Graphviz itself; the built-in layout covers common graphs.
+        DiagramKind::Graphviz => {
+            run_tool("dot", &["-Tsvg"], &[], source).or_else(|_| layout_dot(source))
+        }
+        // The sandbox profile stops `!include`, `!import` and URL fetches from
+        // reading files or the network. PlantUML reads it from the environment
+        // or as a JVM system property.
+        DiagramKind::PlantUml => run_tool(
+            "plantuml",
+            &["-tsvg", "-pipe"],
+            &[
+                ("PLANTUML_SECURITY_PROFILE", "SANDBOX"),
+                ("JAVA_TOOL_OPTIONS", "-DPLANTUML_SECURITY_PROFILE=SANDBOX"),
+            ],
+            source,
+        ),
+    }
+}
+
+/// Lay out a DOT graph in-process with `layout-rs`.
+fn layout_dot(source: &str) -> Result<String, String> {
+    use layout::backends::svg::SVGWriter;
+    use layout::gv::{DotParser, GraphBuilder};
+
+    // The layout engine panics on some malformed graphs.
+    catch_unwind(AssertUnwindSafe(|| {
+        let mut parser = DotParser::new(source);
+        let graph = parser
+            .process()
+            .map_err(|e| format!("Invalid DOT graph: {}", e))?;
+        let mut builder = GraphBuilder::new();
+        builder.visit_graph(&graph);
+        let mut visual = builder.get();
+        let mut svg = SVGWriter::new();
+        visual.do_it(false, false, false, &mut svg);
+        Ok(svg.finalize())
+    }))
+    .unwrap_or_else(|_| Err("Graph layout failed".to_string()))
+}
+
+/// Re-serialize SVG through usvg so it has no CSS, `<foreignObject>` or text
+/// elements left for other renderers to disagree about.
+fn normalize(svg: &str) -> Result<Diagram, String> {
+    let tree = parse_svg(svg)?;
+    let size = tree.size();
+    Ok(Diagram {
+        svg: tree.to_string(&usvg::WriteOptions::default()),
+        width: size.width(),
+        height: size.height(),
+    })
+}
+
+/// App data directory, where the Mermaid config is written.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set the app data directory once it is known.
+pub fn set_data_dir(dir: &Path) {
+    let _ = DATA_DIR.set(dir.to_path_buf());
+}
+
+/// The Mermaid config file in the app data directory, written once per run
+/// so other users can't swap it out as they could in a shared temp folder.
+fn mermaid_config() -> Result<PathBuf, String> {
+    static CONFIG: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let dir = DATA_DIR
+                .get()
+                .ok_or("App data directory is not available")?;
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create data directory: {}", e))?;
+            let path = dir.join("mermaid_config.json");
+            fs::write(&path, MERMAID_CONFIG)
+                .map_err(|e| format!("Failed to write Mermaid config: {}", e))?;
+            Ok(path)
+        })
+        .clone()
+}
+
+/// Directories searched for renderers. GUI apps on macOS don't inherit the
+/// shell's PATH, so the usual Homebrew locations are added.
+fn search_path() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    for extra in ["/opt/homebrew/bin", "/usr/local/bin"] {
+        let extra = PathBuf::from(extra);
+        if !dirs.contains(&extra) {
+            dirs.push(extra);
+        }
+    }
+    dirs
+}
+
+fn find_tool(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let names: Vec<String> = if cfg!(windows) {
+        vec![
+            format!("{name}.exe"),
+            format!("{name}.cmd"),
+            format!("{name}.bat"),
+        ]
+    } else {
+        vec![name.to_string()]
+    };
+    dirs.iter()
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Run a renderer with `input` on stdin and extra environment `vars`, and
+/// return its stdout.
+fn run_tool(
+    name: &str,
+    args: &[&str],
+    vars: &[(&str, &str)],
+    input: &str,
+) -> Result<String, String> {
+    let dirs = search_path();
+    let program = find_tool(name, &dirs).ok_or_else(|| format!("{} is not installed", name))?;
+    // Scripts such as mmdc find `node` through PATH.
+    let path = env::join_paths(&dirs).map_err(|e| format!("Invalid PATH: {}", e))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .env("PATH", path)
+        .envs(vars.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", name, e))?;
+
+    // Feed stdin and drain stdout on their own threads so neither pipe fills up.
+    let mut stdin = child.stdin.take().ok_or("Failed to open stdin")?;
+    let input = input.to_string();
+    let writer = thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+    let mut stdout = child.stdout.take().ok_or("Failed to open stdout")?;
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    let deadline = Instant::now() + TOOL_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .map_err(|e| format!("Failed to wait for {}: {}", name, e))?
+        {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("{} timed out", name));
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    let _ = writer.join();
+    let output = reader.join().unwrap_or_default();
+    if !status.success() {
+        return Err(format!("{} exited with {}", name, status));
+    }
+    Ok(output)
+}
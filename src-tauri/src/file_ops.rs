@@ -0,0 +1,416 @@
+use crate::commands::is_markdown_file;
+use crate::recent::{
+    remove_recent_paths, restore_recent_paths, update_recent_paths, RemovedRecents,
+};
+use crate::state::AppState;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, State};
+
+/// File operations remembered for undo.
+const UNDO_LIMIT: usize = 50;
+
+/// A file operation, with what undoing it needs.
+pub enum FileOp {
+    /// A new file or folder, including copies.
+    Created(PathBuf),
+    /// A rename or move.
+    Moved { from: PathBuf, to: PathBuf },
+    /// A file or folder moved to the trash. `trashed` is the item it became
+    /// there, when that could be told apart from others trashed from the
+    /// same place.
+    Trashed {
+        path: PathBuf,
+        trashed: Option<OsString>,
+        recents: RemovedRecents,
+    },
+}
+
+/// Create an empty markdown file in `dir`, adding `.md` when `name` has no
+/// markdown extension. Taken names get a number: `Notes 2.md`.
+#[tauri::command]
+pub fn create_file(
+    dir: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let name = valid_name(&name)?;
+    let name = if is_markdown_file(name) {
+        name.to_string()
+    } else {
+        format!("{}.md", name)
+    };
+    let path = unique_path(Path::new(&dir), &name);
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create file: {}", e))?;
+    record(&state, FileOp::Created(path.clone()));
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn create_folder(
+    dir: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let name = valid_name(&name)?;
+    let path = unique_path(Path::new(&dir), name);
+    fs::create_dir(&path).map_err(|e| format!("Failed to create folder: {}", e))?;
+    record(&state, FileOp::Created(path.clone()));
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Rename a file or folder in place. Fails if the new name is taken.
+#[tauri::command]
+pub fn rename_path(
+    path: String,
+    new_name: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let from = PathBuf::from(&path);
+    let name = valid_name(&new_name)?;
+    let parent = from
+        .parent()
+        .ok_or_else(|| format!("Cannot rename {}", path))?;
+    let to = parent.join(name);
+    if to == from {
+        return Ok(path);
+    }
+    // Changing only the case finds the file itself on case-insensitive disks
+    if to.exists() && !same_file(&from, &to) {
+        return Err(format!("{} already exists", name));
+    }
+    move_to(&app_handle, &state, from, to)
+}
+
+/// Move a file or folder into `dest_dir`, numbering the name if it is taken.
+#[tauri::command]
+pub fn move_path(
+    path: String,
+    dest_dir: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let from = PathBuf::from(&path);
+    let dest = Path::new(&dest_dir);
+    if dest.starts_with(&from) {
+        return Err("Cannot move a folder into itself".to_string());
+    }
+    if from.parent() == Some(dest) {
+        return Ok(path);
+    }
+    let name = from
+        .file_name()
+        .ok_or_else(|| format!("Cannot move {}", path))?;
+    let to = unique_path(dest, &name.to_string_lossy());
+    move_to(&app_handle, &state, from, to)
+}
+
+/// Copy a file or folder next to itself as `name copy`, `name copy 2`...
+#[tauri::command]
+pub fn duplicate_path(path: String, state: State<'_, AppState>) -> Result<String, String> {
+    let from = PathBuf::from(&path);
+    let (Some(parent), Some(name)) = (from.parent(), from.file_name()) else {
+        return Err(format!("Cannot duplicate {}", path));
+    };
+    let name = name.to_string_lossy();
+    let copy = if from.is_dir() {
+        unique_path(parent, &format!("{} copy", name))
+    } else {
+        let (stem, ext) = split_name(&name);
+        unique_path(parent, &format!("{} copy{}", stem, ext))
+    };
+    let copied = if from.is_dir() {
+        copy_dir(&from, &copy)
+    } else {
+        fs::copy(&from, &copy).map(|_| ())
+    };
+    copied.map_err(|e| format!("Failed to duplicate: {}", e))?;
+    record(&state, FileOp::Created(copy.clone()));
+    Ok(copy.to_string_lossy().to_string())
+}
+
+/// Move a file or folder to the OS trash.
+#[tauri::command]
+pub fn trash_path(
+    path: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let path = PathBuf::from(&path);
+    let before = trash_entries(&path);
+    trash::delete(&path).map_err(|e| format!("Failed to move to trash: {}", e))?;
+    let trashed = before.and_then(|before| new_entry(&before, trash_entries(&path)?));
+    let recents = remove_recent_paths(&app_handle, &state, &path);
+    record(
+        &state,
+        FileOp::Trashed {
+            path,
+            trashed,
+            recents,
+        },
+    );
+    Ok(())
+}
+
+/// Undo the last file operation. Created files go to the trash, moves are
+/// reversed and trashed files are restored. Returns the path that exists
+/// again, if any.
+#[tauri::command]
+pub fn undo_file_op(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    let restored = undo_last(&state.file_ops, |op| undo(&app_handle, &state, op))?;
+    Ok(restored.map(|path| path.to_string_lossy().to_string()))
+}
+
+/// Run `undo` on the last operation in `journal`, keeping the operation
+/// there when it fails so it can be tried again.
+fn undo_last<T>(
+    journal: &Mutex<Vec<FileOp>>,
+    undo: impl FnOnce(&FileOp) -> Result<T, String>,
+) -> Result<T, String> {
+    let Some(op) = journal.lock().unwrap().pop() else {
+        return Err("Nothing to undo".to_string());
+    };
+    let result = undo(&op);
+    if result.is_err() {
+        journal.lock().unwrap().push(op);
+    }
+    result
+}
+
+fn undo(app_handle: &AppHandle, state: &AppState, op: &FileOp) -> Result<Option<PathBuf>, String> {
+    match op {
+        FileOp::Created(path) => {
+            trash::delete(path).map_err(|e| format!("Failed to move to trash: {}", e))?;
+            sync_recents(app_handle, state, path, None);
+            Ok(None)
+        }
+        FileOp::Moved { from, to } => {
+            if from.exists() {
+                return Err(format!("{} already exists", from.display()));
+            }
+            fs::rename(to, from).map_err(|e| format!("Failed to move back: {}", e))?;
+            sync_recents(app_handle, state, to, Some(from));
+            Ok(Some(from.clone()))
+        }
+        FileOp::Trashed {
+            path,
+            trashed,
+            recents,
+        } => {
+            if path.exists() {
+                return Err(format!("{} already exists", path.display()));
+            }
+            let trashed = trashed.as_ref().ok_or_else(|| {
+                format!("Cannot tell which item in the trash is {}", path.display())
+            })?;
+            restore_from_trash(path, trashed)?;
+            if let Err(e) = restore_recent_paths(app_handle, state, recents) {
+                eprintln!("Warning: Could not update recent items: {}", e);
+            }
+            Ok(Some(path.clone()))
+        }
+    }
+}
+
+fn move_to(
+    app_handle: &AppHandle,
+    state: &AppState,
+    from: PathBuf,
+    to: PathBuf,
+) -> Result<String, String> {
+    fs::rename(&from, &to).map_err(|e| format!("Failed to move: {}", e))?;
+    sync_recents(app_handle, state, &from, Some(&to));
+    let moved = to.to_string_lossy().to_string();
+    record(state, FileOp::Moved { from, to });
+    Ok(moved)
+}
+
+fn record(state: &AppState, op: FileOp) {
+    let mut journal = state.file_ops.lock().unwrap();
+    journal.push(op);
+    if journal.len() > UNDO_LIMIT {
+        journal.remove(0);
+    }
+}
+
+fn sync_recents(app_handle: &AppHandle, state: &AppState, from: &Path, to: Option<&Path>) {
+    if let Err(e) = update_recent_paths(app_handle, state, from, to) {
+        eprintln!("Warning: Could not update recent items: {}", e);
+    }
+}
+
+fn valid_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("Invalid name: {}", name));
+    }
+    Ok(name)
+}
+
+/// `dir/name`, or `dir/name 2`, `dir/name 3`... when that is taken.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, ext) = split_name(name);
+    (2..)
+        .map(|n| dir.join(format!("{} {}{}", stem, n, ext)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or(path)
+}
+
+/// Split `notes.md` into `notes` and `.md`; dotfiles have no extension.
+fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 => name.split_at(i),
+        _ => (name, ""),
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// The one entry in `after` that wasn't in `before`, if there is exactly one.
+fn new_entry(before: &HashSet<OsString>, after: HashSet<OsString>) -> Option<OsString> {
+    let mut new = after.into_iter().filter(|entry| !before.contains(entry));
+    let entry = new.next()?;
+    new.next().is_none().then_some(entry)
+}
+
+/// Ids of the trash items that came from `path`.
+#[cfg(not(target_os = "macos"))]
+fn trash_entries(path: &Path) -> Option<HashSet<OsString>> {
+    let items = trash::os_limited::list()
+        .map_err(|e| eprintln!("Warning: Could not read trash: {}", e))
+        .ok()?;
+    Some(
+        items
+            .into_iter()
+            .filter(|item| item.original_path() == path)
+            .map(|item| item.id)
+            .collect(),
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn restore_from_trash(path: &Path, trashed: &OsString) -> Result<(), String> {
+    let items = trash::os_limited::list().map_err(|e| format!("Failed to read trash: {}", e))?;
+    let item = items
+        .into_iter()
+        .find(|item| &item.id == trashed)
+        .ok_or_else(|| format!("{} is no longer in the trash", path.display()))?;
+    trash::os_limited::restore_all([item])
+        .map_err(|e| format!("Failed to restore from trash: {}", e))
+}
+
+/// Paths of everything in `~/.Trash`. The trash crate can't list or restore
+/// on macOS, so items are told apart by what appears there.
+#[cfg(target_os = "macos")]
+fn trash_entries(_path: &Path) -> Option<HashSet<OsString>> {
+    let home = std::env::var("HOME").ok()?;
+    let entries = fs::read_dir(Path::new(&home).join(".Trash"))
+        .map_err(|e| eprintln!("Warning: Could not read trash: {}", e))
+        .ok()?;
+    Some(
+        entries
+            .flatten()
+            .map(|entry| entry.path().into_os_string())
+            .collect(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn restore_from_trash(path: &Path, trashed: &OsString) -> Result<(), String> {
+    let trashed = Path::new(trashed);
+    if !trashed.exists() {
+        return Err(format!("{} is no longer in the trash", path.display()));
+    }
+    fs::rename(trashed, path).map_err(|e| format!("Failed to restore from trash: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn entries(names: &[&str]) -> HashSet<OsString> {
+        names.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn new_entry_finds_the_one_new_item() {
+        let before = entries(&["a", "b"]);
+        assert_eq!(
+            new_entry(&before, entries(&["a", "b", "c"])),
+            Some(OsString::from("c"))
+        );
+    }
+
+    #[test]
+    fn new_entry_refuses_when_ambiguous_or_missing() {
+        let before = entries(&["a"]);
+        assert_eq!(new_entry(&before, entries(&["a", "b", "c"])), None);
+        assert_eq!(new_entry(&before, entries(&["a"])), None);
+    }
+
+    #[test]
+    fn failed_undo_stays_in_the_journal() {
+        let journal = Mutex::new(vec![FileOp::Created(PathBuf::from("/a.md"))]);
+        let result: Result<(), String> = undo_last(&journal, |_| Err("busy".to_string()));
+        assert_eq!(result, Err("busy".to_string()));
+        assert_eq!(journal.lock().unwrap().len(), 1);
+
+        let undone = undo_last(&journal, |op| match op {
+            FileOp::Created(path) => Ok(path.clone()),
+            _ => Err("wrong op".to_string()),
+        });
+        assert_eq!(undone, Ok(PathBuf::from("/a.md")));
+        assert!(journal.lock().unwrap().is_empty());
+        assert!(undo_last(&journal, |_| Ok(())).is_err());
+    }
+
+    /// Trashes and restores a file in the user's real trash.
+    #[test]
+    #[ignore]
+    fn trash_round_trip() {
+        let dir = TempDir::new("trash");
+        let path = dir.write("notes.md", "# Notes");
+
+        let before = trash_entries(&path).unwrap();
+        trash::delete(&path).unwrap();
+        let trashed = new_entry(&before, trash_entries(&path).unwrap()).unwrap();
+        assert!(!path.exists());
+        restore_from_trash(&path, &trashed).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "# Notes");
+    }
+}
@@ -0,0 +1,632 @@
+use crate::commands::{build_backlink_index, is_markdown_file};
+use crate::front_matter::{extract_metadata, strip_front_matter};
+use crate::git;
+use crate::search_index;
+use crate::state::AppState;
+use crate::watcher::watch_folder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, State};
+
+/// Entries returned per `list_folder` call.
+const PAGE_SIZE: usize = 200;
+/// Files returned by `filter_folder`.
+const FILTER_LIMIT: usize = 1000;
+/// How often a running scan reports progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Per-folder file listing entry names in manual sort order, one per line.
+pub const ORDER_FILE: &str = ".mreorder";
+
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderEntry {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    /// Loaded children; `None` for folders that are listed on demand.
+    pub children: Option<Vec<FolderEntry>>,
+    /// Markdown files under a folder, once they have been counted.
+    pub md_count: Option<usize>,
+    /// Last modification, in milliseconds since the Unix epoch.
+    pub modified: Option<u64>,
+    /// File size in bytes.
+    pub size: Option<u64>,
+    pub word_count: Option<usize>,
+    /// Front matter title, or else the first `# ` heading.
+    pub title: Option<String>,
+    /// Git status such as "clean", "modified" or "untracked"; folders are
+    /// "modified" when anything inside changed. `None` outside a repository.
+    pub git_status: Option<String>,
+}
+
+/// Order of a folder's entries in `list_folder`.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortMode {
+    /// Folders first, then files, alphabetically.
+    #[default]
+    Name,
+    /// Folders first, then most recently modified first.
+    Modified,
+    /// Folders first, then by title, falling back to the file name.
+    Title,
+    /// As listed in the folder's `.mreorder` file, then by name.
+    Manual,
+}
+
+/// One page of a folder's direct children.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderPage {
+    pub entries: Vec<FolderEntry>,
+    /// Offset of the next page when there are more entries.
+    pub next_offset: Option<usize>,
+}
+
+/// Markdown files found by the last completed scan of the open folder, kept
+/// up to date by the folder watcher.
+pub struct FolderScan {
+    pub root: PathBuf,
+    /// Markdown files under each folder, for folders that have any.
+    pub counts: HashMap<PathBuf, usize>,
+    pub files: BTreeSet<PathBuf>,
+}
+
+impl FolderScan {
+    /// Whether `path` is a known markdown file or a folder containing any.
+    pub fn contains(&self, path: &Path) -> bool {
+        // Paths sort by component, so files under a folder follow it directly
+        self.files
+            .range(path.to_path_buf()..)
+            .next()
+            .is_some_and(|file| file.starts_with(path))
+    }
+
+    /// Drop `removed` files and folders and add `added` files. Returns the
+    /// folders whose count changed, with 0 for folders left without markdown.
+    pub fn apply(&mut self, added: &[PathBuf], removed: &[PathBuf]) -> HashMap<PathBuf, usize> {
+        for path in removed {
+            self.files.retain(|file| !file.starts_with(path));
+        }
+        self.files.extend(added.iter().cloned());
+        let counts = count_files(&self.root, &self.files);
+        let mut changed: HashMap<PathBuf, usize> = counts
+            .iter()
+            .filter(|(dir, count)| self.counts.get(*dir) != Some(*count))
+            .map(|(dir, count)| (dir.clone(), *count))
+            .collect();
+        for dir in self.counts.keys() {
+            if !counts.contains_key(dir) {
+                changed.insert(dir.clone(), 0);
+            }
+        }
+        self.counts = counts;
+        changed
+    }
+}
+
+/// Git statuses of listed folders, by folder, taken when their first page is
+/// listed and reused for the pages after it.
+pub type GitStatusCache = HashMap<PathBuf, Option<Arc<HashMap<PathBuf, String>>>>;
+
+/// Markdown files a running scan has found so far.
+pub struct PartialScan {
+    root: PathBuf,
+    scan_id: u64,
+    files: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanProgress {
+    scan_id: u64,
+    /// Entries walked so far.
+    scanned: usize,
+    /// Markdown files found so far.
+    found: usize,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScanDone {
+    scan_id: u64,
+    found: usize,
+    counts: HashMap<String, usize>,
+}
+
+/// Open `path` as the current folder and count its markdown files in the
+/// background while watching it for changes and catching up its search index.
+/// The scan reports `folder-scan-progress` events, then
+/// `folder-scan-done` with the count for every folder. Starting another scan
+/// or calling `cancel_folder_scan` stops it. Returns the scan id.
+#[tauri::command]
+pub fn start_folder_scan(
+    path: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let root = PathBuf::from(&path);
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    *state.current_folder.lock().unwrap() = Some(root.clone());
+    build_backlink_index(&root, &state);
+    // Titles are only kept current while their folder is watched
+    state.title_cache.lock().unwrap().clear();
+    state.git_statuses.lock().unwrap().clear();
+
+    let generation = state.folder_scan_generation.clone();
+    let scan_id = {
+        let mut current = generation.lock().unwrap();
+        *current += 1;
+        *current
+    };
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    let slot = state.folder_scan.clone();
+    let partial = state.partial_scan.clone();
+    *partial.lock().unwrap() = Some(PartialScan {
+        root: root.clone(),
+        scan_id,
+        files: Vec::new(),
+    });
+
+    // Replacing the watcher drops the previous folder's one
+    let watcher = watch_folder(
+        app_handle.clone(),
+        &root,
+        scan_options.clone(),
+        slot.clone(),
+    );
+    *state.folder_watcher.lock().unwrap() = match watcher {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+    search_index::open_in_background(app_handle.clone(), root.clone(), scan_options.clone());
+
+    std::thread::spawn(move || {
+        let cancelled = || *generation.lock().unwrap() != scan_id;
+        // Hand files found since the last report to `folder_files`, or drop
+        // the partial list once the scan is over
+        let publish = |found: Option<&mut Vec<PathBuf>>| {
+            let mut partial = partial.lock().unwrap();
+            if partial.as_ref().is_some_and(|p| p.scan_id != scan_id) {
+                return;
+            }
+            match (partial.as_mut(), found) {
+                (Some(partial), Some(found)) => partial.files.append(found),
+                _ => *partial = None,
+            }
+        };
+        let mut files = BTreeSet::new();
+        let mut found = Vec::new();
+        let mut scanned = 0;
+        let mut last_report = Instant::now();
+        for entry in scan_options.walker(&root).build().flatten() {
+            if cancelled() {
+                publish(None);
+                return;
+            }
+            scanned += 1;
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            if is_file && is_markdown_file(&entry.file_name().to_string_lossy()) {
+                found.push(entry.path().to_path_buf());
+                files.insert(entry.into_path());
+            }
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                publish(Some(&mut found));
+                let progress = ScanProgress {
+                    scan_id,
+                    scanned,
+                    found: files.len(),
+                };
+                let _ = app_handle.emit("folder-scan-progress", progress);
+                last_report = Instant::now();
+            }
+        }
+        if cancelled() {
+            publish(None);
+            return;
+        }
+        let counts = count_files(&root, &files);
+        let done = ScanDone {
+            scan_id,
+            found: files.len(),
+            counts: counts
+                .iter()
+                .map(|(dir, count)| (dir.to_string_lossy().to_string(), *count))
+                .collect(),
+        };
+        *slot.lock().unwrap() = Some(FolderScan {
+            root,
+            counts,
+            files,
+        });
+        publish(None);
+        let _ = app_handle.emit("folder-scan-done", done);
+    });
+    Ok(scan_id)
+}
+
+#[tauri::command]
+pub fn cancel_folder_scan(state: State<'_, AppState>) {
+    *state.folder_scan_generation.lock().unwrap() += 1;
+}
+
+/// One page of a folder's direct children, subfolders and markdown files, in
+/// `sort` order. Once the folder has been scanned, subfolders carry their
+/// markdown count and those without markdown are left out. Files on the page
+/// come with their title, word count and git status.
+#[tauri::command(async)]
+pub fn list_folder(
+    path: String,
+    offset: Option<usize>,
+    sort: Option<SortMode>,
+    state: State<'_, AppState>,
+) -> Result<FolderPage, String> {
+    let dir = Path::new(&path);
+    if !dir.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    let root = state
+        .current_folder
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|root| dir.starts_with(root))
+        .unwrap_or_else(|| dir.to_path_buf());
+    let scan_options = state.scan_options.lock().unwrap().clone();
+
+    let mut entries = Vec::new();
+    let mut walker = scan_options.walker_at(&root, dir);
+    walker.max_depth(Some(1));
+    for entry in walker.build().flatten() {
+        if entry.depth() == 0 {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        let meta = entry.metadata().ok();
+        let modified = meta
+            .as_ref()
+            .and_then(|meta| meta.modified().ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_millis() as u64);
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            entries.push(FolderEntry {
+                name,
+                path: path.to_string_lossy().to_string(),
+                is_dir: true,
+                modified,
+                ..Default::default()
+            });
+        } else if is_markdown_file(&name) {
+            entries.push(FolderEntry {
+                name,
+                path: path.to_string_lossy().to_string(),
+                modified,
+                size: meta.map(|meta| meta.len()),
+                ..Default::default()
+            });
+        }
+    }
+
+    // Counts are looked up once the walk is done, so the scan and the
+    // watcher never wait for it
+    let scan = state.folder_scan.lock().unwrap();
+    if let Some(scan) = scan.as_ref().filter(|scan| scan.root == root) {
+        entries.retain_mut(|entry| {
+            if !entry.is_dir {
+                return true;
+            }
+            let count = scan
+                .counts
+                .get(Path::new(&entry.path))
+                .copied()
+                .unwrap_or(0);
+            entry.md_count = Some(count);
+            count > 0
+        });
+    }
+    drop(scan);
+
+    let sort = sort.unwrap_or_default();
+    if sort == SortMode::Title {
+        entries.iter_mut().for_each(read_document_info);
+    }
+    sort_entries_by(&mut entries, sort, dir);
+
+    let offset = offset.unwrap_or(0);
+    let total = entries.len();
+    let mut entries: Vec<FolderEntry> = entries.into_iter().skip(offset).take(PAGE_SIZE).collect();
+    let statuses = folder_git_statuses(dir, offset == 0, &state);
+    for entry in &mut entries {
+        if !entry.is_dir && entry.word_count.is_none() {
+            read_document_info(entry);
+        }
+        if let Some(statuses) = &statuses {
+            entry.git_status = Some(git_status(statuses, entry));
+        }
+    }
+    let next = offset + entries.len();
+    Ok(FolderPage {
+        entries,
+        next_offset: (next < total).then_some(next),
+    })
+}
+
+/// Git statuses under `dir`, read again for a folder's first page and taken
+/// from the cache for the pages after it.
+fn folder_git_statuses(
+    dir: &Path,
+    refresh: bool,
+    state: &AppState,
+) -> Option<Arc<HashMap<PathBuf, String>>> {
+    if !refresh {
+        if let Some(statuses) = state.git_statuses.lock().unwrap().get(dir) {
+            return statuses.clone();
+        }
+    }
+    let statuses = git::get_statuses(dir).map(Arc::new);
+    state
+        .git_statuses
+        .lock()
+        .unwrap()
+        .insert(dir.to_path_buf(), statuses.clone());
+    statuses
+}
+
+/// Markdown files under `root`, from the background scan's file list once it
+/// has finished and what it has found so far while it runs. Folders that
+/// aren't being scanned are walked.
+pub fn folder_files(root: &Path, state: &AppState) -> Vec<PathBuf> {
+    let scan = state.folder_scan.lock().unwrap();
+    if let Some(scan) = scan.as_ref().filter(|scan| scan.root == root) {
+        return scan.files.iter().cloned().collect();
+    }
+    drop(scan);
+    let partial = state.partial_scan.lock().unwrap();
+    if let Some(partial) = partial.as_ref().filter(|partial| partial.root == root) {
+        return partial.files.clone();
+    }
+    drop(partial);
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    scan_options
+        .walker(root)
+        .build()
+        .flatten()
+        .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+        .filter(|e| is_markdown_file(&e.file_name().to_string_lossy()))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Markdown files under `path` whose relative path contains `query`
+/// (case-insensitive), optionally only those in `only`, as a fully loaded
+/// tree. Uses the background scan's file list, or what it has found so far.
+#[tauri::command(async)]
+pub fn filter_folder(
+    path: String,
+    query: String,
+    only: Option<Vec<String>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<FolderEntry>, String> {
+    let root = Path::new(&path);
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    let files = folder_files(root, &state);
+
+    let query = query.to_lowercase();
+    let mut tree = TreeNode::default();
+    let mut matched = 0;
+    for file in &files {
+        let Ok(relative) = file.strip_prefix(root) else {
+            continue;
+        };
+        if !relative.to_string_lossy().to_lowercase().contains(&query) {
+            continue;
+        }
+        if let Some(only) = &only {
+            if !only.iter().any(|p| Path::new(p) == file) {
+                continue;
+            }
+        }
+        tree.insert(relative);
+        matched += 1;
+        if matched >= FILTER_LIMIT {
+            break;
+        }
+    }
+    Ok(tree_entries(root, tree))
+}
+
+/// Markdown files grouped by folder, for turning a file list into a tree.
+#[derive(Default)]
+struct TreeNode {
+    dirs: HashMap<String, TreeNode>,
+    files: Vec<String>,
+}
+
+impl TreeNode {
+    fn insert(&mut self, relative: &Path) {
+        let mut node = self;
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            let name = component.as_os_str().to_string_lossy().to_string();
+            if components.peek().is_none() {
+                node.files.push(name);
+            } else {
+                node = node.dirs.entry(name).or_default();
+            }
+        }
+    }
+}
+
+fn tree_entries(dir: &Path, tree: TreeNode) -> Vec<FolderEntry> {
+    let mut entries: Vec<FolderEntry> = Vec::new();
+    for (name, node) in tree.dirs {
+        let path = dir.join(&name);
+        let children = tree_entries(&path, node);
+        let md_count = count_md_files(&children);
+        entries.push(FolderEntry {
+            name,
+            path: path.to_string_lossy().to_string(),
+            is_dir: true,
+            children: Some(children),
+            md_count: Some(md_count),
+            ..Default::default()
+        });
+    }
+    for name in tree.files {
+        entries.push(FolderEntry {
+            path: dir.join(&name).to_string_lossy().to_string(),
+            name,
+            ..Default::default()
+        });
+    }
+    sort_entries(&mut entries);
+    entries
+}
+
+/// Markdown files under each folder from `root` down.
+fn count_files(root: &Path, files: &BTreeSet<PathBuf>) -> HashMap<PathBuf, usize> {
+    let mut counts: HashMap<PathBuf, usize> = HashMap::new();
+    for file in files {
+        for dir in file.ancestors().skip(1) {
+            *counts.entry(dir.to_path_buf()).or_default() += 1;
+            if dir == root {
+                break;
+            }
+        }
+    }
+    counts
+}
+
+fn count_md_files(entries: &[FolderEntry]) -> usize {
+    entries
+        .iter()
+        .map(|entry| {
+            if entry.is_dir {
+                entry.md_count.unwrap_or(0)
+            } else {
+                1
+            }
+        })
+        .sum()
+}
+
+/// Write the manual order of a folder's entries to its `.mreorder` file.
+#[tauri::command]
+pub fn set_folder_order(path: String, names: Vec<String>) -> Result<(), String> {
+    let mut contents = names.join("\n");
+    contents.push('\n');
+    fs::write(Path::new(&path).join(ORDER_FILE), contents)
+        .map_err(|e| format!("Failed to save folder order: {}", e))
+}
+
+/// Fill in a markdown file's title and word count from its contents.
+fn read_document_info(entry: &mut FolderEntry) {
+    if entry.is_dir {
+        return;
+    }
+    let Ok(content) = fs::read_to_string(&entry.path) else {
+        return;
+    };
+    let body = strip_front_matter(&content);
+    entry.title = extract_metadata(&content)
+        .and_then(|meta| meta.title)
+        .or_else(|| first_heading(&body));
+    let words = body
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric));
+    entry.word_count = Some(words.count());
+}
+
+/// Text of the first `# ` heading outside fenced code.
+pub(crate) fn first_heading(body: &str) -> Option<String> {
+    let mut fence: Option<&str> = None;
+    for line in body.lines() {
+        let line = line.trim_start();
+        if let Some(marker) = fence {
+            if line.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if line.starts_with("```") {
+            fence = Some("```");
+        } else if line.starts_with("~~~") {
+            fence = Some("~~~");
+        } else if let Some(text) = line.strip_prefix("# ") {
+            let text = text.trim().trim_end_matches('#').trim();
+            if !text.is_empty() {
+                return Some(text.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn git_status(statuses: &HashMap<PathBuf, String>, entry: &FolderEntry) -> String {
+    let path = Path::new(&entry.path);
+    if entry.is_dir {
+        let changed = statuses.keys().any(|file| file.starts_with(path));
+        if changed { "modified" } else { "clean" }.to_string()
+    } else {
+        statuses
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| "clean".to_string())
+    }
+}
+
+/// Entry names of a folder's `.mreorder` file with their positions.
+fn read_order(dir: &Path) -> HashMap<String, usize> {
+    let contents = fs::read_to_string(dir.join(ORDER_FILE)).unwrap_or_default();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(i, name)| (name.to_string(), i))
+        .collect()
+}
+
+fn sort_entries_by(entries: &mut [FolderEntry], sort: SortMode, dir: &Path) {
+    sort_entries(entries);
+    // The sorts below are stable, so ties stay in name order
+    match sort {
+        SortMode::Name => {}
+        SortMode::Modified => {
+            entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then(b.modified.cmp(&a.modified)))
+        }
+        SortMode::Title => entries.sort_by_cached_key(|entry| {
+            let title = entry.title.as_deref().unwrap_or(&entry.name);
+            (!entry.is_dir, title.to_lowercase())
+        }),
+        SortMode::Manual => {
+            let order = read_order(dir);
+            entries.sort_by_key(|entry| order.get(&entry.name).copied().unwrap_or(usize::MAX));
+        }
+    }
+}
+
+/// Sort: directories first, then files, alphabetically
+fn sort_entries(entries: &mut [FolderEntry]) {
+    entries.sort_by(|a, b| {
+        if a.is_dir == b.is_dir {
+            a.name.to_lowercase().cmp(&b.name.to_lowercase())
+        } else if a.is_dir {
+            std::cmp::Ordering::Less
+        } else {
+            std::cmp::Ordering::Greater
+        }
+    });
+}
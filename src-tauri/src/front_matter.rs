@@ -0,0 +1,202 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Structured metadata pulled from a document's YAML (`---`) or TOML (`+++`)
+/// front matter block.
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+    /// Every key from the front matter, including the ones above.
+    pub fields: Map<String, Value>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+/// Split a leading front matter block off `input`.
+///
+/// Returns the raw block (without delimiters) and the body with the block's
+/// lines replaced by blank lines, so line numbers in the body still match the
+/// source file.
+pub fn split_front_matter(input: &str) -> (Option<(&str, FrontMatterFormat)>, String) {
+    let text = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut lines = text.split_inclusive('\n');
+    let first = match lines.next() {
+        Some(line) => line,
+        None => return (None, input.to_string()),
+    };
+    let delimiter = first.trim_end();
+    let format = match delimiter {
+        "---" => FrontMatterFormat::Yaml,
+        "+++" => FrontMatterFormat::Toml,
+        _ => return (None, input.to_string()),
+    };
+
+    let start = first.len();
+    let mut pos = start;
+    let mut line_count = 1;
+    for line in lines {
+        line_count += 1;
+        let trimmed = line.trim_end();
+        // YAML blocks may also be closed with `...`
+        if trimmed == delimiter || (format == FrontMatterFormat::Yaml && trimmed == "...") {
+            let raw = &text[start..pos];
+            let rest = &text[pos + line.len()..];
+            let body = format!("{}{}", "\n".repeat(line_count), rest);
+            return (Some((raw, format)), body);
+        }
+        pos += line.len();
+    }
+
+    // No closing delimiter: treat the whole thing as plain markdown.
+    (None, input.to_string())
+}
+
+/// Parse the front matter of `input` into metadata, if present and valid.
+pub fn extract_metadata(input: &str) -> Option<DocumentMetadata> {
+    let (block, _) = split_front_matter(input);
+    let (raw, format) = block?;
+    let fields = parse_fields(raw, format)?;
+
+    Some(DocumentMetadata {
+        title: field_string(&fields, &["title"]),
+        author: field_author(&fields),
+        date: field_string(&fields, &["date", "created", "updated"]),
+        tags: field_list(&fields, &["tags", "keywords", "categories"]),
+        fields,
+    })
+}
+
+/// Remove the front matter block so it isn't rendered as markdown. A leading
+/// block that doesn't parse to a map, such as text between two thematic
+/// breaks, is left alone.
+pub fn strip_front_matter(input: &str) -> String {
+    match split_front_matter(input) {
+        (Some((raw, format)), body) if parse_fields(raw, format).is_some() => body,
+        _ => input.to_string(),
+    }
+}
+
+/// The keys of a front matter block, or `None` unless it is a map. An empty
+/// block is an empty map.
+fn parse_fields(raw: &str, format: FrontMatterFormat) -> Option<Map<String, Value>> {
+    let value = match format {
+        FrontMatterFormat::Yaml => serde_yaml::from_str::<Value>(raw).ok()?,
+        FrontMatterFormat::Toml => {
+            toml_to_json(toml::Value::Table(toml::from_str::<toml::Table>(raw).ok()?))
+        }
+    };
+    match value {
+        Value::Object(map) => Some(map),
+        Value::Null if raw.trim().is_empty() => Some(Map::new()),
+        _ => None,
+    }
+}
+
+fn field_string(fields: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match fields.get(*key)? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+fn field_author(fields: &Map<String, Value>) -> Option<String> {
+    let value = fields.get("author").or_else(|| fields.get("authors"))?;
+    let name_of = |v: &Value| match v {
+        Value::String(s) => Some(s.trim().to_string()),
+        Value::Object(obj) => obj.get("name").and_then(|n| n.as_str()).map(String::from),
+        _ => None,
+    };
+    match value {
+        Value::Array(items) => {
+            let names: Vec<String> = items.iter().filter_map(name_of).collect();
+            if names.is_empty() {
+                None
+            } else {
+                Some(names.join(", "))
+            }
+        }
+        other => name_of(other).filter(|s| !s.is_empty()),
+    }
+}
+
+fn field_list(fields: &Map<String, Value>, keys: &[&str]) -> Vec<String> {
+    for key in keys {
+        match fields.get(*key) {
+            Some(Value::Array(items)) => {
+                return items
+                    .iter()
+                    .filter_map(|v| match v {
+                        Value::String(s) => Some(s.trim().to_string()),
+                        Value::Number(n) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .filter(|s| !s.is_empty())
+                    .collect();
+            }
+            // Comma- or space-separated strings: `tags: rust, tauri`
+            Some(Value::String(s)) => {
+                return s
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    Vec::new()
+}
+
+/// Convert a TOML value to JSON, rendering datetimes as plain strings.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_yaml_and_toml_blocks() {
+        let yaml = "---\ntitle: Notes\ntags: [a, b]\n---\n# Body\n";
+        assert_eq!(strip_front_matter(yaml), "\n\n\n\n# Body\n");
+        let meta = extract_metadata(yaml).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Notes"));
+        assert_eq!(meta.tags, vec!["a", "b"]);
+
+        let toml = "+++\ntitle = \"Notes\"\n+++\nBody\n";
+        assert_eq!(strip_front_matter(toml), "\n\n\nBody\n");
+        assert_eq!(strip_front_matter("---\n---\nBody"), "\n\nBody");
+    }
+
+    #[test]
+    fn keeps_text_between_thematic_breaks() {
+        let input = "---\nAn opening paragraph.\n\n---\nMore text.\n";
+        assert!(extract_metadata(input).is_none());
+        assert_eq!(strip_front_matter(input), input);
+        let invalid = "---\ntitle: [unclosed\n---\nBody\n";
+        assert_eq!(strip_front_matter(invalid), invalid);
+    }
+}
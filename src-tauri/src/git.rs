@@ -1,7 +1,8 @@
-use git2::{Cred, DiffOptions, Oid, RemoteCallbacks, Repository, Sort, StatusOptions};
+use git2::{Cred, DiffOptions, Oid, RemoteCallbacks, Repository, Sort, Status, StatusOptions};
 use serde::Serialize;
 use similar::{ChangeTag, TextDiff};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -79,20 +80,7 @@ pub fn get_file_status(file_path: &Path) -> GitStatus {
             if statuses.is_empty() {
                 "clean".into()
             } else {
-                let s = statuses.get(0).unwrap().status();
-                if s.is_index_new() {
-                    "staged".into()
-                } else if s.is_index_modified() || s.is_index_renamed() {
-                    "staged".into()
-                } else if s.is_wt_modified() {
-                    "modified".into()
-                } else if s.is_wt_new() {
-                    "untracked".into()
-                } else if s.is_ignored() {
-                    "ignored".into()
-                } else {
-                    "clean".into()
-                }
+                status_label(statuses.get(0).unwrap().status()).into()
             }
         }
         Err(_) => "unknown".into(),
@@ -108,6 +96,45 @@ pub fn get_file_status(file_path: &Path) -> GitStatus {
     }
 }
 
+fn status_label(s: Status) -> &'static str {
+    if s.is_index_new() || s.is_index_modified() || s.is_index_renamed() {
+        "staged"
+    } else if s.is_wt_modified() {
+        "modified"
+    } else if s.is_wt_new() {
+        "untracked"
+    } else if s.is_ignored() {
+        "ignored"
+    } else {
+        "clean"
+    }
+}
+
+/// Status of every changed file under `dir`, by absolute path. Files that
+/// aren't listed are clean. `None` outside a repository.
+pub fn get_statuses(dir: &Path) -> Option<HashMap<PathBuf, String>> {
+    let repo = find_repo(dir)?;
+    let workdir = repo.workdir()?.to_path_buf();
+    let rel_dir = dir.strip_prefix(&workdir).ok()?;
+
+    let mut opts = StatusOptions::new();
+    if !rel_dir.as_os_str().is_empty() {
+        opts.pathspec(rel_dir.to_string_lossy().as_ref());
+    }
+    opts.include_untracked(true);
+    opts.recurse_untracked_dirs(true);
+
+    let statuses = repo.statuses(Some(&mut opts)).ok()?;
+    let changed = statuses
+        .iter()
+        .filter_map(|entry| {
+            let path = workdir.join(entry.path()?);
+            Some((path, status_label(entry.status()).to_string()))
+        })
+        .collect();
+    Some(changed)
+}
+
 /// Get the commit log for a specific file (only commits that touched it).
 pub fn get_file_log(file_path: &Path, limit: usize) -> Result<Vec<CommitInfo>, String> {
     let repo = find_repo(file_path).ok_or("Not a git repository")?;
@@ -1,7 +1,10 @@
 use crate::commands::{rewrite_image_paths, RenderResult};
+use crate::front_matter::extract_metadata;
 use crate::git;
-use crate::markdown::render_markdown;
+use crate::links::LinkResolver;
+use crate::render_cache::render_cached;
 use crate::state::AppState;
+use crate::trust::is_trusted;
 use std::path::Path;
 use tauri::State;
 
@@ -31,11 +34,22 @@ pub fn git_file_history(path: String, limit: Option<usize>) -> Result<Vec<git::C
 }
 
 #[tauri::command]
-pub fn git_file_at_commit(path: String, oid: String) -> Result<RenderResult, String> {
+pub fn git_file_at_commit(
+    path: String,
+    oid: String,
+    state: State<'_, AppState>,
+) -> Result<RenderResult, String> {
     let file_path = Path::new(&path);
 
     let content = git::get_file_at_commit(file_path, &oid)?;
-    let html = render_markdown(&content);
+    // Links resolve against the working tree, not the historical revision
+    let scan_options = state.scan_options.lock().unwrap().clone();
+    let resolver = LinkResolver::new(file_path, None, &scan_options);
+    // Includes are skipped: parts in the working tree may not match this revision
+    let sanitized = !is_trusted(&state, file_path);
+    let options = state.render_options.lock().unwrap().clone();
+    let html = render_cached(&state, &content, &options, None, Some(&resolver), sanitized).html;
+    let metadata = extract_metadata(&content);
 
     // Rewrite relative image paths using the file's parent directory
     let parent_dir = file_path
@@ -54,6 +68,11 @@ pub fn git_file_at_commit(path: String, oid: String) -> Result<RenderResult, Str
         html,
         file_name,
         file_path: path,
+        metadata,
+        sanitized,
+        // Revisions are shown in one piece
+        render_id: 0,
+        chunk_count: 1,
     })
 }
 
@@ -0,0 +1,498 @@
+use comrak::adapters::SyntaxHighlighterAdapter;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Token categories we colour. Each maps to a highlight.js class name so the
+/// webview can style server-highlighted and client-highlighted code alike.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TokenClass {
+    Comment,
+    Keyword,
+    String,
+    Regexp,
+    Number,
+    Literal,
+    Type,
+    Function,
+    Tag,
+    Attribute,
+    Variable,
+    Meta,
+    Section,
+    Addition,
+    Deletion,
+}
+
+impl TokenClass {
+    pub const ALL: [TokenClass; 15] = [
+        TokenClass::Comment,
+        TokenClass::Keyword,
+        TokenClass::String,
+        TokenClass::Regexp,
+        TokenClass::Number,
+        TokenClass::Literal,
+        TokenClass::Type,
+        TokenClass::Function,
+        TokenClass::Tag,
+        TokenClass::Attribute,
+        TokenClass::Variable,
+        TokenClass::Meta,
+        TokenClass::Section,
+        TokenClass::Addition,
+        TokenClass::Deletion,
+    ];
+
+    pub fn css_class(self) -> &'static str {
+        match self {
+            TokenClass::Comment => "hljs-comment",
+            TokenClass::Keyword => "hljs-keyword",
+            TokenClass::String => "hljs-string",
+            TokenClass::Regexp => "hljs-regexp",
+            TokenClass::Number => "hljs-number",
+            TokenClass::Literal => "hljs-literal",
+            TokenClass::Type => "hljs-type",
+            TokenClass::Function => "hljs-title",
+            TokenClass::Tag => "hljs-name",
+            TokenClass::Attribute => "hljs-attr",
+            TokenClass::Variable => "hljs-variable",
+            TokenClass::Meta => "hljs-meta",
+            TokenClass::Section => "hljs-section",
+            TokenClass::Addition => "hljs-addition",
+            TokenClass::Deletion => "hljs-deletion",
+        }
+    }
+}
+
+/// Token colours for one app theme variant.
+pub struct Palette {
+    pub foreground: &'static str,
+    pub comment: &'static str,
+    pub keyword: &'static str,
+    pub string: &'static str,
+    pub number: &'static str,
+    pub type_: &'static str,
+    pub function: &'static str,
+    pub tag: &'static str,
+    pub attribute: &'static str,
+    pub variable: &'static str,
+    pub meta: &'static str,
+    pub addition: &'static str,
+    pub deletion: &'static str,
+}
+
+impl Palette {
+    pub fn color(&self, class: TokenClass) -> &'static str {
+        match class {
+            TokenClass::Comment => self.comment,
+            TokenClass::Keyword => self.keyword,
+            TokenClass::String | TokenClass::Regexp => self.string,
+            TokenClass::Number | TokenClass::Literal => self.number,
+            TokenClass::Type => self.type_,
+            TokenClass::Function | TokenClass::Section => self.function,
+            TokenClass::Tag => self.tag,
+            TokenClass::Attribute => self.attribute,
+            TokenClass::Variable => self.variable,
+            TokenClass::Meta => self.meta,
+            TokenClass::Addition => self.addition,
+            TokenClass::Deletion => self.deletion,
+        }
+    }
+}
+
+const GITHUB_LIGHT: Palette = Palette {
+    foreground: "#24292f",
+    comment: "#6e7781",
+    keyword: "#cf222e",
+    string: "#0a3069",
+    number: "#0550ae",
+    type_: "#953800",
+    function: "#8250df",
+    tag: "#116329",
+    attribute: "#0550ae",
+    variable: "#953800",
+    meta: "#6e7781",
+    addition: "#116329",
+    deletion: "#82071e",
+};
+
+const GITHUB_DARK: Palette = Palette {
+    foreground: "#e6edf3",
+    comment: "#8b949e",
+    keyword: "#ff7b72",
+    string: "#a5d6ff",
+    number: "#79c0ff",
+    type_: "#ffa657",
+    function: "#d2a8ff",
+    tag: "#7ee787",
+    attribute: "#79c0ff",
+    variable: "#ffa657",
+    meta: "#8b949e",
+    addition: "#7ee787",
+    deletion: "#ffa198",
+};
+
+const SOLARIZED_LIGHT: Palette = Palette {
+    foreground: "#657b83",
+    comment: "#93a1a1",
+    keyword: "#859900",
+    string: "#2aa198",
+    number: "#d33682",
+    type_: "#b58900",
+    function: "#268bd2",
+    tag: "#268bd2",
+    attribute: "#b58900",
+    variable: "#cb4b16",
+    meta: "#cb4b16",
+    addition: "#859900",
+    deletion: "#dc322f",
+};
+
+const SOLARIZED_DARK: Palette = Palette {
+    foreground: "#839496",
+    comment: "#586e75",
+    ..SOLARIZED_LIGHT
+};
+
+const ONE_DARK_LIGHT: Palette = Palette {
+    foreground: "#383a42",
+    comment: "#a0a1a7",
+    keyword: "#a626a4",
+    string: "#50a14f",
+    number: "#986801",
+    type_: "#c18401",
+    function: "#4078f2",
+    tag: "#e45649",
+    attribute: "#986801",
+    variable: "#e45649",
+    meta: "#0184bc",
+    addition: "#50a14f",
+    deletion: "#e45649",
+};
+
+const ONE_DARK_DARK: Palette = Palette {
+    foreground: "#abb2bf",
+    comment: "#5c6370",
+    keyword: "#c678dd",
+    string: "#98c379",
+    number: "#d19a66",
+    type_: "#e5c07b",
+    function: "#61afef",
+    tag: "#e06c75",
+    attribute: "#d19a66",
+    variable: "#e06c75",
+    meta: "#56b6c2",
+    addition: "#98c379",
+    deletion: "#e06c75",
+};
+
+const DRACULA_LIGHT: Palette = Palette {
+    foreground: "#1f1f1f",
+    comment: "#6c664b",
+    keyword: "#a3144d",
+    string: "#846e15",
+    number: "#644ac9",
+    type_: "#036a96",
+    function: "#14710a",
+    tag: "#a3144d",
+    attribute: "#14710a",
+    variable: "#a34d14",
+    meta: "#a3144d",
+    addition: "#14710a",
+    deletion: "#cb3a2a",
+};
+
+const DRACULA_DARK: Palette = Palette {
+    foreground: "#f8f8f2",
+    comment: "#6272a4",
+    keyword: "#ff79c6",
+    string: "#f1fa8c",
+    number: "#bd93f9",
+    type_: "#8be9fd",
+    function: "#50fa7b",
+    tag: "#ff79c6",
+    attribute: "#50fa7b",
+    variable: "#ffb86c",
+    meta: "#ff79c6",
+    addition: "#50fa7b",
+    deletion: "#ff5555",
+};
+
+const MONOKAI_LIGHT: Palette = Palette {
+    foreground: "#272822",
+    comment: "#75715e",
+    keyword: "#c2185b",
+    string: "#9a7b00",
+    number: "#7e57c2",
+    type_: "#0089b3",
+    function: "#5f8700",
+    tag: "#c2185b",
+    attribute: "#5f8700",
+    variable: "#d75f00",
+    meta: "#75715e",
+    addition: "#5f8700",
+    deletion: "#c2185b",
+};
+
+const MONOKAI_DARK: Palette = Palette {
+    foreground: "#f8f8f2",
+    comment: "#75715e",
+    keyword: "#f92672",
+    string: "#e6db74",
+    number: "#ae81ff",
+    type_: "#66d9ef",
+    function: "#a6e22e",
+    tag: "#f92672",
+    attribute: "#a6e22e",
+    variable: "#fd971f",
+    meta: "#75715e",
+    addition: "#a6e22e",
+    deletion: "#f92672",
+};
+
+const NORD_LIGHT: Palette = Palette {
+    foreground: "#2e3440",
+    comment: "#7b88a1",
+    keyword: "#5e81ac",
+    string: "#5f7f41",
+    number: "#9a6c93",
+    type_: "#2f7f7e",
+    function: "#3b7b8c",
+    tag: "#5e81ac",
+    attribute: "#2f7f7e",
+    variable: "#b7791f",
+    meta: "#5e81ac",
+    addition: "#5f7f41",
+    deletion: "#bf616a",
+};
+
+const NORD_DARK: Palette = Palette {
+    foreground: "#d8dee9",
+    comment: "#616e88",
+    keyword: "#81a1c1",
+    string: "#a3be8c",
+    number: "#b48ead",
+    type_: "#8fbcbb",
+    function: "#88c0d0",
+    tag: "#81a1c1",
+    attribute: "#8fbcbb",
+    variable: "#ebcb8b",
+    meta: "#5e81ac",
+    addition: "#a3be8c",
+    deletion: "#bf616a",
+};
+
+/// Look up the palette for an app theme name (`github`, `solarized`, ...).
+/// Unknown names fall back to GitHub.
+pub fn palette(theme: &str, dark: bool) -> &'static Palette {
+    match (theme, dark) {
+        ("solarized", false) => &SOLARIZED_LIGHT,
+        ("solarized", true) => &SOLARIZED_DARK,
+        ("one-dark", false) => &ONE_DARK_LIGHT,
+        ("one-dark", true) => &ONE_DARK_DARK,
+        ("dracula", false) => &DRACULA_LIGHT,
+        ("dracula", true) => &DRACULA_DARK,
+        ("monokai", false) => &MONOKAI_LIGHT,
+        ("monokai", true) => &MONOKAI_DARK,
+        ("nord", false) => &NORD_LIGHT,
+        ("nord", true) => &NORD_DARK,
+        (_, true) => &GITHUB_DARK,
+        _ => &GITHUB_LIGHT,
+    }
+}
+
+/// Build the stylesheet that colours highlighted code for a theme variant.
+pub fn theme_css(theme: &str, dark: bool) -> String {
+    let p = palette(theme, dark);
+    let mut css = format!("#content pre code.hljs {{ color: {}; }}\n", p.foreground);
+    for class in TokenClass::ALL {
+        css.push_str(&format!(
+            "#content .{} {{ color: {}; }}\n",
+            class.css_class(),
+            p.color(class)
+        ));
+    }
+    css.push_str("#content .hljs-comment { font-style: italic; }\n");
+    css
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn find_syntax(lang: &str) -> Option<&'static SyntaxReference> {
+    if lang.is_empty() {
+        return None;
+    }
+    let set = syntax_set();
+    set.find_syntax_by_token(lang)
+        .or_else(|| set.find_syntax_by_name(lang))
+}
+
+/// Whether `lang` is a language we can highlight server-side.
+pub fn is_supported(lang: &str) -> bool {
+    // Diagram sources are rendered by the webview, not highlighted.
+    lang != "mermaid" && find_syntax(lang).is_some()
+}
+
+/// Split code into lines of `(class, text)` tokens.
+/// Returns `None` when the language is unknown.
+pub fn highlight_tokens(code: &str, lang: &str) -> Option<Vec<Vec<(Option<TokenClass>, String)>>> {
+    if !is_supported(lang) {
+        return None;
+    }
+    let syntax = find_syntax(lang)?;
+    let set = syntax_set();
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+
+    for line in LinesWithEndings::from(code) {
+        let ops = state.parse_line(line, set).ok()?;
+        let mut tokens: Vec<(Option<TokenClass>, String)> = Vec::new();
+        let mut push = |class: Option<TokenClass>, text: &str| {
+            let text = text.trim_end_matches(['\n', '\r']);
+            if text.is_empty() {
+                return;
+            }
+            match tokens.last_mut() {
+                Some((last, buf)) if *last == class => buf.push_str(text),
+                _ => tokens.push((class, text.to_string())),
+            }
+        };
+
+        let mut last = 0;
+        for (pos, op) in ops {
+            if pos > last {
+                push(classify(&stack), &line[last..pos]);
+                last = pos;
+            }
+            stack.apply(&op).ok()?;
+        }
+        push(classify(&stack), &line[last..]);
+        lines.push(tokens);
+    }
+    Some(lines)
+}
+
+/// Map the innermost recognisable scope on the stack to a token class.
+fn classify(stack: &ScopeStack) -> Option<TokenClass> {
+    stack.as_slice().iter().rev().find_map(classify_scope)
+}
+
+fn classify_scope(scope: &Scope) -> Option<TokenClass> {
+    let name = scope.build_string();
+    let starts = |prefix: &str| name == prefix || name.starts_with(&format!("{prefix}."));
+    let class = if starts("comment") {
+        TokenClass::Comment
+    } else if starts("string.regexp") {
+        TokenClass::Regexp
+    } else if starts("string") {
+        TokenClass::String
+    } else if starts("constant.numeric") {
+        TokenClass::Number
+    } else if starts("constant.language") || starts("variable.language") {
+        TokenClass::Literal
+    } else if starts("constant.character.escape") {
+        TokenClass::String
+    } else if starts("keyword.operator") {
+        return None;
+    } else if starts("keyword") || starts("storage") {
+        TokenClass::Keyword
+    } else if starts("entity.name.function")
+        || starts("support.function")
+        || starts("variable.function")
+    {
+        TokenClass::Function
+    } else if starts("entity.name.type")
+        || starts("entity.name.class")
+        || starts("entity.name.struct")
+        || starts("entity.name.enum")
+        || starts("entity.name.trait")
+        || starts("entity.other.inherited-class")
+        || starts("support.type")
+        || starts("support.class")
+    {
+        TokenClass::Type
+    } else if starts("entity.name.tag") {
+        TokenClass::Tag
+    } else if starts("entity.other.attribute-name") || starts("support.type.property-name") {
+        TokenClass::Attribute
+    } else if starts("variable.parameter") || starts("variable.other.constant") {
+        TokenClass::Variable
+    } else if starts("meta.preprocessor") || starts("meta.annotation") || starts("meta.attribute") {
+        TokenClass::Meta
+    } else if starts("markup.heading") {
+        TokenClass::Section
+    } else if starts("markup.inserted") {
+        TokenClass::Addition
+    } else if starts("markup.deleted") {
+        TokenClass::Deletion
+    } else {
+        return None;
+    };
+    Some(class)
+}
+
+/// comrak adapter that highlights fenced code with syntect, emitting
+/// highlight.js-compatible class names so app themes can colour them.
+pub struct ClassedHighlighter;
+
+impl SyntaxHighlighterAdapter for ClassedHighlighter {
+    fn write_highlighted(
+        &self,
+        output: &mut dyn Write,
+        lang: Option<&str>,
+        code: &str,
+    ) -> io::Result<()> {
+        let lines = match highlight_tokens(code, lang.unwrap_or("")) {
+            Some(lines) => lines,
+            None => return comrak::html::escape(output, code.as_bytes()),
+        };
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                output.write_all(b"\n")?;
+            }
+            for (class, text) in line {
+                match class {
+                    Some(class) => {
+                        write!(output, "<span class=\"{}\">", class.css_class())?;
+                        comrak::html::escape(output, text.as_bytes())?;
+                        output.write_all(b"</span>")?;
+                    }
+                    None => comrak::html::escape(output, text.as_bytes())?,
+                }
+            }
+        }
+        if code.ends_with('\n') {
+            output.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    fn write_pre_tag(
+        &self,
+        output: &mut dyn Write,
+        attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        comrak::html::write_opening_tag(output, "pre", attributes)
+    }
+
+    fn write_code_tag(
+        &self,
+        output: &mut dyn Write,
+        mut attributes: HashMap<String, String>,
+    ) -> io::Result<()> {
+        // Mark blocks we highlighted so the webview doesn't run highlight.js on them.
+        if let Some(class) = attributes.get_mut("class") {
+            let lang = class.strip_prefix("language-").unwrap_or("");
+            if is_supported(lang) {
+                class.push_str(" hljs");
+            }
+        }
+        comrak::html::write_opening_tag(output, "code", attributes)
+    }
+}
@@ -0,0 +1,376 @@
+use crate::commands::is_markdown_file;
+use crate::links::{has_scheme, normalize_path, percent_decode};
+use crate::render_options::RenderOptions;
+use crate::state::AppState;
+use crate::trust::is_trusted;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Arena, Options};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Deepest chain of nested includes expanded before giving up.
+const MAX_DEPTH: usize = 16;
+
+/// A document that may include others, and the folder its includes must
+/// come from.
+pub struct IncludeScope {
+    pub file: PathBuf,
+    /// Only markdown files under this folder can be included.
+    pub root: PathBuf,
+}
+
+impl IncludeScope {
+    /// `folder` is the opened folder; it is only used when it contains
+    /// `file`, otherwise includes stay within the file's own folder.
+    pub fn new(file: &Path, folder: Option<&Path>) -> Self {
+        let file = normalize_path(file);
+        let root = match folder.map(normalize_path) {
+            Some(folder) if file.starts_with(&folder) => folder,
+            _ => file.parent().unwrap_or(Path::new("/")).to_path_buf(),
+        };
+        IncludeScope { file, root }
+    }
+}
+
+/// The include scope of `file` in the current folder. Files outside trusted
+/// folders get none, so their includes are left unexpanded.
+pub fn include_scope(state: &AppState, file: &Path) -> Option<IncludeScope> {
+    if !is_trusted(state, file) {
+        return None;
+    }
+    let folder = state.current_folder.lock().unwrap().clone();
+    Some(IncludeScope::new(file, folder.as_deref()))
+}
+
+/// Replace `![[part.md]]` and `<!-- include: part.md -->` directives with the
+/// parsed contents of the named files, resolved relative to the including
+/// file. `source` is the text `root` was parsed from. Returns every file that
+/// was pulled in, so callers can watch them for changes.
+///
+/// Included nodes take the directive's sourcepos, so the preview maps them
+/// back to the include line. Cycles, missing files, targets outside the
+/// scope's folder and runaway nesting are replaced with a warning alert.
+pub fn expand_includes<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    source: &str,
+    scope: &IncludeScope,
+    render_options: &RenderOptions,
+) -> Vec<PathBuf> {
+    let file = &scope.file;
+    let mut include = Include {
+        render_options,
+        options: render_options.comrak_options(),
+        root: scope.root.clone(),
+        real_root: fs::canonicalize(&scope.root).unwrap_or_else(|_| scope.root.clone()),
+        stack: vec![fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())],
+        files: Vec::new(),
+    };
+    include.expand(arena, root, source, file);
+    include.files
+}
+
+/// Replace include directives with a notice that they weren't expanded, for
+/// documents outside trusted folders.
+pub fn skip_includes<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    source: &str,
+    render_options: &RenderOptions,
+) {
+    let options = render_options.comrak_options();
+    for (node, target) in directives(root, source) {
+        let message = format!(
+            "`{}` is not included because this folder isn't trusted",
+            target
+        );
+        splice(node, warning(arena, &options, &message));
+    }
+}
+
+/// State for one expansion: the folder includes must stay in, the files on
+/// the current include chain, for cycle detection, and every file included
+/// so far.
+struct Include<'o> {
+    render_options: &'o RenderOptions,
+    options: Options<'static>,
+    root: PathBuf,
+    /// `root` with symlinks resolved.
+    real_root: PathBuf,
+    stack: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+impl Include<'_> {
+    fn expand<'a>(
+        &mut self,
+        arena: &'a Arena<AstNode<'a>>,
+        root: &'a AstNode<'a>,
+        source: &str,
+        file: &Path,
+    ) {
+        let directives = directives(root, source);
+        if directives.is_empty() {
+            return;
+        }
+
+        let dir = file.parent().unwrap_or(Path::new("/"));
+        for (node, target) in directives {
+            let included = match self.include_file(arena, &target, dir) {
+                Ok(doc) => doc,
+                Err(message) => warning(arena, &self.options, &message),
+            };
+            splice(node, included);
+        }
+    }
+
+    /// Parse one included file, expanding its own includes.
+    fn include_file<'a>(
+        &mut self,
+        arena: &'a Arena<AstNode<'a>>,
+        target: &str,
+        dir: &Path,
+    ) -> Result<&'a AstNode<'a>, String> {
+        let path = resolve(dir, target, &self.root, &self.real_root)?;
+        let key = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+        if self.stack.contains(&key) {
+            return Err(format!("Include cycle: `{}` includes itself", target));
+        }
+        if self.stack.len() > MAX_DEPTH {
+            return Err(format!("Includes nested too deeply at `{}`", target));
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to include `{}`: {}", target, e))?;
+        if !self.files.contains(&path) {
+            self.files.push(path.clone());
+        }
+
+        let text = self.render_options.prepare(&content);
+        let doc = parse_document(arena, &text, &self.options);
+        self.stack.push(key);
+        self.expand(arena, doc, &text, &path);
+        self.stack.pop();
+        rebase_urls(doc, target);
+        Ok(doc)
+    }
+}
+
+/// Include directives under `root` with their targets.
+fn directives<'a>(root: &'a AstNode<'a>, source: &str) -> Vec<(&'a AstNode<'a>, String)> {
+    let lines: Vec<&str> = source.lines().collect();
+    root.descendants()
+        .filter_map(|node| include_target(node, &lines).map(|target| (node, target)))
+        .collect()
+}
+
+/// Put the blocks of `doc` in place of the directive `node`, giving them its
+/// sourcepos.
+fn splice<'a>(node: &'a AstNode<'a>, doc: &'a AstNode<'a>) {
+    let sourcepos = node.data.borrow().sourcepos;
+    for descendant in doc.descendants() {
+        descendant.data.borrow_mut().sourcepos = sourcepos;
+    }
+    while let Some(child) = doc.first_child() {
+        node.insert_before(child);
+    }
+    node.detach();
+}
+
+/// The path named by an include directive, if `node` is one. Directives must
+/// sit alone in their block; the target is read from the source line because
+/// smart punctuation may have rewritten the parsed text.
+fn include_target<'a>(node: &'a AstNode<'a>, lines: &[&str]) -> Option<String> {
+    let ast = node.data.borrow();
+    match &ast.value {
+        NodeValue::HtmlBlock(block) if block.literal.contains("include:") => {
+            let re = Regex::new(r"^<!--\s*include:\s*(.+?)\s*-->$").unwrap();
+            let caps = re.captures(block.literal.trim())?;
+            Some(caps[1].to_string())
+        }
+        NodeValue::Paragraph if ast.sourcepos.start.line == ast.sourcepos.end.line => {
+            let line = lines.get(ast.sourcepos.start.line.checked_sub(1)?)?;
+            if !line.trim_start().starts_with("![[") {
+                return None;
+            }
+            let re = Regex::new(r"^!\[\[([^\[\]|#]+)(?:\|[^\[\]]*)?\]\]$").unwrap();
+            let caps = re.captures(line.trim())?;
+            let target = caps[1].trim();
+            // `![[diagram.png]]` is an image embed, not an include
+            if Path::new(target).extension().is_some() && !is_markdown_file(target) {
+                return None;
+            }
+            Some(target.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Resolve an include target against the including file's folder, trying a
+/// `.md` extension for bare names. Only relative paths to markdown files
+/// inside `root` resolve, also once symlinks are followed (`real_root`).
+fn resolve(dir: &Path, target: &str, root: &Path, real_root: &Path) -> Result<PathBuf, String> {
+    let decoded = percent_decode(target);
+    let relative = Path::new(&decoded);
+    if relative.is_absolute() || relative.has_root() || has_scheme(&decoded) {
+        return Err(format!("Includes must be relative paths: `{}`", target));
+    }
+    let candidate = normalize_path(&dir.join(relative));
+    let path = if candidate.is_file() {
+        candidate
+    } else if candidate.extension().is_none() && candidate.with_extension("md").is_file() {
+        candidate.with_extension("md")
+    } else {
+        return Err(format!("Included file not found: `{}`", target));
+    };
+    if !is_markdown_file(&path.to_string_lossy()) {
+        return Err(format!("Only markdown files can be included: `{}`", target));
+    }
+    let real = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    if !path.starts_with(root) || !real.starts_with(real_root) {
+        return Err(format!("Included file is outside the folder: `{}`", target));
+    }
+    Ok(path)
+}
+
+/// Make relative links and images in an included file relative to the
+/// including file instead.
+fn rebase_urls<'a>(root: &'a AstNode<'a>, target: &str) {
+    let prefix = match Path::new(target).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().to_string(),
+        _ => return,
+    };
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let link = match &mut ast.value {
+            NodeValue::Link(link) | NodeValue::Image(link) => link,
+            _ => continue,
+        };
+        if link.url.is_empty()
+            || link.url.starts_with('#')
+            || link.url.starts_with('/')
+            || has_scheme(&link.url)
+        {
+            continue;
+        }
+        link.url = format!("{}/{}", prefix, link.url);
+    }
+}
+
+/// A warning alert standing in for an include that couldn't be expanded.
+fn warning<'a>(arena: &'a Arena<AstNode<'a>>, options: &Options, message: &str) -> &'a AstNode<'a> {
+    parse_document(arena, &format!("> [!WARNING]\n> {}\n", message), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// A scratch folder laid out as `notes/` with `part.md`, `data.txt` and
+    /// `sub/inner.md`, next to `secret.md` outside it.
+    struct Fixture {
+        dir: TempDir,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = TempDir::new(&format!("include-{}", name));
+            dir.write("notes/part.md", "part");
+            dir.write("notes/data.txt", "data");
+            dir.write("notes/sub/inner.md", "inner");
+            dir.write("secret.md", "secret");
+            Fixture { dir }
+        }
+
+        fn notes(&self) -> PathBuf {
+            self.dir.path().join("notes")
+        }
+
+        fn resolve(&self, dir: &Path, target: &str) -> Result<PathBuf, String> {
+            let root = self.notes();
+            let real_root = fs::canonicalize(&root).unwrap();
+            resolve(dir, target, &root, &real_root)
+        }
+    }
+
+    #[test]
+    fn resolves_markdown_inside_the_folder() {
+        let fixture = Fixture::new("inside");
+        let notes = fixture.notes();
+        assert_eq!(
+            fixture.resolve(&notes, "part.md"),
+            Ok(notes.join("part.md"))
+        );
+        assert_eq!(fixture.resolve(&notes, "part"), Ok(notes.join("part.md")));
+        assert_eq!(
+            fixture.resolve(&notes, "sub%2Finner.md"),
+            Ok(notes.join("sub/inner.md"))
+        );
+        // `..` that stays inside the folder is fine
+        assert_eq!(
+            fixture.resolve(&notes.join("sub"), "../part.md"),
+            Ok(notes.join("part.md"))
+        );
+    }
+
+    #[test]
+    fn rejects_paths_escaping_the_folder() {
+        let fixture = Fixture::new("escape");
+        let notes = fixture.notes();
+        assert!(fixture.resolve(&notes, "../secret.md").is_err());
+        assert!(fixture.resolve(&notes.join("sub"), "../../secret").is_err());
+        assert!(fixture.resolve(&notes, "%2E%2E/secret.md").is_err());
+        let absolute = fixture.dir.path().join("secret.md");
+        assert!(fixture
+            .resolve(&notes, &absolute.to_string_lossy())
+            .is_err());
+        assert!(fixture.resolve(&notes, "file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn rejects_non_markdown_and_missing_files() {
+        let fixture = Fixture::new("kinds");
+        let notes = fixture.notes();
+        assert!(fixture.resolve(&notes, "data.txt").is_err());
+        assert!(fixture.resolve(&notes, "missing.md").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_leaving_the_folder() {
+        let fixture = Fixture::new("symlink");
+        let notes = fixture.notes();
+        std::os::unix::fs::symlink(fixture.dir.path().join("secret.md"), notes.join("link.md"))
+            .unwrap();
+        assert!(fixture.resolve(&notes, "link.md").is_err());
+    }
+
+    #[test]
+    fn skipped_includes_leave_a_notice() {
+        let source = "Intro\n\n![[part.md]]\n\n<!-- include: other.md -->\n";
+        let render_options = RenderOptions::default();
+        let options = render_options.comrak_options();
+        let arena = Arena::new();
+        let root = parse_document(&arena, source, &options);
+        skip_includes(&arena, root, source, &render_options);
+        let mut html = Vec::new();
+        comrak::format_html(root, &options, &mut html).unwrap();
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.contains("Intro"));
+        assert!(html.contains("part.md</code> is not included"));
+        assert!(html.contains("other.md</code> is not included"));
+        assert!(!html.contains("include:"));
+    }
+
+    #[test]
+    fn scope_is_the_open_folder_or_the_document_folder() {
+        let folder = Path::new("/work/notes");
+        let inside = IncludeScope::new(Path::new("/work/notes/a/doc.md"), Some(folder));
+        assert_eq!(inside.root, folder);
+        let outside = IncludeScope::new(Path::new("/tmp/doc.md"), Some(folder));
+        assert_eq!(outside.root, Path::new("/tmp"));
+        let no_folder = IncludeScope::new(Path::new("/work/notes/a/doc.md"), None);
+        assert_eq!(no_folder.root, Path::new("/work/notes/a"));
+    }
+}
@@ -1,12 +1,36 @@
+mod alerts;
+mod backlinks;
 mod commands;
+mod diagrams;
+mod file_ops;
+mod folder_tree;
+mod front_matter;
 mod git;
 mod git_commands;
 mod github_auth;
+mod highlight;
+mod include;
+mod link_audit;
+mod links;
 mod markdown;
+mod math;
+mod outline;
 mod pdf_export;
+mod quick_open;
 mod recent;
+mod render_cache;
+mod render_options;
+mod replace;
+mod sanitize;
+mod scan_options;
+mod search;
+mod search_index;
+mod sourcemap;
 mod state;
 mod terminal;
+#[cfg(test)]
+mod test_support;
+mod trust;
 mod tts;
 mod typst_convert;
 mod watcher;
@@ -32,13 +56,30 @@ pub fn run() {
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             commands::open_and_render,
-            commands::scan_folder,
+            folder_tree::start_folder_scan,
+            folder_tree::cancel_folder_scan,
+            folder_tree::list_folder,
+            folder_tree::filter_folder,
+            folder_tree::set_folder_order,
             commands::get_initial_file,
             commands::export_pdf,
             commands::export_diagram_pdf,
+            commands::highlight_theme_css,
+            commands::get_outline,
+            commands::get_backlinks,
+            commands::audit_links,
+            commands::source_line_to_blocks,
+            commands::block_to_source,
             commands::read_file_content,
             commands::save_file,
             commands::open_path,
+            file_ops::create_file,
+            file_ops::create_folder,
+            file_ops::rename_path,
+            file_ops::move_path,
+            file_ops::duplicate_path,
+            file_ops::trash_path,
+            file_ops::undo_file_op,
             git_commands::git_file_status,
             git_commands::git_file_history,
             git_commands::git_file_at_commit,
@@ -58,7 +99,12 @@ pub fn run() {
             tts::tts_generate,
             tts::tts_cancel,
             tts::tts_list_voices,
-            commands::search_in_files,
+            search::search_in_files,
+            search_index::query_search_index,
+            quick_open::quick_open,
+            replace::preview_replace,
+            replace::replace_in_files,
+            replace::undo_replace,
             terminal::spawn_terminal,
             terminal::send_terminal_input,
             terminal::resize_terminal,
@@ -66,16 +112,28 @@ pub fn run() {
             recent::add_recent_file,
             recent::add_recent_folder,
             recent::clear_recents,
+            trust::get_trust_status,
+            trust::trust_folder,
+            trust::untrust_folder,
+            render_options::get_render_options,
+            render_options::set_render_options,
+            scan_options::get_scan_options,
+            scan_options::set_scan_options,
         ])
         .setup(|app| {
             // Set app data dir and load saved GitHub token
             if let Ok(data_dir) = app.path().app_data_dir() {
                 let state = app.state::<AppState>();
                 let mut dir = state.app_data_dir.lock().unwrap();
-                *dir = Some(data_dir);
+                *dir = Some(data_dir.clone());
                 drop(dir);
+                let cache_dir = data_dir.join(render_cache::RENDER_CACHE_DIR);
+                state.render_cache.lock().unwrap().set_dir(cache_dir);
+                diagrams::set_data_dir(&data_dir);
                 github_auth::load_saved_token(&state);
                 tts::load_saved_tts_keys(&state);
+                render_options::load_render_options(&state);
+                scan_options::load_scan_options(&state);
             }
 
             // Check CLI args for a file path
@@ -0,0 +1,286 @@
+use crate::front_matter::strip_front_matter;
+use crate::links::{
+    has_scheme, percent_decode, split_fragment, LinkResolver, LinkTarget, PageIndex,
+};
+use crate::markdown::{markdown_options, preprocess_markdown};
+use crate::outline::heading_anchors;
+use comrak::nodes::{AstNode, NodeValue};
+use comrak::{parse_document, Anchorizer, Arena};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many remote URLs are checked at once.
+const REMOTE_CONCURRENCY: usize = 8;
+
+/// A link or image whose target couldn't be found.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkProblem {
+    pub file_path: String,
+    pub file_name: String,
+    pub line: usize,
+    pub column: usize,
+    /// `link`, `wikilink` or `image`.
+    pub kind: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// A remote URL to check once local checks are done.
+pub struct RemoteLink {
+    url: String,
+    problem: LinkProblem,
+}
+
+/// Checks link targets across one or more documents, caching the heading
+/// anchors of every document it has looked at.
+pub struct LinkAuditor {
+    pages: Arc<PageIndex>,
+    anchors: HashMap<PathBuf, HashSet<String>>,
+    check_remote: bool,
+    pub remote: Vec<RemoteLink>,
+}
+
+impl LinkAuditor {
+    pub fn new(pages: Arc<PageIndex>, check_remote: bool) -> Self {
+        LinkAuditor {
+            pages,
+            anchors: HashMap::new(),
+            check_remote,
+            remote: Vec::new(),
+        }
+    }
+
+    /// Every markdown file under the root, in path order.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.pages.files().cloned().collect();
+        files.sort();
+        files
+    }
+
+    /// Check every link, wiki-link and image in one document.
+    pub fn audit_file(&mut self, path: &Path) -> Vec<LinkProblem> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                return vec![problem(
+                    path,
+                    0,
+                    0,
+                    "link",
+                    "",
+                    &format!("Failed to read file: {}", e),
+                )]
+            }
+        };
+        let input = preprocess_markdown(&strip_front_matter(&content));
+        let options = markdown_options();
+        let arena = Arena::new();
+        let root = parse_document(&arena, &input, &options);
+
+        let own_anchors = document_anchors(root);
+        let resolver = LinkResolver::with_pages(path, self.pages.clone());
+        let mut problems = Vec::new();
+
+        for node in root.descendants() {
+            let ast = node.data.borrow();
+            let (kind, url) = match &ast.value {
+                NodeValue::Link(link) => ("link", link.url.as_str()),
+                NodeValue::WikiLink(link) => ("wikilink", link.url.as_str()),
+                NodeValue::Image(link) => ("image", link.url.as_str()),
+                _ => continue,
+            };
+            let line = ast.sourcepos.start.line;
+            let column = ast.sourcepos.start.column;
+            let report = |message: String| problem(path, line, column, kind, url, &message);
+
+            // In-page anchors
+            if let Some(fragment) = url.strip_prefix('#') {
+                // Wiki-link fragments are heading text rather than ids
+                let fragment = match kind {
+                    "wikilink" => Anchorizer::new().anchorize(fragment.trim().to_string()),
+                    _ => fragment.to_string(),
+                };
+                if kind != "image" && !has_anchor(&own_anchors, &fragment) {
+                    problems.push(report(format!("No heading with id \"{}\"", fragment)));
+                }
+                continue;
+            }
+
+            if kind != "wikilink" && has_scheme(url) {
+                if self.check_remote && (url.starts_with("http://") || url.starts_with("https://"))
+                {
+                    self.remote.push(RemoteLink {
+                        url: split_fragment(url).0.to_string(),
+                        problem: report(String::new()),
+                    });
+                }
+                continue;
+            }
+            if url.starts_with("data:") {
+                continue;
+            }
+
+            let target = match kind {
+                "wikilink" => resolver.resolve_wiki(url),
+                _ => resolver.resolve_url(url),
+            };
+            match target {
+                LinkTarget::Unresolved(missing) => problems.push(report(match kind {
+                    "image" => format!("Image not found: {}", missing),
+                    "wikilink" => format!("No page named \"{}\"", missing),
+                    _ => format!("File not found: {}", missing),
+                })),
+                LinkTarget::Document {
+                    path: doc,
+                    fragment: Some(fragment),
+                } => {
+                    let found = if doc == path {
+                        has_anchor(&own_anchors, &fragment)
+                    } else {
+                        has_anchor(self.anchors_for(&doc), &fragment)
+                    };
+                    if !found {
+                        let name = doc.file_name().unwrap_or_default().to_string_lossy();
+                        problems.push(report(format!(
+                            "No heading with id \"{}\" in {}",
+                            fragment, name
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+        problems
+    }
+
+    fn anchors_for(&mut self, path: &Path) -> &HashSet<String> {
+        self.anchors.entry(path.to_path_buf()).or_insert_with(|| {
+            let content = fs::read_to_string(path).unwrap_or_default();
+            let input = preprocess_markdown(&strip_front_matter(&content));
+            let options = markdown_options();
+            let arena = Arena::new();
+            let root = parse_document(&arena, &input, &options);
+            document_anchors(root)
+        })
+    }
+}
+
+/// Request each remote URL once and report the ones that fail.
+pub async fn check_remote_links(remote: Vec<RemoteLink>) -> Vec<LinkProblem> {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .user_agent("MRE link checker")
+        .build()
+    {
+        Ok(client) => client,
+        Err(_) => return Vec::new(),
+    };
+
+    let urls: Vec<String> = remote
+        .iter()
+        .map(|r| r.url.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let mut failures: HashMap<String, String> = HashMap::new();
+    for batch in urls.chunks(REMOTE_CONCURRENCY) {
+        let handles: Vec<_> = batch
+            .iter()
+            .map(|url| {
+                let client = client.clone();
+                let url = url.clone();
+                tauri::async_runtime::spawn(async move {
+                    let failure = probe(&client, &url).await;
+                    (url, failure)
+                })
+            })
+            .collect();
+        for handle in handles {
+            if let Ok((url, Some(message))) = handle.await {
+                failures.insert(url, message);
+            }
+        }
+    }
+
+    remote
+        .into_iter()
+        .filter_map(|r| {
+            let message = failures.get(&r.url)?;
+            Some(LinkProblem {
+                message: message.clone(),
+                ..r.problem
+            })
+        })
+        .collect()
+}
+
+async fn probe(client: &reqwest::Client, url: &str) -> Option<String> {
+    // Some servers reject HEAD; retry those with GET
+    let response = match client.head(url).send().await {
+        Ok(resp) if resp.status().is_client_error() => client.get(url).send().await,
+        other => other,
+    };
+    match response {
+        Ok(resp) if resp.status().is_success() => None,
+        Ok(resp) => Some(format!("HTTP {}", resp.status())),
+        Err(e) => Some(format!("Request failed: {}", e)),
+    }
+}
+
+/// Heading ids plus any `id`/`name` attributes in raw HTML and footnote ids.
+fn document_anchors<'a>(root: &'a AstNode<'a>) -> HashSet<String> {
+    let mut anchors: HashSet<String> = heading_anchors(root).into_iter().collect();
+    let html_id = Regex::new(r#"\b(?:id|name)\s*=\s*["']([^"']+)["']"#).unwrap();
+    for node in root.descendants() {
+        match &node.data.borrow().value {
+            NodeValue::HtmlBlock(block) => {
+                for caps in html_id.captures_iter(&block.literal) {
+                    anchors.insert(caps[1].to_string());
+                }
+            }
+            NodeValue::HtmlInline(html) => {
+                for caps in html_id.captures_iter(html) {
+                    anchors.insert(caps[1].to_string());
+                }
+            }
+            NodeValue::FootnoteDefinition(def) => {
+                anchors.insert(format!("fn-{}", def.name));
+            }
+            _ => {}
+        }
+    }
+    anchors
+}
+
+fn has_anchor(anchors: &HashSet<String>, fragment: &str) -> bool {
+    anchors.contains(fragment) || anchors.contains(&percent_decode(fragment))
+}
+
+fn problem(
+    path: &Path,
+    line: usize,
+    column: usize,
+    kind: &str,
+    target: &str,
+    message: &str,
+) -> LinkProblem {
+    LinkProblem {
+        file_path: path.to_string_lossy().to_string(),
+        file_name: path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+        line,
+        column,
+        kind: kind.to_string(),
+        target: target.to_string(),
+        message: message.to_string(),
+    }
+}
@@ -0,0 +1,391 @@
+use crate::commands::is_markdown_file;
+use crate::markdown::escape_html;
+use crate::scan_options::ScanOptions;
+use comrak::arena_tree::Node;
+use comrak::nodes::{Ast, AstNode, NodeValue};
+use comrak::{Anchorizer, Arena};
+use serde::{Deserialize, Serialize};
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Resolves `[[wiki links]]` and relative links from one document to other
+/// markdown files, searching the opened folder for wiki-link targets.
+pub struct LinkResolver {
+    file_dir: PathBuf,
+    root: PathBuf,
+    scan_options: ScanOptions,
+    /// Wiki-link targets under `root`, built on first use unless shared.
+    pages: OnceCell<Arc<PageIndex>>,
+}
+
+/// Markdown files under a folder, keyed by normalized file stem for
+/// wiki-link lookups.
+pub struct PageIndex {
+    root: PathBuf,
+    pages: HashMap<String, Vec<PathBuf>>,
+}
+
+impl PageIndex {
+    /// Index the markdown files the folder tree would show under `root`.
+    pub fn build(root: &Path, scan_options: &ScanOptions) -> Self {
+        let mut pages: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for entry in scan_options.walker(root).build().flatten() {
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            if !is_file || !is_markdown_file(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let path = entry.into_path();
+            if let Some(stem) = path.file_stem() {
+                pages
+                    .entry(page_key(&stem.to_string_lossy()))
+                    .or_default()
+                    .push(path);
+            }
+        }
+        PageIndex {
+            root: root.to_path_buf(),
+            pages,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Every markdown file in the index.
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.pages.values().flatten()
+    }
+}
+
+/// Where a link points once resolved.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub enum LinkTarget {
+    /// A markdown file, with an optional heading anchor.
+    Document {
+        path: PathBuf,
+        fragment: Option<String>,
+    },
+    /// A local target that doesn't exist.
+    Unresolved(String),
+    /// External URLs, in-page anchors and non-markdown files.
+    Other,
+}
+
+/// A link and where it pointed when a document was rendered, so cached
+/// HTML can be checked against the filesystem later.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResolvedLink {
+    pub url: String,
+    pub wiki: bool,
+    pub target: LinkTarget,
+}
+
+impl LinkResolver {
+    /// `root` is the opened folder; it is only used when it contains `file`.
+    pub fn new(file: &Path, root: Option<&Path>, scan_options: &ScanOptions) -> Self {
+        let file_dir = file.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let root = match root {
+            Some(root) if file.starts_with(root) => root.to_path_buf(),
+            _ => file_dir.clone(),
+        };
+        LinkResolver {
+            file_dir,
+            root,
+            scan_options: scan_options.clone(),
+            pages: OnceCell::new(),
+        }
+    }
+
+    /// Like `new`, but reuses an already built page index for `file`'s
+    /// folder.
+    pub fn with_pages(file: &Path, pages: Arc<PageIndex>) -> Self {
+        LinkResolver {
+            file_dir: file.parent().unwrap_or(Path::new("/")).to_path_buf(),
+            root: pages.root.clone(),
+            scan_options: ScanOptions::default(),
+            pages: OnceCell::from(pages),
+        }
+    }
+
+    pub fn file_dir(&self) -> &Path {
+        &self.file_dir
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Whether `link` still resolves to the same target.
+    pub fn still_resolves(&self, link: &ResolvedLink) -> bool {
+        let target = if link.wiki {
+            self.resolve_wiki(&link.url)
+        } else {
+            self.resolve_url(&link.url)
+        };
+        target == link.target
+    }
+
+    /// Resolve the URL of a regular markdown link.
+    pub fn resolve_url(&self, url: &str) -> LinkTarget {
+        if url.is_empty() || url.starts_with('#') || has_scheme(url) {
+            return LinkTarget::Other;
+        }
+        let (path, fragment) = split_fragment(url);
+        let path = percent_decode(path);
+        let fragment = fragment.map(percent_decode);
+
+        // Leading `/` is relative to the opened folder
+        let candidate = match path.strip_prefix('/') {
+            Some(rest) => self.root.join(rest),
+            None => self.file_dir.join(&path),
+        };
+        let candidate = normalize_path(&candidate);
+
+        if candidate.is_file() {
+            if is_markdown_file(&candidate.to_string_lossy()) {
+                return LinkTarget::Document {
+                    path: candidate,
+                    fragment,
+                };
+            }
+            return LinkTarget::Other;
+        }
+        // Extensionless links to notes: `[Setup](setup)`
+        if candidate.extension().is_none() {
+            let with_ext = candidate.with_extension("md");
+            if with_ext.is_file() {
+                return LinkTarget::Document {
+                    path: with_ext,
+                    fragment,
+                };
+            }
+        }
+        if candidate.is_dir() {
+            return LinkTarget::Other;
+        }
+        LinkTarget::Unresolved(path)
+    }
+
+    /// Resolve a wiki-link target such as `Page Name` or `notes/Page#Heading`.
+    pub fn resolve_wiki(&self, target: &str) -> LinkTarget {
+        let (name, heading) = split_fragment(target.trim());
+        // Wiki-link fragments are heading text; turn them into anchor ids.
+        let fragment = heading.map(|h| Anchorizer::new().anchorize(h.trim().to_string()));
+        let name = name.trim();
+        if name.is_empty() {
+            return match fragment {
+                Some(_) => LinkTarget::Other,
+                None => LinkTarget::Unresolved(target.to_string()),
+            };
+        }
+
+        // Explicit paths resolve like regular links
+        if name.contains('/') || is_markdown_file(name) {
+            let found = [&self.file_dir, &self.root].into_iter().find_map(|base| {
+                let candidate = normalize_path(&base.join(name.trim_start_matches('/')));
+                [candidate.clone(), candidate.with_extension("md")]
+                    .into_iter()
+                    .find(|p| p.is_file() && is_markdown_file(&p.to_string_lossy()))
+            });
+            return match found {
+                Some(path) => LinkTarget::Document { path, fragment },
+                None => LinkTarget::Unresolved(name.to_string()),
+            };
+        }
+
+        let candidates = match self.pages().pages.get(&page_key(name)) {
+            Some(candidates) => candidates,
+            None => return LinkTarget::Unresolved(name.to_string()),
+        };
+        // Prefer a page next to the current file, then the shallowest match
+        let path = candidates
+            .iter()
+            .find(|p| p.parent() == Some(self.file_dir.as_path()))
+            .or_else(|| candidates.iter().min_by_key(|p| p.components().count()))
+            .cloned();
+        match path {
+            Some(path) => LinkTarget::Document { path, fragment },
+            None => LinkTarget::Unresolved(name.to_string()),
+        }
+    }
+
+    fn pages(&self) -> &PageIndex {
+        self.pages
+            .get_or_init(|| Arc::new(PageIndex::build(&self.root, &self.scan_options)))
+    }
+}
+
+/// The page index of `root` held in `slot`, built and stored there when the
+/// slot is empty or holds another folder's. The folder watcher empties the
+/// slot when files come and go.
+pub fn shared_pages(
+    slot: &Mutex<Option<Arc<PageIndex>>>,
+    root: &Path,
+    scan_options: &ScanOptions,
+) -> Arc<PageIndex> {
+    if let Some(pages) = slot.lock().unwrap().as_ref().filter(|p| p.root == root) {
+        return pages.clone();
+    }
+    // Built without the lock so renders of other documents aren't held up
+    let pages = Arc::new(PageIndex::build(root, scan_options));
+    *slot.lock().unwrap() = Some(pages.clone());
+    pages
+}
+
+/// Rewrite links to markdown files as in-app links carrying the resolved
+/// path (`data-md-path`) and anchor (`data-md-fragment`). Links to missing
+/// files are marked `md-link-unresolved`. Returns every link with its
+/// resolution.
+pub fn rewrite_links<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    resolver: &LinkResolver,
+) -> Vec<ResolvedLink> {
+    let links: Vec<_> = root
+        .descendants()
+        .filter(|n| {
+            matches!(
+                n.data.borrow().value,
+                NodeValue::Link(_) | NodeValue::WikiLink(_)
+            )
+        })
+        .collect();
+
+    let mut resolved = Vec::new();
+    for link in links {
+        let (target, href, title, wiki) = match &link.data.borrow().value {
+            NodeValue::Link(l) => (
+                resolver.resolve_url(&l.url),
+                l.url.clone(),
+                l.title.clone(),
+                false,
+            ),
+            NodeValue::WikiLink(w) => (
+                resolver.resolve_wiki(&w.url),
+                w.url.clone(),
+                String::new(),
+                true,
+            ),
+            _ => continue,
+        };
+        resolved.push(ResolvedLink {
+            url: href.clone(),
+            wiki,
+            target: target.clone(),
+        });
+
+        let open = match target {
+            LinkTarget::Document { path, fragment } => {
+                let mut tag = format!(
+                    "<a href=\"{}\" class=\"md-link\" data-md-path=\"{}\"",
+                    escape_html(&href),
+                    escape_html(&path.to_string_lossy())
+                );
+                if let Some(fragment) = fragment {
+                    tag.push_str(&format!(" data-md-fragment=\"{}\"", escape_html(&fragment)));
+                }
+                if !title.is_empty() {
+                    tag.push_str(&format!(" title=\"{}\"", escape_html(&title)));
+                }
+                tag.push('>');
+                tag
+            }
+            LinkTarget::Unresolved(missing) => format!(
+                "<a href=\"{}\" class=\"md-link md-link-unresolved\" data-md-unresolved=\"{}\" title=\"Unresolved link: {}\">",
+                escape_html(&href),
+                escape_html(&missing),
+                escape_html(&missing)
+            ),
+            // External and in-page links keep comrak's rendering
+            LinkTarget::Other => continue,
+        };
+
+        // Splice the link text between raw opening and closing tags.
+        let start = link.data.borrow().sourcepos.start;
+        let html_inline = |literal: String| {
+            arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlInline(literal),
+                start,
+            ))))
+        };
+        link.insert_before(html_inline(open));
+        while let Some(child) = link.first_child() {
+            link.insert_before(child);
+        }
+        link.insert_before(html_inline("</a>".to_string()));
+        link.detach();
+    }
+    resolved
+}
+
+/// Normalized key for matching wiki-link names to file stems:
+/// case-insensitive, with spaces, hyphens and underscores treated alike.
+fn page_key(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c == ' ' || c == '_' { '-' } else { c })
+        .collect()
+}
+
+/// Whether `url` starts with a URL scheme such as `https:` or `mailto:`.
+/// Single letters are Windows drive letters, not schemes.
+pub(crate) fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+pub(crate) fn split_fragment(url: &str) -> (&str, Option<&str>) {
+    match url.split_once('#') {
+        Some((path, fragment)) if !fragment.is_empty() => (path, Some(fragment)),
+        Some((path, _)) => (path, None),
+        None => (url, None),
+    }
+}
+
+/// Decode `%XX` escapes, leaving malformed ones untouched.
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("0");
+            out.push(u8::from_str_radix(hex, 16).unwrap_or(0));
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Resolve `.` and `..` components without touching the filesystem.
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
@@ -1,5 +1,21 @@
-use comrak::{markdown_to_html, Options};
+use crate::diagrams::{render_diagram, DiagramKind};
+use crate::highlight::ClassedHighlighter;
+use crate::include::{expand_includes, skip_includes, IncludeScope};
+use crate::links::{rewrite_links, LinkResolver, ResolvedLink};
+use crate::render_options::RenderOptions;
+use crate::sanitize::sanitize_html;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use comrak::arena_tree::Node;
+use comrak::nodes::{Ast, AstNode, NodeHtmlBlock, NodeValue};
+use comrak::{format_html_with_plugins, parse_document, Arena, Options, Plugins};
 use regex::Regex;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source bytes per chunk when a large document is split for streaming.
+const CHUNK_BYTES: usize = 256 * 1024;
 
 /// Escape `>=` at the start of list items so `>` isn't parsed as a blockquote.
 /// In markdown, `>` after a list marker starts a nested blockquote, but `>=` is
@@ -9,28 +25,398 @@ pub(crate) fn preprocess_markdown(input: &str) -> String {
     re.replace_all(input, "${1}\\>=").to_string()
 }
 
-pub fn render_markdown(input: &str) -> String {
-    let input = preprocess_markdown(input);
-    let mut options = Options::default();
+/// Escape text for use in HTML content or attribute values.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// comrak options with every feature on, for passes that analyse documents
+/// rather than render them (outlines, backlinks, the link audit).
+pub(crate) fn markdown_options() -> Options<'static> {
+    RenderOptions::default().comrak_options()
+}
+
+/// Drop raw HTML written in the document, for when `raw_html` is off.
+pub(crate) fn strip_raw_html<'a>(root: &'a AstNode<'a>) {
+    let html: Vec<_> = root
+        .descendants()
+        .filter(|n| {
+            matches!(
+                n.data.borrow().value,
+                NodeValue::HtmlBlock(_) | NodeValue::HtmlInline(_)
+            )
+        })
+        .collect();
+    for node in html {
+        node.detach();
+    }
+}
+
+/// Turn `==text==` into `<mark>` elements. comrak has no highlight
+/// extension, so marks are spliced in as inline HTML after raw HTML has been
+/// stripped; the PDF exporter maps them to `#highlight` and speech skips them.
+/// A highlight can't span other inline formatting.
+pub(crate) fn render_highlights<'a>(arena: &'a Arena<AstNode<'a>>, root: &'a AstNode<'a>) {
+    let re = Regex::new(r"==([^\s=](?:[^=]*[^\s=])?)==").unwrap();
+    let texts: Vec<_> = root
+        .descendants()
+        .filter(|n| matches!(&n.data.borrow().value, NodeValue::Text(t) if t.contains("==")))
+        .collect();
+    for node in texts {
+        let (text, start) = {
+            let ast = node.data.borrow();
+            match &ast.value {
+                NodeValue::Text(text) => (text.clone(), ast.sourcepos.start),
+                _ => continue,
+            }
+        };
+        if !re.is_match(&text) {
+            continue;
+        }
+        let inline =
+            |value: NodeValue| arena.alloc(Node::new(RefCell::new(Ast::new(value, start))));
+        let mut last = 0;
+        for caps in re.captures_iter(&text) {
+            let whole = caps.get(0).unwrap();
+            if whole.start() > last {
+                let before = text[last..whole.start()].to_string();
+                node.insert_before(inline(NodeValue::Text(before)));
+            }
+            node.insert_before(inline(NodeValue::HtmlInline("<mark>".to_string())));
+            node.insert_before(inline(NodeValue::Text(caps[1].to_string())));
+            node.insert_before(inline(NodeValue::HtmlInline("</mark>".to_string())));
+            last = whole.end();
+        }
+        if last < text.len() {
+            node.insert_before(inline(NodeValue::Text(text[last..].to_string())));
+        }
+        node.detach();
+    }
+}
+
+/// Rendered HTML, the files its include directives pulled in and the links
+/// it resolved.
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub includes: Vec<PathBuf>,
+    pub links: Vec<ResolvedLink>,
+    /// Offsets in `html` where block-level chunks after the first begin.
+    /// Empty unless the document is large enough to stream.
+    pub breaks: Vec<usize>,
+    /// Diagrams went through external renderers, whose output changes as
+    /// they are installed or updated.
+    pub external_diagrams: bool,
+}
+
+impl RenderedMarkdown {
+    /// `html` split at `breaks`; each chunk is a run of complete blocks.
+    pub fn chunks(&self) -> Vec<&str> {
+        let mut chunks = Vec::with_capacity(self.breaks.len() + 1);
+        let mut start = 0;
+        for &end in &self.breaks {
+            chunks.push(&self.html[start..end]);
+            start = end;
+        }
+        chunks.push(&self.html[start..]);
+        chunks
+    }
+}
+
+/// Render markdown to HTML with the user's `RenderOptions`. With an include
+/// scope, include directives are expanded relative to its file. With a
+/// `LinkResolver`, links to other markdown files and `[[wiki links]]` are
+/// resolved against the filesystem. With `sanitize`, raw HTML is filtered
+/// through an allow-list so untrusted documents can't run scripts in the
+/// webview, external diagram renderers are skipped and include directives
+/// are replaced with a notice.
+pub fn render_markdown(
+    input: &str,
+    render_options: &RenderOptions,
+    includes: Option<&IncludeScope>,
+    links: Option<&LinkResolver>,
+    sanitize: bool,
+) -> RenderedMarkdown {
+    let input = render_options.prepare(input);
+    let options = render_options.comrak_options();
+
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &options);
+    let includes = match includes.filter(|_| !sanitize) {
+        Some(scope) => expand_includes(&arena, root, &input, scope, render_options),
+        None => {
+            if sanitize {
+                skip_includes(&arena, root, &input, render_options);
+            }
+            Vec::new()
+        }
+    };
+    if !render_options.raw_html {
+        strip_raw_html(root);
+    }
+    // Marks go in before passes that splice raw HTML around blocks, and
+    // never inside raw HTML elements left open across blocks, so a chunk
+    // never splits an element.
+    let marker = format!(
+        "<!-- chunk-{:x} -->",
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default()
+    );
+    insert_chunk_breaks(&arena, root, &input, &marker);
+    render_highlights(&arena, root);
+    render_math(root);
+    let external_diagrams = render_diagrams(root, !sanitize);
+    crate::alerts::render_alerts(&arena, root);
+    crate::outline::expand_toc(root, render_options.heading_ids);
+    let links = match links {
+        Some(resolver) => rewrite_links(&arena, root, resolver),
+        None => Vec::new(),
+    };
+
+    // Highlight fenced code in Rust; the webview only styles the token classes.
+    let highlighter = ClassedHighlighter;
+    let mut plugins = Plugins::default();
+    plugins.render.codefence_syntax_highlighter = Some(&highlighter);
+
+    let mut html = Vec::new();
+    let html = match format_html_with_plugins(root, &options, &mut html, &plugins) {
+        Ok(()) => String::from_utf8(html).unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Warning: Could not format document: {}", e);
+            String::new()
+        }
+    };
+
+    // Sanitize chunk by chunk: the markers are comments, which ammonia drops.
+    let mut output = String::with_capacity(html.len());
+    let mut breaks = Vec::new();
+    for (i, chunk) in html.split(marker.as_str()).enumerate() {
+        if i > 0 {
+            breaks.push(output.len());
+        }
+        if sanitize {
+            output.push_str(&sanitize_html(chunk));
+        } else {
+            output.push_str(chunk);
+        }
+    }
+    RenderedMarkdown {
+        html: output,
+        includes,
+        links,
+        external_diagrams,
+        breaks,
+    }
+}
+
+/// Split the top-level blocks of a large document into chunks of roughly
+/// `CHUNK_BYTES` of source, separated by `marker` HTML blocks. Footnote
+/// definitions come last and stay together, as comrak wraps them in one
+/// section. Raw HTML such as a `<details>` around blank-line separated
+/// blocks is kept in one chunk until its elements are closed.
+fn insert_chunk_breaks<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    input: &str,
+    marker: &str,
+) {
+    if input.len() <= CHUNK_BYTES {
+        return;
+    }
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut chunk_start = 0;
+    // Raw HTML elements opened by earlier blocks and not yet closed
+    let mut open_elements = 0;
+    let blocks: Vec<_> = root.children().collect();
+    for node in blocks {
+        let start = {
+            let ast = node.data.borrow();
+            if matches!(ast.value, NodeValue::FootnoteDefinition(_)) {
+                break;
+            }
+            ast.sourcepos.start
+        };
+        let offset = line_starts
+            .get(start.line.saturating_sub(1))
+            .copied()
+            .unwrap_or(input.len());
+        let inside_html = open_elements > 0;
+        for descendant in node.descendants() {
+            if let NodeValue::HtmlBlock(NodeHtmlBlock { literal, .. })
+            | NodeValue::HtmlInline(literal) = &descendant.data.borrow().value
+            {
+                open_elements = html_depth(literal, open_elements);
+            }
+        }
+        if !inside_html && offset >= chunk_start + CHUNK_BYTES {
+            let html = NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 0,
+                literal: format!("{}\n", marker),
+            });
+            node.insert_before(arena.alloc(Node::new(RefCell::new(Ast::new(html, start)))));
+            chunk_start = offset;
+        }
+    }
+}
+
+/// Elements left open after `html`, starting from `depth` open ones. Void
+/// elements and self-closing tags don't count; stray end tags are ignored.
+fn html_depth(html: &str, depth: usize) -> usize {
+    const VOID: &[&str] = &[
+        "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+        "track", "wbr",
+    ];
+    let re = Regex::new(r"<(/?)([A-Za-z][A-Za-z0-9-]*)(?:[^>]*?)(/?)>").unwrap();
+    let html = Regex::new(r"(?s)<!--.*?-->").unwrap().replace_all(html, "");
+    let mut depth = depth;
+    for caps in re.captures_iter(&html) {
+        let name = caps[2].to_ascii_lowercase();
+        if VOID.contains(&name.as_str()) || !caps[3].is_empty() {
+            continue;
+        }
+        if caps[1].is_empty() {
+            depth += 1;
+        } else {
+            depth = depth.saturating_sub(1);
+        }
+    }
+    depth
+}
+
+/// Replace diagram fences with their rendered SVG. Fences without a renderer
+/// stay code blocks; the webview still draws Mermaid ones itself. Returns
+/// whether any went to external renderers, which only `trusted` documents
+/// use.
+fn render_diagrams<'a>(root: &'a AstNode<'a>, trusted: bool) -> bool {
+    let mut external = false;
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let (kind, diagram) = match &ast.value {
+            NodeValue::CodeBlock(cb) => match DiagramKind::from_info(&cb.info) {
+                Some(kind) => {
+                    external |= trusted;
+                    match render_diagram(kind, &cb.literal, trusted) {
+                        Some(diagram) => (kind, diagram),
+                        None => continue,
+                    }
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+        // An <img> keeps each diagram's ids and styles isolated from the page.
+        let literal = format!(
+            "<div class=\"mermaid-diagram diagram-rendered\" data-diagram=\"{}\" \
+             data-sourcepos=\"{}\"><img src=\"data:image/svg+xml;base64,{}\" \
+             width=\"{}\" alt=\"{} diagram\"></div>\n",
+            kind.name(),
+            ast.sourcepos,
+            STANDARD.encode(&diagram.svg),
+            diagram.width.round(),
+            kind.name()
+        );
+        ast.value = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 0,
+            literal,
+        });
+    }
+    external
+}
+
+/// Replace math spans and ```` ```math ```` blocks with server-rendered MathML.
+fn render_math<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let replacement = match &ast.value {
+            NodeValue::Math(math) => NodeValue::HtmlInline(crate::math::latex_to_mathml(
+                &math.literal,
+                math.display_math,
+            )),
+            NodeValue::CodeBlock(cb) if cb.info.split_whitespace().next() == Some("math") => {
+                // Raw HTML blocks carry no sourcepos, so tag the <math> element ourselves.
+                let mathml = crate::math::latex_to_mathml(&cb.literal, true).replacen(
+                    "<math",
+                    &format!("<math data-sourcepos=\"{}\"", ast.sourcepos),
+                    1,
+                );
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    block_type: 0,
+                    literal: mathml,
+                })
+            }
+            _ => continue,
+        };
+        ast.value = replacement;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Paragraphs adding up to about `bytes` of source.
+    fn paragraphs(bytes: usize) -> String {
+        let paragraph = format!("{}\n\n", "lorem ipsum dolor sit amet ".repeat(20));
+        paragraph.repeat(bytes / paragraph.len() + 1)
+    }
+
+    fn render(input: &str) -> RenderedMarkdown {
+        render_markdown(input, &RenderOptions::default(), None, None, false)
+    }
 
-    // Enable GFM extensions
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.footnotes = true;
-    options.extension.header_ids = Some(String::new());
-    options.extension.shortcodes = true;
+    #[test]
+    fn small_documents_are_one_chunk() {
+        let rendered = render("# Title\n\nSome text.\n");
+        assert!(rendered.breaks.is_empty());
+        assert_eq!(rendered.chunks().len(), 1);
+    }
 
-    // Parse options
-    options.parse.smart = true;
+    #[test]
+    fn large_documents_split_between_blocks() {
+        let input = paragraphs(CHUNK_BYTES * 3);
+        let rendered = render(&input);
+        let chunks = rendered.chunks();
+        assert!(chunks.len() >= 3);
+        assert_eq!(chunks.concat(), rendered.html);
+        for chunk in chunks {
+            assert!(chunk.trim_start().starts_with("<p"));
+            assert!(chunk.trim_end().ends_with("</p>"));
+        }
+    }
 
-    // Render options
-    options.render.unsafe_ = true; // Allow raw HTML in markdown
-    // Treat single newlines as hard line breaks. CommonMark would merge
-    // consecutive non-blank lines into one paragraph, but users editing
-    // documents in this app expect WYSIWYG-style line breaks.
-    options.render.hardbreaks = true;
+    #[test]
+    fn open_html_elements_stay_in_one_chunk() {
+        let input = format!(
+            "<details>\n<summary>More</summary>\n\n{}</details>\n\n{}",
+            paragraphs(CHUNK_BYTES * 2),
+            paragraphs(CHUNK_BYTES * 2)
+        );
+        let rendered = render(&input);
+        let chunks = rendered.chunks();
+        assert!(chunks.len() >= 2);
+        let first = chunks[0];
+        assert!(first.contains("<details>"));
+        assert!(first.contains("</details>"));
+        for chunk in &chunks[1..] {
+            assert!(!chunk.contains("</details>"));
+        }
+    }
 
-    markdown_to_html(&input, &options)
+    #[test]
+    fn html_depth_counts_unclosed_elements() {
+        assert_eq!(html_depth("<details>\n<summary>More</summary>", 0), 1);
+        assert_eq!(html_depth("</details>", 1), 0);
+        assert_eq!(
+            html_depth("<div><br><img src=\"a.png\"/><hr /></div>", 0),
+            0
+        );
+        assert_eq!(html_depth("<!-- <div> -->", 0), 0);
+        assert_eq!(html_depth("</div>", 0), 0);
+    }
 }
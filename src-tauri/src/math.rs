@@ -0,0 +1,1381 @@
+//! LaTeX math support shared by the HTML renderer and the Typst exporter.
+//!
+//! A math span is parsed once into a small `MathNode` tree, which is then
+//! emitted either as MathML (for the webview) or as native Typst math (for PDF
+//! export), so a formula looks the same on screen and in the exported file.
+//! Only the commonly used subset of LaTeX math is understood; anything else
+//! falls back to showing the raw source.
+
+use crate::typst_convert::typst_string;
+
+/// Deepest nesting of groups and arguments parsed before giving up, so a
+/// hostile document can't overflow the stack.
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone)]
+enum MathNode {
+    Ident(String),
+    Number(String),
+    Operator(String),
+    /// Big operators such as `\sum` and `\int`.
+    LargeOp {
+        symbol: String,
+        limits: bool,
+    },
+    /// Named functions such as `\sin` or `\lim`.
+    Function {
+        name: String,
+        limits: bool,
+    },
+    Text(String),
+    Space(Space),
+    Prime,
+    Row(Vec<MathNode>),
+    Frac(Box<MathNode>, Box<MathNode>),
+    Binom(Box<MathNode>, Box<MathNode>),
+    Sqrt(Option<Box<MathNode>>, Box<MathNode>),
+    Scripts {
+        base: Box<MathNode>,
+        sub: Option<Box<MathNode>>,
+        sup: Option<Box<MathNode>>,
+    },
+    Fenced {
+        open: String,
+        close: String,
+        body: Box<MathNode>,
+    },
+    Accent {
+        kind: Accent,
+        body: Box<MathNode>,
+    },
+    Styled {
+        variant: Variant,
+        body: Box<MathNode>,
+    },
+    Table {
+        env: TableEnv,
+        rows: Vec<Vec<MathNode>>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Space {
+    Thin,
+    Medium,
+    Thick,
+    Quad,
+    Wide,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Accent {
+    Hat,
+    Bar,
+    Vec,
+    Dot,
+    DoubleDot,
+    Tilde,
+    Overline,
+    Underline,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Variant {
+    Bold,
+    Italic,
+    Upright,
+    DoubleStruck,
+    Calligraphic,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableEnv {
+    Matrix,
+    PMatrix,
+    BMatrix,
+    BraceMatrix,
+    VMatrix,
+    DoubleVMatrix,
+    Cases,
+    Aligned,
+}
+
+/// Render a LaTeX math expression as a MathML `<math>` element.
+/// Expressions that can't be parsed are shown as escaped source.
+pub fn latex_to_mathml(latex: &str, display: bool) -> String {
+    match parse(latex) {
+        Ok(node) => {
+            let mut out = String::new();
+            write_mathml(&node, &mut out, None);
+            let mode = if display { "block" } else { "inline" };
+            format!(
+                "<math display=\"{mode}\"><semantics><mrow>{out}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+                escape_xml(latex)
+            )
+        }
+        Err(_) => {
+            let class = if display {
+                "math-error math-display"
+            } else {
+                "math-error"
+            };
+            format!("<code class=\"{class}\">{}</code>", escape_xml(latex))
+        }
+    }
+}
+
+/// Render a LaTeX math expression as a Typst equation (`$...$`).
+/// Expressions that can't be parsed are emitted as raw text.
+pub fn latex_to_typst(latex: &str, display: bool) -> String {
+    match parse(latex) {
+        Ok(node) => {
+            let mut out = String::new();
+            write_typst(&node, &mut out);
+            let body = out.trim();
+            if body.is_empty() {
+                String::new()
+            } else if display {
+                // Surrounding spaces make Typst lay the equation out as a block.
+                format!("$ {body} $")
+            } else {
+                format!("${body}$")
+            }
+        }
+        Err(_) => {
+            if !latex.contains('`') {
+                return format!("`{latex}`");
+            }
+            // Typst reads two backticks as empty raw, so use a fence of three
+            // or more, longer than any run inside. The spaces keep the source
+            // from being read as a language tag.
+            let longest = latex.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+            let ticks = "`".repeat(longest.max(2) + 1);
+            format!("{ticks} {latex} {ticks}")
+        }
+    }
+}
+
+// ===== Parsing =====
+
+fn parse(latex: &str) -> Result<MathNode, String> {
+    parse_nested(latex, 0)
+}
+
+/// Parse an expression found `depth` levels deep in another one.
+fn parse_nested(latex: &str, depth: usize) -> Result<MathNode, String> {
+    let mut parser = Parser {
+        chars: latex.chars().collect(),
+        pos: 0,
+        depth,
+    };
+    let (rows, stop) = parser.parse_rows()?;
+    if stop != Stop::End {
+        return Err(format!("Unexpected {:?}", stop));
+    }
+    if rows.len() == 1 && rows[0].len() == 1 {
+        let mut cells = rows.into_iter().next().unwrap();
+        Ok(cells.pop().unwrap())
+    } else {
+        // Bare `&` / `\\` at the top level behave like an `aligned` block.
+        Ok(MathNode::Table {
+            env: TableEnv::Aligned,
+            rows,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Stop {
+    End,
+    CloseBrace,
+    Right,
+    /// `\end` with the environment it names.
+    EndEnv(String),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Groups and arguments currently open.
+    depth: usize,
+}
+
+impl Parser {
+    /// Run `parse` one nesting level deeper, failing past `MAX_NESTING`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.depth >= MAX_NESTING {
+            return Err("Expression nested too deeply".into());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Read a control word (`\alpha`) or control symbol (`\,`) after the backslash.
+    fn read_command(&mut self) -> String {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            if let Some(c) = self.peek() {
+                self.pos += 1;
+                return c.to_string();
+            }
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Look at the next command name without consuming it.
+    fn peek_command(&self) -> Option<String> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let mut end = self.pos + 1;
+        while matches!(self.chars.get(end), Some(c) if c.is_ascii_alphabetic()) {
+            end += 1;
+        }
+        if end == self.pos + 1 {
+            return self.chars.get(end).map(|c| c.to_string());
+        }
+        Some(self.chars[self.pos + 1..end].iter().collect())
+    }
+
+    /// Parse cells separated by `&` and rows separated by `\\` until a closing
+    /// token. Plain expressions come back as a single row with a single cell.
+    fn parse_rows(&mut self) -> Result<(Vec<Vec<MathNode>>, Stop), String> {
+        self.nested(Self::parse_rows_inner)
+    }
+
+    fn parse_rows_inner(&mut self) -> Result<(Vec<Vec<MathNode>>, Stop), String> {
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        let mut current = Vec::new();
+
+        let stop = loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => break Stop::End,
+                Some('}') => {
+                    self.pos += 1;
+                    break Stop::CloseBrace;
+                }
+                Some('&') => {
+                    self.pos += 1;
+                    cells.push(row_node(std::mem::take(&mut current)));
+                }
+                Some('\\') => match self.peek_command().as_deref() {
+                    Some("\\") | Some("cr") => {
+                        self.pos += 1;
+                        self.read_command();
+                        // Skip an optional spacing argument such as `\\[2pt]`.
+                        if self.peek() == Some('[') {
+                            self.read_until(']');
+                        }
+                        cells.push(row_node(std::mem::take(&mut current)));
+                        rows.push(std::mem::take(&mut cells));
+                    }
+                    Some("right") => {
+                        self.pos += 1;
+                        self.read_command();
+                        break Stop::Right;
+                    }
+                    Some("end") => {
+                        self.pos += 1;
+                        self.read_command();
+                        break Stop::EndEnv(self.read_group_text()?);
+                    }
+                    _ => {
+                        if let Some(node) = self.parse_scripted()? {
+                            current.push(node);
+                        }
+                    }
+                },
+                Some(_) => {
+                    if let Some(node) = self.parse_scripted()? {
+                        current.push(node);
+                    }
+                }
+            }
+        };
+
+        if !current.is_empty() || !cells.is_empty() || rows.is_empty() {
+            cells.push(row_node(current));
+        }
+        if !cells.is_empty() {
+            rows.push(cells);
+        }
+        Ok((rows, stop))
+    }
+
+    /// Parse everything up to the matching `}` as a single node.
+    fn parse_group_body(&mut self) -> Result<MathNode, String> {
+        let (rows, stop) = self.parse_rows()?;
+        if stop != Stop::CloseBrace {
+            return Err("Unbalanced braces".into());
+        }
+        Ok(flatten_rows(rows))
+    }
+
+    /// Parse a single argument: a `{group}`, a command, or a single character.
+    fn parse_argument(&mut self) -> Result<MathNode, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.parse_group_body()
+            }
+            // `x^12` only raises the first digit, as in TeX.
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(MathNode::Number(c.to_string()))
+            }
+            Some(_) => self
+                .parse_atom()?
+                .ok_or_else(|| "Missing argument".to_string()),
+            None => Err("Missing argument".into()),
+        }
+    }
+
+    /// Read the raw text of a `{...}` group (used for `\text` and environment names).
+    fn read_group_text(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err("Expected '{'".into());
+        }
+        self.pos += 1;
+        let mut depth = 1;
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(text);
+                    }
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err("Unbalanced braces".into())
+    }
+
+    fn read_until(&mut self, end: char) -> String {
+        let mut text = String::new();
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == end {
+                break;
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Parse an atom followed by any `_`, `^` and `'` attachments.
+    fn parse_scripted(&mut self) -> Result<Option<MathNode>, String> {
+        let base = match self.peek() {
+            Some('_') | Some('^') => MathNode::Row(vec![]),
+            _ => match self.parse_atom()? {
+                Some(node) => node,
+                None => return Ok(None),
+            },
+        };
+
+        let mut sub = None;
+        let mut sup: Option<MathNode> = None;
+        let mut primes = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(Box::new(self.parse_argument()?));
+                }
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument()?);
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    primes += 1;
+                }
+                Some('\\')
+                    if matches!(
+                        self.peek_command().as_deref(),
+                        Some("limits") | Some("nolimits")
+                    ) =>
+                {
+                    self.pos += 1;
+                    self.read_command();
+                }
+                _ => break,
+            }
+        }
+
+        if primes > 0 {
+            let mut nodes = vec![MathNode::Prime; primes];
+            if let Some(s) = sup {
+                nodes.push(s);
+            }
+            sup = Some(row_node(nodes));
+        }
+
+        if sub.is_none() && sup.is_none() {
+            return Ok(Some(base));
+        }
+        Ok(Some(MathNode::Scripts {
+            base: Box::new(base),
+            sub,
+            sup: sup.map(Box::new),
+        }))
+    }
+
+    fn parse_atom(&mut self) -> Result<Option<MathNode>, String> {
+        self.nested(Self::parse_atom_inner)
+    }
+
+    fn parse_atom_inner(&mut self) -> Result<Option<MathNode>, String> {
+        self.skip_whitespace();
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        if c.is_ascii_digit()
+            || (c == '.' && matches!(self.chars.get(self.pos + 1), Some(d) if d.is_ascii_digit()))
+        {
+            let start = self.pos;
+            while matches!(self.peek(), Some(d) if d.is_ascii_digit() || d == '.') {
+                self.pos += 1;
+            }
+            let number: String = self.chars[start..self.pos].iter().collect();
+            return Ok(Some(MathNode::Number(number)));
+        }
+
+        self.pos += 1;
+        match c {
+            '{' => self.parse_group_body().map(Some),
+            '}' => Err("Unbalanced braces".into()),
+            '\\' => self.parse_command(),
+            '~' => Ok(Some(MathNode::Space(Space::Thick))),
+            c if c.is_alphabetic() => Ok(Some(MathNode::Ident(c.to_string()))),
+            c => Ok(Some(MathNode::Operator(c.to_string()))),
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Option<MathNode>, String> {
+        let name = self.read_command();
+        let node = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.parse_argument()?;
+                let den = self.parse_argument()?;
+                MathNode::Frac(Box::new(num), Box::new(den))
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.parse_argument()?;
+                let bottom = self.parse_argument()?;
+                MathNode::Binom(Box::new(top), Box::new(bottom))
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = if self.peek() == Some('[') {
+                    let text = self.read_until(']');
+                    Some(Box::new(parse_nested(&text, self.depth + 1)?))
+                } else {
+                    None
+                };
+                let body = self.parse_argument()?;
+                MathNode::Sqrt(index, Box::new(body))
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "textit" | "textbf" => {
+                MathNode::Text(self.read_group_text()?)
+            }
+            "operatorname" => MathNode::Function {
+                name: self.read_group_text()?,
+                limits: false,
+            },
+            "left" => {
+                let open = self.read_delimiter()?;
+                let (rows, stop) = self.parse_rows()?;
+                if stop != Stop::Right {
+                    return Err("\\left without \\right".into());
+                }
+                let close = self.read_delimiter()?;
+                MathNode::Fenced {
+                    open,
+                    close,
+                    body: Box::new(flatten_rows(rows)),
+                }
+            }
+            "begin" => self.parse_environment()?,
+            "," | "thinspace" => MathNode::Space(Space::Thin),
+            ":" | ">" | "medspace" => MathNode::Space(Space::Medium),
+            ";" | "thickspace" | " " => MathNode::Space(Space::Thick),
+            "quad" => MathNode::Space(Space::Quad),
+            "qquad" => MathNode::Space(Space::Wide),
+            "!" | "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "big"
+            | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" => return Ok(None),
+            _ => {
+                if let Some(kind) = accent_for(&name) {
+                    let body = self.parse_argument()?;
+                    MathNode::Accent {
+                        kind,
+                        body: Box::new(body),
+                    }
+                } else if let Some(variant) = variant_for(&name) {
+                    let body = self.parse_argument()?;
+                    MathNode::Styled {
+                        variant,
+                        body: Box::new(body),
+                    }
+                } else if let Some(limits) = function_limits(&name) {
+                    MathNode::Function { name, limits }
+                } else if let Some((symbol, limits)) = large_operator(&name) {
+                    MathNode::LargeOp {
+                        symbol: symbol.to_string(),
+                        limits,
+                    }
+                } else if let Some(symbol) = symbol_for(&name) {
+                    if symbol.chars().all(char::is_alphabetic)
+                        || matches!(symbol, "∞" | "∂" | "∇" | "∅" | "△" | "∠")
+                    {
+                        MathNode::Ident(symbol.to_string())
+                    } else {
+                        MathNode::Operator(symbol.to_string())
+                    }
+                } else {
+                    return Err(format!("Unsupported command \\{}", name));
+                }
+            }
+        };
+        Ok(Some(node))
+    }
+
+    fn read_delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\\') => {
+                self.pos += 1;
+                let name = self.read_command();
+                match name.as_str() {
+                    "{" | "lbrace" => Ok("{".into()),
+                    "}" | "rbrace" => Ok("}".into()),
+                    "|" | "Vert" => Ok("‖".into()),
+                    "vert" => Ok("|".into()),
+                    _ => symbol_for(&name)
+                        .map(String::from)
+                        .ok_or_else(|| format!("Unsupported delimiter \\{}", name)),
+                }
+            }
+            Some('.') => {
+                self.pos += 1;
+                Ok(String::new())
+            }
+            Some(c) => {
+                self.pos += 1;
+                Ok(c.to_string())
+            }
+            None => Err("Missing delimiter".into()),
+        }
+    }
+
+    fn parse_environment(&mut self) -> Result<MathNode, String> {
+        let name = self.read_group_text()?;
+        let env = match name.trim_end_matches('*') {
+            "matrix" | "smallmatrix" | "array" => TableEnv::Matrix,
+            "pmatrix" => TableEnv::PMatrix,
+            "bmatrix" => TableEnv::BMatrix,
+            "Bmatrix" => TableEnv::BraceMatrix,
+            "vmatrix" => TableEnv::VMatrix,
+            "Vmatrix" => TableEnv::DoubleVMatrix,
+            "cases" => TableEnv::Cases,
+            "aligned" | "align" | "alignat" | "gathered" | "gather" | "split" | "eqnarray" => {
+                TableEnv::Aligned
+            }
+            other => return Err(format!("Unsupported environment {}", other)),
+        };
+        // `array` and `alignat` take a column spec argument we don't need.
+        if matches!(name.as_str(), "array" | "alignat" | "alignat*") {
+            self.read_group_text()?;
+        }
+        let (rows, stop) = self.parse_rows()?;
+        match stop {
+            Stop::EndEnv(end) if end == name => {}
+            Stop::EndEnv(end) => {
+                return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end))
+            }
+            _ => return Err(format!("Unterminated environment {}", name)),
+        }
+        let rows = rows
+            .into_iter()
+            .filter(|row| !(row.len() == 1 && is_empty(&row[0])))
+            .collect();
+        Ok(MathNode::Table { env, rows })
+    }
+}
+
+fn row_node(mut nodes: Vec<MathNode>) -> MathNode {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        MathNode::Row(nodes)
+    }
+}
+
+fn flatten_rows(rows: Vec<Vec<MathNode>>) -> MathNode {
+    if rows.len() == 1 && rows[0].len() == 1 {
+        rows.into_iter().next().unwrap().pop().unwrap()
+    } else {
+        MathNode::Table {
+            env: TableEnv::Aligned,
+            rows,
+        }
+    }
+}
+
+fn is_empty(node: &MathNode) -> bool {
+    matches!(node, MathNode::Row(nodes) if nodes.is_empty())
+}
+
+// ===== Symbol tables =====
+
+fn symbol_for(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        // Greek lowercase
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" => "ϵ",
+        "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" => "θ",
+        "vartheta" => "ϑ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "omicron" => "ο",
+        "pi" => "π",
+        "varpi" => "ϖ",
+        "rho" => "ρ",
+        "varrho" => "ϱ",
+        "sigma" => "σ",
+        "varsigma" => "ς",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" => "ϕ",
+        "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        // Greek uppercase
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        // Letter-like
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "nexists" => "∄",
+        "angle" => "∠",
+        "triangle" => "△",
+        "prime" => "′",
+        "degree" => "°",
+        // Binary operators
+        "pm" => "±",
+        "mp" => "∓",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "⋅",
+        "ast" => "∗",
+        "star" => "⋆",
+        "circ" => "∘",
+        "bullet" => "∙",
+        "oplus" => "⊕",
+        "ominus" => "⊖",
+        "otimes" => "⊗",
+        "odot" => "⊙",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        // Relations
+        "le" | "leq" => "≤",
+        "ge" | "geq" => "≥",
+        "ne" | "neq" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "perp" => "⊥",
+        "parallel" => "∥",
+        "mid" => "∣",
+        "models" => "⊨",
+        "vdash" => "⊢",
+        "coloneqq" => "≔",
+        // Arrows
+        "to" | "rightarrow" => "→",
+        "gets" | "leftarrow" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" => "⇔",
+        "implies" | "Longrightarrow" => "⟹",
+        "impliedby" | "Longleftarrow" => "⟸",
+        "iff" | "Longleftrightarrow" => "⟺",
+        "longrightarrow" => "⟶",
+        "longleftarrow" => "⟵",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "hookrightarrow" => "↪",
+        // Dots
+        "ldots" | "dots" | "dotsc" | "dotsb" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        // Delimiters and escaped characters
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "{" | "lbrace" => "{",
+        "}" | "rbrace" => "}",
+        "|" | "Vert" => "‖",
+        "vert" => "|",
+        "colon" => ":",
+        "%" => "%",
+        "$" => "$",
+        "&" => "&",
+        "#" => "#",
+        "_" => "_",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Big operators and whether they take their scripts as limits in display mode.
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    let op = match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "bigvee" => ("⋁", true),
+        "bigwedge" => ("⋀", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Named functions, and whether their scripts are placed as limits.
+fn function_limits(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+        | "argmax" | "argmin" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "hom"
+        | "ker" | "arg" | "mod" | "bmod" => Some(false),
+        _ => None,
+    }
+}
+
+fn accent_for(name: &str) -> Option<Accent> {
+    let accent = match name {
+        "hat" | "widehat" => Accent::Hat,
+        "bar" => Accent::Bar,
+        "vec" | "overrightarrow" => Accent::Vec,
+        "dot" => Accent::Dot,
+        "ddot" => Accent::DoubleDot,
+        "tilde" | "widetilde" => Accent::Tilde,
+        "overline" => Accent::Overline,
+        "underline" => Accent::Underline,
+        _ => return None,
+    };
+    Some(accent)
+}
+
+fn variant_for(name: &str) -> Option<Variant> {
+    let variant = match name {
+        "mathbf" | "boldsymbol" | "bm" => Variant::Bold,
+        "mathit" => Variant::Italic,
+        "mathrm" => Variant::Upright,
+        "mathbb" => Variant::DoubleStruck,
+        "mathcal" | "mathscr" => Variant::Calligraphic,
+        "mathfrak" => Variant::Fraktur,
+        "mathsf" => Variant::SansSerif,
+        "mathtt" => Variant::Monospace,
+        _ => return None,
+    };
+    Some(variant)
+}
+
+// ===== MathML output =====
+
+fn write_mathml(node: &MathNode, out: &mut String, variant: Option<Variant>) {
+    let variant_attr = variant
+        .map(|v| format!(" mathvariant=\"{}\"", mathml_variant(v)))
+        .unwrap_or_default();
+    match node {
+        MathNode::Ident(s) => out.push_str(&format!("<mi{variant_attr}>{}</mi>", escape_xml(s))),
+        MathNode::Number(s) => out.push_str(&format!("<mn{variant_attr}>{}</mn>", escape_xml(s))),
+        MathNode::Operator(s) => out.push_str(&format!("<mo>{}</mo>", escape_xml(s))),
+        MathNode::LargeOp { symbol, .. } => {
+            out.push_str(&format!("<mo largeop=\"true\">{symbol}</mo>"))
+        }
+        MathNode::Function { name, limits } => {
+            if *limits {
+                out.push_str(&format!(
+                    "<mo movablelimits=\"true\" form=\"prefix\">{}</mo>",
+                    escape_xml(name)
+                ));
+            } else {
+                out.push_str(&format!("<mi>{}</mi>", escape_xml(name)));
+            }
+        }
+        MathNode::Text(s) => out.push_str(&format!("<mtext>{}</mtext>", escape_xml(s))),
+        MathNode::Space(space) => {
+            let width = match space {
+                Space::Thin => "0.1667em",
+                Space::Medium => "0.2222em",
+                Space::Thick => "0.2778em",
+                Space::Quad => "1em",
+                Space::Wide => "2em",
+            };
+            out.push_str(&format!("<mspace width=\"{width}\"/>"));
+        }
+        MathNode::Prime => out.push_str("<mo>′</mo>"),
+        MathNode::Row(nodes) => {
+            out.push_str("<mrow>");
+            for child in nodes {
+                write_mathml(child, out, variant);
+            }
+            out.push_str("</mrow>");
+        }
+        MathNode::Frac(num, den) => {
+            out.push_str("<mfrac>");
+            write_mathml_row(num, out, variant);
+            write_mathml_row(den, out, variant);
+            out.push_str("</mfrac>");
+        }
+        MathNode::Binom(top, bottom) => {
+            out.push_str("<mrow><mo>(</mo><mfrac linethickness=\"0\">");
+            write_mathml_row(top, out, variant);
+            write_mathml_row(bottom, out, variant);
+            out.push_str("</mfrac><mo>)</mo></mrow>");
+        }
+        MathNode::Sqrt(index, body) => match index {
+            Some(index) => {
+                out.push_str("<mroot>");
+                write_mathml_row(body, out, variant);
+                write_mathml_row(index, out, variant);
+                out.push_str("</mroot>");
+            }
+            None => {
+                out.push_str("<msqrt>");
+                write_mathml(body, out, variant);
+                out.push_str("</msqrt>");
+            }
+        },
+        MathNode::Scripts { base, sub, sup } => {
+            let limits = matches!(
+                base.as_ref(),
+                MathNode::LargeOp { limits: true, .. } | MathNode::Function { limits: true, .. }
+            );
+            let tag = match (sub.is_some(), sup.is_some(), limits) {
+                (true, true, true) => "munderover",
+                (true, false, true) => "munder",
+                (false, true, true) => "mover",
+                (true, true, false) => "msubsup",
+                (true, false, false) => "msub",
+                _ => "msup",
+            };
+            out.push_str(&format!("<{tag}>"));
+            write_mathml_row(base, out, variant);
+            if let Some(sub) = sub {
+                write_mathml_row(sub, out, variant);
+            }
+            if let Some(sup) = sup {
+                write_mathml_row(sup, out, variant);
+            }
+            out.push_str(&format!("</{tag}>"));
+        }
+        MathNode::Fenced { open, close, body } => {
+            out.push_str("<mrow>");
+            if !open.is_empty() {
+                out.push_str(&format!("<mo fence=\"true\">{}</mo>", escape_xml(open)));
+            }
+            write_mathml(body, out, variant);
+            if !close.is_empty() {
+                out.push_str(&format!("<mo fence=\"true\">{}</mo>", escape_xml(close)));
+            }
+            out.push_str("</mrow>");
+        }
+        MathNode::Accent { kind, body } => {
+            let (tag, mark) = match kind {
+                Accent::Hat => ("mover", "^"),
+                Accent::Bar => ("mover", "¯"),
+                Accent::Vec => ("mover", "→"),
+                Accent::Dot => ("mover", "˙"),
+                Accent::DoubleDot => ("mover", "¨"),
+                Accent::Tilde => ("mover", "~"),
+                Accent::Overline => ("mover", "‾"),
+                Accent::Underline => ("munder", "_"),
+            };
+            let attr = if tag == "mover" {
+                "accent"
+            } else {
+                "accentunder"
+            };
+            out.push_str(&format!("<{tag} {attr}=\"true\">"));
+            write_mathml_row(body, out, variant);
+            out.push_str(&format!("<mo stretchy=\"true\">{mark}</mo></{tag}>"));
+        }
+        MathNode::Styled { variant, body } => write_mathml(body, out, Some(*variant)),
+        MathNode::Table { env, rows } => {
+            let (open, close) = table_delimiters(*env);
+            let align = match env {
+                TableEnv::Cases => " columnalign=\"left left\"",
+                TableEnv::Aligned => " columnalign=\"right left right left\"",
+                _ => "",
+            };
+            out.push_str("<mrow>");
+            if !open.is_empty() {
+                out.push_str(&format!("<mo>{}</mo>", escape_xml(open)));
+            }
+            out.push_str(&format!("<mtable{align}>"));
+            for row in rows {
+                out.push_str("<mtr>");
+                for cell in row {
+                    out.push_str("<mtd>");
+                    write_mathml(cell, out, variant);
+                    out.push_str("</mtd>");
+                }
+                out.push_str("</mtr>");
+            }
+            out.push_str("</mtable>");
+            if !close.is_empty() {
+                out.push_str(&format!("<mo>{}</mo>", escape_xml(close)));
+            }
+            out.push_str("</mrow>");
+        }
+    }
+}
+
+/// MathML layout elements expect exactly one child per slot, so wrap in `<mrow>`.
+fn write_mathml_row(node: &MathNode, out: &mut String, variant: Option<Variant>) {
+    if matches!(node, MathNode::Row(_)) {
+        write_mathml(node, out, variant);
+    } else {
+        out.push_str("<mrow>");
+        write_mathml(node, out, variant);
+        out.push_str("</mrow>");
+    }
+}
+
+fn mathml_variant(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Bold => "bold",
+        Variant::Italic => "italic",
+        Variant::Upright => "normal",
+        Variant::DoubleStruck => "double-struck",
+        Variant::Calligraphic => "script",
+        Variant::Fraktur => "fraktur",
+        Variant::SansSerif => "sans-serif",
+        Variant::Monospace => "monospace",
+    }
+}
+
+fn table_delimiters(env: TableEnv) -> (&'static str, &'static str) {
+    match env {
+        TableEnv::PMatrix => ("(", ")"),
+        TableEnv::BMatrix => ("[", "]"),
+        TableEnv::BraceMatrix => ("{", "}"),
+        TableEnv::VMatrix => ("|", "|"),
+        TableEnv::DoubleVMatrix => ("‖", "‖"),
+        TableEnv::Cases => ("{", ""),
+        TableEnv::Matrix | TableEnv::Aligned => ("", ""),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ===== Typst output =====
+
+fn write_typst(node: &MathNode, out: &mut String) {
+    match node {
+        MathNode::Ident(s) | MathNode::Number(s) | MathNode::Operator(s) => {
+            out.push_str(&escape_typst_math(s));
+        }
+        MathNode::LargeOp { symbol, .. } => out.push_str(symbol),
+        MathNode::Function { name, limits } => {
+            if *limits {
+                out.push_str(&format!("op({}, limits: #true)", typst_string(name)));
+            } else {
+                out.push_str(&format!("op({})", typst_string(name)));
+            }
+        }
+        MathNode::Text(s) => out.push_str(&typst_string(s)),
+        MathNode::Space(space) => out.push_str(match space {
+            Space::Thin => "thin",
+            Space::Medium => "med",
+            Space::Thick => "thick",
+            Space::Quad => "quad",
+            Space::Wide => "wide",
+        }),
+        MathNode::Prime => out.push_str("prime"),
+        MathNode::Row(nodes) => {
+            if nodes.is_empty() {
+                out.push_str("\"\"");
+            }
+            for (i, child) in nodes.iter().enumerate() {
+                // Separate atoms so adjacent letters don't form multi-letter
+                // identifiers and operators don't merge into shorthands.
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_typst(child, out);
+            }
+        }
+        MathNode::Frac(num, den) => {
+            out.push_str("frac(");
+            write_typst(num, out);
+            out.push_str(", ");
+            write_typst(den, out);
+            out.push(')');
+        }
+        MathNode::Binom(top, bottom) => {
+            out.push_str("binom(");
+            write_typst(top, out);
+            out.push_str(", ");
+            write_typst(bottom, out);
+            out.push(')');
+        }
+        MathNode::Sqrt(index, body) => match index {
+            Some(index) => {
+                out.push_str("root(");
+                write_typst(index, out);
+                out.push_str(", ");
+                write_typst(body, out);
+                out.push(')');
+            }
+            None => {
+                out.push_str("sqrt(");
+                write_typst(body, out);
+                out.push(')');
+            }
+        },
+        MathNode::Scripts { base, sub, sup } => {
+            if matches!(base.as_ref(), MathNode::Row(nodes) if nodes.len() > 1) {
+                out.push_str("attach(");
+                write_typst(base, out);
+                if let Some(sub) = sub {
+                    out.push_str(", b: ");
+                    write_typst(sub, out);
+                }
+                if let Some(sup) = sup {
+                    out.push_str(", t: ");
+                    write_typst(sup, out);
+                }
+                out.push(')');
+            } else {
+                write_typst(base, out);
+                // Typst spells primes as `f'`, so pull them out of the superscript.
+                let (primes, sup) = split_primes(sup.as_deref());
+                out.push_str(&"'".repeat(primes));
+                if let Some(sub) = sub {
+                    out.push_str("_(");
+                    write_typst(sub, out);
+                    out.push(')');
+                }
+                if let Some(sup) = sup {
+                    out.push_str("^(");
+                    write_typst(&sup, out);
+                    out.push(')');
+                }
+            }
+        }
+        MathNode::Fenced { open, close, body } => {
+            // Only a matching pair can stay unescaped without unbalancing `lr(...)`.
+            let paired = matches!((open.as_str(), close.as_str()), ("(", ")") | ("[", "]"));
+            let delimiter = |d: &str| {
+                if paired {
+                    d.to_string()
+                } else {
+                    escape_typst_math(d)
+                }
+            };
+            out.push_str("lr(");
+            out.push_str(&delimiter(open));
+            out.push(' ');
+            write_typst(body, out);
+            out.push(' ');
+            out.push_str(&delimiter(close));
+            out.push(')');
+        }
+        MathNode::Accent { kind, body } => {
+            let func = match kind {
+                Accent::Hat => "hat",
+                Accent::Bar => "macron",
+                Accent::Vec => "arrow",
+                Accent::Dot => "dot",
+                Accent::DoubleDot => "dot.double",
+                Accent::Tilde => "tilde",
+                Accent::Overline => "overline",
+                Accent::Underline => "underline",
+            };
+            out.push_str(func);
+            out.push('(');
+            write_typst(body, out);
+            out.push(')');
+        }
+        MathNode::Styled { variant, body } => {
+            let func = match variant {
+                Variant::Bold => "bold",
+                Variant::Italic => "italic",
+                Variant::Upright => "upright",
+                Variant::DoubleStruck => "bb",
+                Variant::Calligraphic => "cal",
+                Variant::Fraktur => "frak",
+                Variant::SansSerif => "sans",
+                Variant::Monospace => "mono",
+            };
+            out.push_str(func);
+            out.push('(');
+            write_typst(body, out);
+            out.push(')');
+        }
+        MathNode::Table { env, rows } => write_typst_table(*env, rows, out),
+    }
+}
+
+fn write_typst_table(env: TableEnv, rows: &[Vec<MathNode>], out: &mut String) {
+    let render_cells = |row: &Vec<MathNode>, sep: &str| -> String {
+        row.iter()
+            .map(|cell| {
+                let mut s = String::new();
+                write_typst(cell, &mut s);
+                s
+            })
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+
+    match env {
+        TableEnv::Aligned => {
+            let lines: Vec<String> = rows.iter().map(|row| render_cells(row, " & ")).collect();
+            out.push_str(&lines.join(" \\ "));
+        }
+        TableEnv::Cases => {
+            let lines: Vec<String> = rows.iter().map(|row| render_cells(row, " & ")).collect();
+            out.push_str(&format!("cases({})", lines.join(", ")));
+        }
+        _ => {
+            let delim = match env {
+                TableEnv::PMatrix => "\"(\"",
+                TableEnv::BMatrix => "\"[\"",
+                TableEnv::BraceMatrix => "\"{\"",
+                TableEnv::VMatrix => "\"|\"",
+                TableEnv::DoubleVMatrix => "\"‖\"",
+                _ => "#none",
+            };
+            let lines: Vec<String> = rows.iter().map(|row| render_cells(row, ", ")).collect();
+            out.push_str(&format!("mat(delim: {delim}, {})", lines.join("; ")));
+        }
+    }
+}
+
+/// Escape characters with special meaning in Typst math mode.
+fn escape_typst_math(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' | '$' | '#' | '_' | '^' | '&' | '/' | '"' | '\'' | ',' | ';' | '(' | ')' | '['
+            | ']' | '{' | '}' | '@' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Split leading primes off a superscript, returning the count and what remains.
+fn split_primes(sup: Option<&MathNode>) -> (usize, Option<MathNode>) {
+    match sup {
+        Some(MathNode::Prime) => (1, None),
+        Some(MathNode::Row(nodes)) => {
+            let primes = nodes
+                .iter()
+                .take_while(|n| matches!(n, MathNode::Prime))
+                .count();
+            let rest = &nodes[primes..];
+            if primes == 0 {
+                (0, sup.cloned())
+            } else if rest.is_empty() {
+                (primes, None)
+            } else {
+                (primes, Some(row_node(rest.to_vec())))
+            }
+        }
+        other => (0, other.cloned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mathml(latex: &str) -> String {
+        latex_to_mathml(latex, false)
+    }
+
+    #[test]
+    fn fractions() {
+        let out = mathml(r"\frac{a+1}{b}");
+        assert!(out.contains(
+            "<mfrac><mrow><mi>a</mi><mo>+</mo><mn>1</mn></mrow><mrow><mi>b</mi></mrow></mfrac>"
+        ));
+        assert_eq!(latex_to_typst(r"\frac{1}{2}", false), "$frac(1, 2)$");
+    }
+
+    #[test]
+    fn scripts() {
+        assert!(mathml("x_i^2").contains("<msubsup><mrow><mi>x</mi></mrow><mrow><mi>i</mi></mrow><mrow><mn>2</mn></mrow></msubsup>"));
+        assert!(mathml(r"\sum_{k=0}^n k").contains("<munderover>"));
+        assert_eq!(latex_to_typst("f'(x)", false), "$f' \\( x \\)$");
+        // Only the first digit is raised, as in TeX
+        assert_eq!(latex_to_typst("x^12", false), "$x^(1) 2$");
+    }
+
+    #[test]
+    fn left_right() {
+        let out = mathml(r"\left( \frac{a}{b} \right)");
+        assert!(out.contains("<mo fence=\"true\">(</mo><mfrac>"));
+        assert!(out.contains("</mfrac><mo fence=\"true\">)</mo>"));
+        assert_eq!(latex_to_typst(r"\left\{ x \right.", false), "$lr(\\{ x )$");
+    }
+
+    #[test]
+    fn environments() {
+        let out = mathml(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}");
+        assert!(
+            out.contains("<mo>(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>")
+        );
+        assert_eq!(
+            latex_to_typst(
+                r"\begin{cases} 1 & x > 0 \\ 0 & \text{otherwise} \end{cases}",
+                false
+            ),
+            "$cases(1 & x > 0, 0 & \"otherwise\")$"
+        );
+    }
+
+    #[test]
+    fn malformed_input_shows_source() {
+        for latex in [
+            r"\frac{a}",
+            "{x",
+            "x}",
+            r"\left( x",
+            r"\begin{foo} x \end{foo}",
+            r"\begin{matrix} a \end{cases}",
+            r"\nosuchcommand",
+        ] {
+            let out = mathml(latex);
+            assert!(
+                out.starts_with("<code class=\"math-error\">"),
+                "{latex}: {out}"
+            );
+        }
+        assert_eq!(latex_to_typst(r"\frac{a}", false), r"`\frac{a}`");
+        assert_eq!(latex_to_typst(r"\frac{`}", false), r"``` \frac{`} ```");
+        assert_eq!(
+            latex_to_typst(r"\frac{```}", false),
+            r"```` \frac{```} ````"
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let braces = format!("{}x{}", "{".repeat(5000), "}".repeat(5000));
+        assert!(mathml(&braces).starts_with("<code class=\"math-error\">"));
+        let fracs = format!("{}x", r"\hat".repeat(5000));
+        assert!(mathml(&fracs).starts_with("<code class=\"math-error\">"));
+        let roots = format!("{}x", r"\sqrt[".repeat(5000));
+        assert!(mathml(&roots).starts_with("<code class=\"math-error\">"));
+        // Reasonable nesting still renders
+        let nested = format!("{}x{}", "{".repeat(20), "}".repeat(20));
+        assert!(mathml(&nested).starts_with("<math"));
+    }
+}
@@ -0,0 +1,170 @@
+use crate::include::{expand_includes, IncludeScope};
+use crate::markdown::escape_html;
+use crate::render_options::RenderOptions;
+use comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+use comrak::{parse_document, Anchorizer, Arena};
+use serde::Serialize;
+
+/// One heading in a document's outline, with the headings nested under it.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineEntry {
+    pub level: u8,
+    pub text: String,
+    /// The `id` that `render_markdown` gives this heading.
+    pub anchor: String,
+    pub line: usize,
+    pub children: Vec<OutlineEntry>,
+}
+
+/// Build the heading tree for a markdown document, including the headings
+/// of files it includes when an include scope is given.
+pub fn document_outline(
+    markdown: &str,
+    render_options: &RenderOptions,
+    includes: Option<&IncludeScope>,
+) -> Vec<OutlineEntry> {
+    let input = render_options.prepare(markdown);
+    let options = render_options.comrak_options();
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &options);
+    if let Some(scope) = includes {
+        expand_includes(&arena, root, &input, scope, render_options);
+    }
+    nest(collect_headings(root))
+}
+
+/// The ids `render_markdown` gives the document's headings.
+pub fn heading_anchors<'a>(root: &'a AstNode<'a>) -> Vec<String> {
+    collect_headings(root)
+        .into_iter()
+        .map(|heading| heading.anchor)
+        .collect()
+}
+
+/// Whether `node` is a paragraph containing only the `[TOC]` marker.
+pub fn is_toc_marker<'a>(node: &'a AstNode<'a>) -> bool {
+    if !matches!(node.data.borrow().value, NodeValue::Paragraph) {
+        return false;
+    }
+    let mut text = String::new();
+    for child in node.children() {
+        match &child.data.borrow().value {
+            NodeValue::Text(t) => text.push_str(t),
+            _ => return false,
+        }
+    }
+    text.trim().eq_ignore_ascii_case("[toc]")
+}
+
+/// Replace `[TOC]` paragraphs, at any depth as the PDF exporter does, with a
+/// table of contents. Entries link to their headings only when headings get
+/// ids (`linked`).
+pub fn expand_toc<'a>(root: &'a AstNode<'a>, linked: bool) {
+    let markers: Vec<_> = root.descendants().filter(|n| is_toc_marker(n)).collect();
+    if markers.is_empty() {
+        return;
+    }
+
+    let outline = nest(collect_headings(root));
+    for marker in markers {
+        let mut ast = marker.data.borrow_mut();
+        let literal = format!(
+            "<nav class=\"toc\" data-sourcepos=\"{}\">\n{}</nav>\n",
+            ast.sourcepos,
+            toc_list(&outline, linked)
+        );
+        ast.value = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 0,
+            literal,
+        });
+    }
+}
+
+/// Collect headings in render order, anchoring them the way comrak's
+/// `header_ids` does so duplicate titles get the same `-1`, `-2` suffixes.
+fn collect_headings<'a>(root: &'a AstNode<'a>) -> Vec<OutlineEntry> {
+    let mut anchorizer = Anchorizer::new();
+    let mut headings = Vec::new();
+    for node in root.descendants() {
+        let ast = node.data.borrow();
+        if let NodeValue::Heading(heading) = &ast.value {
+            let mut text = String::new();
+            let mut id_text = String::new();
+            collect_text(node, &mut text, &mut id_text);
+            headings.push(OutlineEntry {
+                level: heading.level,
+                text: text.trim().to_string(),
+                anchor: anchorizer.anchorize(id_text),
+                line: ast.sourcepos.start.line,
+                children: Vec::new(),
+            });
+        }
+    }
+    headings
+}
+
+/// Gather a heading's display text and the text comrak derives its id from.
+/// Math is already raw HTML by the time the renderer anchors headings, so it
+/// only contributes to the display text.
+fn collect_text<'a>(node: &'a AstNode<'a>, text: &mut String, id_text: &mut String) {
+    match &node.data.borrow().value {
+        NodeValue::Text(literal) => {
+            text.push_str(literal);
+            id_text.push_str(literal);
+        }
+        NodeValue::Code(code) => {
+            text.push_str(&code.literal);
+            id_text.push_str(&code.literal);
+        }
+        NodeValue::LineBreak | NodeValue::SoftBreak => {
+            text.push(' ');
+            id_text.push(' ');
+        }
+        NodeValue::Math(math) => text.push_str(&math.literal),
+        _ => {
+            for child in node.children() {
+                collect_text(child, text, id_text);
+            }
+        }
+    }
+}
+
+/// Nest a flat heading list so deeper headings become children of the
+/// nearest shallower heading before them.
+fn nest(headings: Vec<OutlineEntry>) -> Vec<OutlineEntry> {
+    let mut roots = Vec::new();
+    for heading in headings {
+        insert(&mut roots, heading);
+    }
+    roots
+}
+
+fn insert(siblings: &mut Vec<OutlineEntry>, entry: OutlineEntry) {
+    match siblings.last_mut() {
+        Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+        _ => siblings.push(entry),
+    }
+}
+
+fn toc_list(entries: &[OutlineEntry], linked: bool) -> String {
+    let mut html = String::from("<ul>\n");
+    for entry in entries {
+        if linked {
+            html.push_str(&format!(
+                "<li><a href=\"#{}\">{}</a>",
+                escape_html(&entry.anchor),
+                escape_html(&entry.text)
+            ));
+        } else {
+            html.push_str(&format!("<li>{}", escape_html(&entry.text)));
+        }
+        if !entry.children.is_empty() {
+            html.push('\n');
+            html.push_str(&toc_list(&entry.children, linked));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+    html
+}
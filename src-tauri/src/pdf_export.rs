@@ -1,10 +1,13 @@
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, OnceLock};
 use typst::layout::PagedDocument;
 use typst_as_lib::typst_kit_options::TypstKitFontOptions;
 use typst_as_lib::TypstEngine;
 
-use crate::typst_convert::markdown_to_typst;
+use crate::front_matter::extract_metadata;
+use crate::highlight::{palette, TokenClass};
+use crate::typst_convert::metadata_to_typst;
 
 const TYPST_PREAMBLE: &str = r##"
 #set page(paper: "a4", margin: 2.5cm, numbering: "1", number-align: center)
@@ -69,11 +72,49 @@ const TYPST_PREAMBLE: &str = r##"
   body,
 )
 
+#let callout-colors = (
+  note: rgb("#0969da"),
+  tip: rgb("#1a7f37"),
+  important: rgb("#8250df"),
+  warning: rgb("#9a6700"),
+  caution: rgb("#d1242f"),
+)
+
+#let callout(kind, title, body) = {
+  let color = callout-colors.at(kind)
+  block(
+    width: 100%,
+    inset: (left: 12pt, y: 6pt, right: 6pt),
+    stroke: (left: 3pt + color),
+    fill: color.lighten(92%),
+    [#text(fill: color, weight: "bold", title) \ #body],
+  )
+}
+
 #let task(checked, body) = {
   let marker = if checked { sym.ballot.check } else { sym.ballot }
   [#marker #body]
 }
 
+#let codeblock(lines) = block(
+  fill: luma(245),
+  stroke: 0.5pt + luma(210),
+  inset: 10pt,
+  radius: 4pt,
+  width: 100%,
+  {
+    set text(font: "DejaVu Sans Mono", size: 0.8em)
+    set par(justify: false)
+    for (i, line) in lines.enumerate() {
+      if i > 0 { linebreak() }
+      for (tok, cls) in line {
+        let tok = tok.replace("\t", "    ").replace(" ", "\u{a0}")
+        text(fill: code-colors.at(cls, default: code-fg), tok)
+      }
+    }
+  },
+)
+
 #let hrule() = {
   v(0.5em)
   line(length: 100%, stroke: 0.5pt + luma(180))
@@ -82,13 +123,49 @@ const TYPST_PREAMBLE: &str = r##"
 
 "##;
 
-pub fn export_svg_to_pdf(svg_content: &str, output_path: &Path, landscape: bool) -> Result<(), String> {
-    // Rasterize SVG to PNG using resvg (properly renders SVG text with system fonts)
+/// Typst definitions for the token colours used by `#codeblock`.
+/// PDFs are printed on white, so always use the theme's light palette.
+fn code_colors_typst(theme: &str) -> String {
+    let palette = palette(theme, false);
+    let colors: Vec<String> = TokenClass::ALL
+        .iter()
+        .map(|class| {
+            format!(
+                "\"{}\": rgb(\"{}\")",
+                class.css_class(),
+                palette.color(*class)
+            )
+        })
+        .collect();
+    format!(
+        "#let code-fg = rgb(\"{}\")\n#let code-colors = ({})\n",
+        palette.foreground,
+        colors.join(", ")
+    )
+}
+
+/// System fonts for laying out SVG text, loaded once.
+fn system_fonts() -> Arc<fontdb::Database> {
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+/// Parse SVG with system fonts available for its text.
+pub(crate) fn parse_svg(svg_content: &str) -> Result<usvg::Tree, String> {
     let mut options = usvg::Options::default();
-    options.fontdb_mut().load_system_fonts();
+    options.fontdb = system_fonts();
+    usvg::Tree::from_str(svg_content, &options).map_err(|e| format!("Failed to parse SVG: {e}"))
+}
 
-    let tree = usvg::Tree::from_str(svg_content, &options)
-        .map_err(|e| format!("Failed to parse SVG: {e}"))?;
+pub fn export_svg_to_pdf(svg_content: &str, output_path: &Path, landscape: bool) -> Result<(), String> {
+    // Rasterize SVG to PNG using resvg (properly renders SVG text with system fonts)
+    let tree = parse_svg(svg_content)?;
 
     let size = tree.size();
     let scale = 3.0_f32; // 3x for high-quality rasterization
@@ -144,15 +221,24 @@ pub fn export_svg_to_pdf(svg_content: &str, output_path: &Path, landscape: bool)
     Ok(())
 }
 
-pub fn export_pdf(markdown: &str, source_path: &Path, output_path: &Path, font_size: f32) -> Result<(), String> {
-    let base_dir = source_path.parent().unwrap_or(Path::new("/"));
-
-    // Convert markdown to Typst markup
-    let typst_body = markdown_to_typst(markdown, base_dir);
+/// Typeset a document into a PDF. `typst_body` is `markdown` converted with
+/// `markdown_to_typst`.
+pub fn export_pdf(
+    markdown: &str,
+    typst_body: &str,
+    output_path: &Path,
+    font_size: f32,
+    theme: &str,
+) -> Result<(), String> {
+    // Front matter drives the PDF metadata and title page
+    let title_page = extract_metadata(markdown)
+        .map(|meta| metadata_to_typst(&meta))
+        .unwrap_or_default();
 
     // Prepend preamble with user's font size
     let preamble = TYPST_PREAMBLE.replace("__FONT_SIZE__", &format!("{font_size}"));
-    let full_source = format!("{preamble}{typst_body}");
+    let code_colors = code_colors_typst(theme);
+    let full_source = format!("{code_colors}{preamble}{title_page}{typst_body}");
 
     // Build Typst engine with embedded fonts
     let engine = TypstEngine::builder()
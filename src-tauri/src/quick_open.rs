@@ -0,0 +1,218 @@
+use crate::folder_tree::folder_files;
+use crate::front_matter::extract_metadata;
+use crate::state::AppState;
+use crate::watcher::TreeDelta;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Results returned by one `quick_open` call.
+const QUICK_OPEN_LIMIT: usize = 50;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickOpenHit {
+    pub file_path: String,
+    /// Path relative to the folder.
+    pub relative_path: String,
+    /// Front-matter title, if the file has one.
+    pub title: Option<String>,
+    pub score: i64,
+    /// Character indexes of matched characters in `relative_path`.
+    pub path_matches: Vec<usize>,
+    /// Character indexes of matched characters in `title`.
+    pub title_matches: Vec<usize>,
+}
+
+/// Front-matter titles by file. The folder watcher drops the titles of
+/// files that change, and they are read again on the next quick open.
+#[derive(Default)]
+pub struct TitleCache {
+    titles: HashMap<PathBuf, Option<String>>,
+}
+
+impl TitleCache {
+    /// Files in `files` whose title hasn't been read yet.
+    fn missing(&self, files: &[PathBuf]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .filter(|path| !self.titles.contains_key(*path))
+            .cloned()
+            .collect()
+    }
+
+    /// Forget files that are no longer in the folder.
+    fn retain(&mut self, files: &[PathBuf]) {
+        if self.titles.len() > files.len() {
+            let present: HashSet<&PathBuf> = files.iter().collect();
+            self.titles.retain(|path, _| present.contains(path));
+        }
+    }
+
+    /// Follow a batch of folder changes: renamed files keep their title, and
+    /// files that were removed, replaced or edited are forgotten.
+    pub fn apply(&mut self, delta: &TreeDelta, edited: &[PathBuf]) {
+        for path in &delta.removed {
+            self.titles.retain(|file, _| !file.starts_with(path));
+        }
+        for renamed in &delta.renamed {
+            if let Some(title) = self.titles.remove(Path::new(&renamed.from)) {
+                self.titles.insert(PathBuf::from(&renamed.to), title);
+            }
+        }
+        for path in &delta.added {
+            self.titles.remove(Path::new(path));
+        }
+        for path in edited {
+            self.titles.remove(path);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.titles.clear();
+    }
+}
+
+fn read_title(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| extract_metadata(&content))
+        .and_then(|meta| meta.title)
+}
+
+/// Fuzzy-match `query` against the relative path and front-matter title of
+/// every markdown file in the current folder, best matches first. Uses the
+/// folder scan's file list, or what it has found so far, rather than walking
+/// the folder.
+#[tauri::command(async)]
+pub fn quick_open(query: String, state: State<'_, AppState>) -> Result<Vec<QuickOpenHit>, String> {
+    let Some(root) = state.current_folder.lock().unwrap().clone() else {
+        return Err("No folder is open".to_string());
+    };
+    let files = folder_files(&root, &state);
+    let query: String = query.split_whitespace().collect();
+    let missing = {
+        let mut titles = state.title_cache.lock().unwrap();
+        titles.retain(&files);
+        titles.missing(&files)
+    };
+    // Files are read without holding the cache
+    let read: Vec<(PathBuf, Option<String>)> = missing
+        .into_iter()
+        .map(|path| {
+            let title = read_title(&path);
+            (path, title)
+        })
+        .collect();
+    let titles: Vec<Option<String>> = {
+        let mut cache = state.title_cache.lock().unwrap();
+        cache.titles.extend(read);
+        files
+            .iter()
+            .map(|path| cache.titles.get(path).cloned().flatten())
+            .collect()
+    };
+
+    let matcher = SkimMatcherV2::default().smart_case();
+    let mut hits = Vec::new();
+    for (path, title) in files.iter().zip(titles) {
+        let relative = path
+            .strip_prefix(&root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .to_string();
+        if query.is_empty() {
+            hits.push(QuickOpenHit {
+                file_path: path.to_string_lossy().to_string(),
+                relative_path: relative,
+                title,
+                score: 0,
+                path_matches: vec![],
+                title_matches: vec![],
+            });
+            continue;
+        }
+        let by_path = matcher.fuzzy_indices(&relative, &query);
+        let by_title = title
+            .as_deref()
+            .and_then(|title| matcher.fuzzy_indices(title, &query));
+        let score = match (&by_path, &by_title) {
+            (Some((a, _)), Some((b, _))) => *a.max(b),
+            (Some((score, _)), None) | (None, Some((score, _))) => *score,
+            (None, None) => continue,
+        };
+        hits.push(QuickOpenHit {
+            file_path: path.to_string_lossy().to_string(),
+            relative_path: relative,
+            title,
+            score,
+            path_matches: by_path.map(|(_, indexes)| indexes).unwrap_or_default(),
+            title_matches: by_title.map(|(_, indexes)| indexes).unwrap_or_default(),
+        });
+    }
+    // Shorter paths first among equal scores, so `notes.md` beats `a/b/notes.md`
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.relative_path.len().cmp(&b.relative_path.len()))
+            .then_with(|| a.relative_path.cmp(&b.relative_path))
+    });
+    hits.truncate(QUICK_OPEN_LIMIT);
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::watcher::RenamedPath;
+
+    fn cache(entries: &[(&str, Option<&str>)]) -> TitleCache {
+        TitleCache {
+            titles: entries
+                .iter()
+                .map(|(path, title)| (PathBuf::from(path), title.map(str::to_string)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn missing_lists_unread_files() {
+        let titles = cache(&[("/n/a.md", Some("A")), ("/n/b.md", None)]);
+        let files = vec![
+            PathBuf::from("/n/a.md"),
+            PathBuf::from("/n/b.md"),
+            PathBuf::from("/n/c.md"),
+        ];
+        assert_eq!(titles.missing(&files), vec![PathBuf::from("/n/c.md")]);
+    }
+
+    #[test]
+    fn apply_follows_folder_changes() {
+        let mut titles = cache(&[
+            ("/n/a.md", Some("A")),
+            ("/n/b.md", Some("B")),
+            ("/n/old/c.md", Some("C")),
+            ("/n/d.md", Some("D")),
+        ]);
+        let delta = TreeDelta {
+            removed: vec!["/n/old".to_string()],
+            renamed: vec![RenamedPath {
+                from: "/n/a.md".to_string(),
+                to: "/n/z.md".to_string(),
+            }],
+            added: vec!["/n/d.md".to_string()],
+            ..Default::default()
+        };
+        titles.apply(&delta, &[PathBuf::from("/n/b.md")]);
+        let mut left: Vec<_> = titles.titles.into_iter().collect();
+        left.sort();
+        assert_eq!(
+            left,
+            vec![(PathBuf::from("/n/z.md"), Some("A".to_string()))]
+        );
+    }
+}
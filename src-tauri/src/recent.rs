@@ -1,6 +1,7 @@
 use crate::state::AppState;
 use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::Path;
 use tauri::menu::{AboutMetadata, MenuBuilder, MenuItemBuilder, SubmenuBuilder};
 use tauri::{AppHandle, Manager, Runtime, State};
 
@@ -44,6 +45,64 @@ fn add_to_list(list: &mut Vec<String>, path: String) {
     list.truncate(MAX_RECENTS);
 }
 
+/// Recent entries dropped along with a trashed path, and where they were in
+/// their lists, so undoing the trash can put them back.
+#[derive(Default)]
+pub struct RemovedRecents {
+    files: Vec<(usize, String)>,
+    folders: Vec<(usize, String)>,
+}
+
+/// Drop entries at `path` or inside it, returning them with their positions.
+fn take_under(list: &mut Vec<String>, path: &Path) -> Vec<(usize, String)> {
+    let mut removed = Vec::new();
+    for (index, item) in std::mem::take(list).into_iter().enumerate() {
+        if Path::new(&item).starts_with(path) {
+            removed.push((index, item));
+        } else {
+            list.push(item);
+        }
+    }
+    removed
+}
+
+/// Reinsert entries returned by `take_under` where they were, skipping any
+/// that were added again since. Returns whether anything changed.
+fn put_back(list: &mut Vec<String>, removed: &[(usize, String)]) -> bool {
+    let mut changed = false;
+    for (index, item) in removed {
+        if list.contains(item) {
+            continue;
+        }
+        list.insert((*index).min(list.len()), item.clone());
+        changed = true;
+    }
+    list.truncate(MAX_RECENTS);
+    changed
+}
+
+/// Point entries at `from`, or inside it, at the same place under `to`, or
+/// drop them when `to` is `None`. Returns whether anything changed.
+fn retarget(list: &mut Vec<String>, from: &Path, to: Option<&Path>) -> bool {
+    let before = list.clone();
+    *list = before
+        .iter()
+        .filter_map(|item| {
+            let Ok(rest) = Path::new(item).strip_prefix(from) else {
+                return Some(item.clone());
+            };
+            let to = to?;
+            let path = if rest.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(rest)
+            };
+            Some(path.to_string_lossy().to_string())
+        })
+        .collect();
+    *list != before
+}
+
 fn abbreviate_home(path: &str) -> String {
     if let Ok(home) = std::env::var("HOME") {
         if path.starts_with(&home) {
@@ -247,3 +306,101 @@ pub fn clear_recents(
     app_handle.set_menu(menu).map_err(|e| e.to_string())?;
     Ok(())
 }
+
+/// Keep recents in step with a file or folder that was renamed or moved to
+/// `to`, or trashed when `to` is `None`, and rebuild the Open Recent menu.
+pub fn update_recent_paths(
+    app_handle: &AppHandle,
+    state: &AppState,
+    from: &Path,
+    to: Option<&Path>,
+) -> Result<(), String> {
+    let mut data = load_recents(state);
+    let files_changed = retarget(&mut data.recent_files, from, to);
+    let folders_changed = retarget(&mut data.recent_folders, from, to);
+    if !files_changed && !folders_changed {
+        return Ok(());
+    }
+    set_recents(app_handle, state, &data)
+}
+
+/// Drop recent entries at `path` or inside it, which has gone to the trash.
+pub fn remove_recent_paths(
+    app_handle: &AppHandle,
+    state: &AppState,
+    path: &Path,
+) -> RemovedRecents {
+    let mut data = load_recents(state);
+    let removed = RemovedRecents {
+        files: take_under(&mut data.recent_files, path),
+        folders: take_under(&mut data.recent_folders, path),
+    };
+    if removed.files.is_empty() && removed.folders.is_empty() {
+        return removed;
+    }
+    if let Err(e) = set_recents(app_handle, state, &data) {
+        eprintln!("Warning: Could not update recent items: {}", e);
+    }
+    removed
+}
+
+/// Put back recent entries dropped by `remove_recent_paths`.
+pub fn restore_recent_paths(
+    app_handle: &AppHandle,
+    state: &AppState,
+    removed: &RemovedRecents,
+) -> Result<(), String> {
+    let mut data = load_recents(state);
+    let files_changed = put_back(&mut data.recent_files, &removed.files);
+    let folders_changed = put_back(&mut data.recent_folders, &removed.folders);
+    if !files_changed && !folders_changed {
+        return Ok(());
+    }
+    set_recents(app_handle, state, &data)
+}
+
+/// Save the recent items and rebuild the menu that lists them.
+fn set_recents(app_handle: &AppHandle, state: &AppState, data: &RecentData) -> Result<(), String> {
+    save_recents(state, data);
+    let menu = build_app_menu(app_handle, data).map_err(|e| e.to_string())?;
+    app_handle.set_menu(menu).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn take_under_drops_the_path_and_its_contents() {
+        let mut recents = list(&["/a/notes.md", "/docs/x.md", "/b.md", "/docs"]);
+        let removed = take_under(&mut recents, Path::new("/docs"));
+        assert_eq!(recents, list(&["/a/notes.md", "/b.md"]));
+        assert_eq!(
+            removed,
+            vec![(1, "/docs/x.md".to_string()), (3, "/docs".to_string())]
+        );
+    }
+
+    #[test]
+    fn put_back_restores_the_old_order() {
+        let original = list(&["/docs/x.md", "/a.md", "/docs/y.md", "/b.md"]);
+        let mut recents = original.clone();
+        let removed = take_under(&mut recents, Path::new("/docs"));
+        assert!(put_back(&mut recents, &removed));
+        assert_eq!(recents, original);
+    }
+
+    #[test]
+    fn put_back_skips_entries_added_again() {
+        let mut recents = list(&["/a.md", "/b.md"]);
+        let removed = take_under(&mut recents, Path::new("/a.md"));
+        recents.insert(0, "/a.md".to_string());
+        assert!(!put_back(&mut recents, &removed));
+        assert_eq!(recents, list(&["/a.md", "/b.md"]));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::include::IncludeScope;
+use crate::links::{LinkResolver, ResolvedLink};
+use crate::markdown::{render_markdown, RenderedMarkdown};
+use crate::render_options::RenderOptions;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Rendered HTML kept in memory, in bytes.
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+/// Size of the on-disk cache, in bytes. Pruning goes down to three quarters
+/// of this so it doesn't run on every write.
+const DISK_LIMIT: u64 = 256 * 1024 * 1024;
+pub const RENDER_CACHE_DIR: &str = "render_cache";
+
+/// A rendered document and what it depended on besides its own text.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    html: String,
+    /// Included files with a hash of their contents at render time.
+    includes: Vec<(PathBuf, String)>,
+    links: Vec<ResolvedLink>,
+    #[serde(default)]
+    breaks: Vec<usize>,
+}
+
+impl Entry {
+    /// Whether included files and link targets are unchanged since rendering.
+    fn is_fresh(&self, links: Option<&LinkResolver>) -> bool {
+        let includes_fresh = self.includes.iter().all(|(path, hash)| {
+            fs::read(path)
+                .map(|bytes| hex_digest(&bytes) == *hash)
+                .unwrap_or(false)
+        });
+        includes_fresh
+            && links.map_or(true, |resolver| {
+                self.links.iter().all(|link| resolver.still_resolves(link))
+            })
+    }
+
+    fn rendered(&self) -> RenderedMarkdown {
+        RenderedMarkdown {
+            html: self.html.clone(),
+            includes: self.includes.iter().map(|(path, _)| path.clone()).collect(),
+            links: self.links.clone(),
+            breaks: self.breaks.clone(),
+            external_diagrams: false,
+        }
+    }
+}
+
+/// Rendered HTML keyed by a hash of the document text, its location, trust
+/// and render options. Recently used entries stay in memory; everything is
+/// also written to the app data directory so reopened files and revisions
+/// render instantly across restarts.
+#[derive(Default)]
+pub struct RenderCache {
+    entries: HashMap<String, Arc<Entry>>,
+    /// Keys from least to most recently used.
+    order: VecDeque<String>,
+    memory_bytes: usize,
+    dir: Option<PathBuf>,
+    /// Bytes on disk, counted on the first write.
+    disk_bytes: Option<u64>,
+}
+
+impl RenderCache {
+    pub fn set_dir(&mut self, dir: PathBuf) {
+        self.dir = Some(dir);
+        self.disk_bytes = None;
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<Entry>> {
+        let entry = self.entries.get(key)?.clone();
+        self.order.retain(|k| k != key);
+        self.order.push_back(key.to_string());
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: String, entry: Arc<Entry>) {
+        if let Some(old) = self.entries.insert(key.clone(), entry.clone()) {
+            self.memory_bytes -= old.html.len();
+            self.order.retain(|k| *k != key);
+        }
+        self.memory_bytes += entry.html.len();
+        self.order.push_back(key);
+        while self.memory_bytes > MEMORY_LIMIT {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.entries.remove(&oldest) {
+                self.memory_bytes -= old.html.len();
+            }
+        }
+    }
+
+    /// Account for a file written to the disk cache, pruning when over the limit.
+    fn added_to_disk(&mut self, dir: &Path, size: u64) {
+        let total = match self.disk_bytes {
+            Some(bytes) => bytes + size,
+            None => disk_usage(dir),
+        };
+        self.disk_bytes = Some(if total > DISK_LIMIT {
+            prune_disk(dir, DISK_LIMIT / 4 * 3)
+        } else {
+            total
+        });
+    }
+}
+
+/// `render_markdown` through the cache. Hits are checked against included
+/// files and link targets first, so a cached render is never stale. Renders
+/// with externally drawn diagrams aren't cached.
+pub fn render_cached(
+    state: &AppState,
+    input: &str,
+    render_options: &RenderOptions,
+    includes: Option<&IncludeScope>,
+    links: Option<&LinkResolver>,
+    sanitize: bool,
+) -> RenderedMarkdown {
+    let key = cache_key(input, render_options, includes, links, sanitize);
+    let (cached, dir) = {
+        let mut cache = state.render_cache.lock().unwrap();
+        (cache.get(&key), cache.dir.clone())
+    };
+    let cached = cached.or_else(|| read_disk(dir.as_deref()?, &key).map(Arc::new));
+    if let Some(entry) = cached.filter(|entry| entry.is_fresh(links)) {
+        let rendered = entry.rendered();
+        state.render_cache.lock().unwrap().insert(key, entry);
+        return rendered;
+    }
+
+    let rendered = render_markdown(input, render_options, includes, links, sanitize);
+    // External renderers may be installed, updated or fixed at any time; their
+    // diagrams are cached on their own, so the document is cheap to redo
+    if rendered.external_diagrams {
+        return rendered;
+    }
+    let entry = Entry {
+        includes: rendered
+            .includes
+            .iter()
+            .map(|path| {
+                let hash = fs::read(path).map(|b| hex_digest(&b)).unwrap_or_default();
+                (path.clone(), hash)
+            })
+            .collect(),
+        links: rendered.links,
+        html: rendered.html,
+        breaks: rendered.breaks,
+    };
+    if let Some(dir) = &dir {
+        match write_disk(dir, &key, &entry) {
+            Ok(size) => state.render_cache.lock().unwrap().added_to_disk(dir, size),
+            Err(e) => eprintln!("Warning: Could not cache rendered document: {}", e),
+        }
+    }
+    let rendered = entry.rendered();
+    let entry = Arc::new(entry);
+    state.render_cache.lock().unwrap().insert(key, entry);
+    rendered
+}
+
+fn cache_key(
+    input: &str,
+    render_options: &RenderOptions,
+    includes: Option<&IncludeScope>,
+    links: Option<&LinkResolver>,
+    sanitize: bool,
+) -> String {
+    let mut hasher = Sha256::new();
+    // Another version of the app may render the same text differently
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(serde_json::to_vec(render_options).unwrap_or_default());
+    let paths = [
+        includes.map(|scope| scope.file.as_path()),
+        includes.map(|scope| scope.root.as_path()),
+        links.map(LinkResolver::file_dir),
+        links.map(LinkResolver::root),
+    ];
+    for path in paths {
+        hasher.update([0u8]);
+        if let Some(path) = path {
+            hasher.update(path.to_string_lossy().as_bytes());
+        }
+    }
+    hasher.update([u8::from(sanitize)]);
+    hasher.update(input);
+    format!("{:x}", hasher.finalize())
+}
+
+pub(crate) fn hex_digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn read_disk(dir: &Path, key: &str) -> Option<Entry> {
+    let path = dir.join(format!("{}.json", key));
+    let entry = serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+    // Bump the modification time; pruning removes the oldest files first
+    if let Ok(file) = fs::File::options().write(true).open(&path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(entry)
+}
+
+fn write_disk(dir: &Path, key: &str, entry: &Entry) -> Result<u64, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    let json =
+        serde_json::to_vec(entry).map_err(|e| format!("Failed to serialize render: {}", e))?;
+    fs::write(dir.join(format!("{}.json", key)), &json)
+        .map_err(|e| format!("Failed to write render cache: {}", e))?;
+    Ok(json.len() as u64)
+}
+
+/// Cache files with their size and modification time.
+fn disk_entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            meta.is_file().then(|| (entry.path(), meta.len(), modified))
+        })
+        .collect()
+}
+
+fn disk_usage(dir: &Path) -> u64 {
+    disk_entries(dir).iter().map(|(_, size, _)| size).sum()
+}
+
+/// Remove the least recently used files until the cache fits in `target`
+/// bytes, returning the remaining size.
+fn prune_disk(dir: &Path, target: u64) -> u64 {
+    let mut entries = disk_entries(dir);
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    for (path, size, _) in entries {
+        if total <= target {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= size;
+        }
+    }
+    total
+}
@@ -0,0 +1,126 @@
+use crate::front_matter::strip_front_matter;
+use crate::markdown::preprocess_markdown;
+use crate::state::AppState;
+use comrak::Options;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tauri::State;
+
+const RENDER_OPTIONS_FILE: &str = "render_options.json";
+
+/// User-facing markdown settings shared by the preview, PDF export and
+/// read-aloud pipelines. Defaults match the app's original behavior.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RenderOptions {
+    /// Treat single newlines as line breaks instead of CommonMark spaces.
+    pub hard_breaks: bool,
+    /// Curly quotes, en/em dashes and ellipses.
+    pub smart_punctuation: bool,
+    /// Render raw HTML in documents; when off it is dropped.
+    pub raw_html: bool,
+    pub footnotes: bool,
+    /// Link bare URLs and email addresses.
+    pub autolinks: bool,
+    pub heading_ids: bool,
+    /// Escape `>=` after list markers so it isn't parsed as a blockquote.
+    pub escape_list_gte: bool,
+    /// `~sub~` subscript. Off by default: GFM also accepts `~single~` tildes
+    /// for strikethrough, which then need `~~double~~` ones.
+    pub subscript: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            hard_breaks: true,
+            smart_punctuation: true,
+            raw_html: true,
+            footnotes: true,
+            autolinks: true,
+            heading_ids: true,
+            escape_list_gte: true,
+            subscript: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// comrak options for these settings. Raw HTML stays enabled in comrak
+    /// because the renderer emits its own HTML nodes; user HTML is removed
+    /// from the tree instead when `raw_html` is off.
+    pub fn comrak_options(&self) -> Options<'static> {
+        let mut options = Options::default();
+
+        // Enable GFM extensions
+        options.extension.strikethrough = true;
+        options.extension.table = true;
+        options.extension.autolink = self.autolinks;
+        options.extension.tasklist = true;
+        options.extension.footnotes = self.footnotes;
+        options.extension.header_ids = self.heading_ids.then(String::new);
+        options.extension.shortcodes = true;
+        options.extension.math_dollars = true;
+        options.extension.math_code = true;
+        options.extension.wikilinks_title_after_pipe = true;
+        options.extension.description_lists = true;
+        options.extension.superscript = true;
+        options.extension.subscript = self.subscript;
+
+        // Parse options
+        options.parse.smart = self.smart_punctuation;
+
+        // Render options
+        options.render.unsafe_ = true;
+        options.render.hardbreaks = self.hard_breaks;
+        // Emit data-sourcepos so the preview can be mapped back to source lines.
+        options.render.sourcepos = true;
+        options
+    }
+
+    /// Strip front matter and apply the text-level preprocessing.
+    pub fn prepare(&self, markdown: &str) -> String {
+        let markdown = strip_front_matter(markdown);
+        if self.escape_list_gte {
+            preprocess_markdown(&markdown)
+        } else {
+            markdown
+        }
+    }
+}
+
+pub fn load_render_options(state: &AppState) {
+    let data_dir = state.app_data_dir.lock().unwrap();
+    if let Some(ref dir) = *data_dir {
+        if let Ok(contents) = fs::read_to_string(dir.join(RENDER_OPTIONS_FILE)) {
+            if let Ok(options) = serde_json::from_str::<RenderOptions>(&contents) {
+                *state.render_options.lock().unwrap() = options;
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_render_options(state: State<'_, AppState>) -> RenderOptions {
+    state.render_options.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_render_options(
+    options: RenderOptions,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    {
+        let data_dir = state.app_data_dir.lock().unwrap();
+        if let Some(ref dir) = *data_dir {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create data directory: {}", e))?;
+            let json = serde_json::to_string_pretty(&options)
+                .map_err(|e| format!("Failed to serialize render options: {}", e))?;
+            fs::write(dir.join(RENDER_OPTIONS_FILE), json)
+                .map_err(|e| format!("Failed to save render options: {}", e))?;
+        }
+    }
+    *state.render_options.lock().unwrap() = options;
+    Ok(())
+}
@@ -0,0 +1,332 @@
+use crate::git::{compute_diff, DiffResult};
+use crate::render_cache::hex_digest;
+use crate::search::{searched_files, Matcher, PathFilter, SearchOptions};
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Replace batches that can be undone.
+const UNDO_LIMIT: usize = 10;
+/// Unchanged lines kept around each change in preview diffs.
+const DIFF_CONTEXT: usize = 2;
+
+/// The replacements `replace_in_files` would make in one file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplacePreview {
+    pub file_path: String,
+    pub file_name: String,
+    /// Hash of the contents the preview was made from.
+    pub hash: String,
+    pub replacements: Vec<ReplacementPreview>,
+    /// The file with every replacement made, trimmed to changed lines and
+    /// their context.
+    pub diff: DiffResult,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplacementPreview {
+    pub line_number: usize,
+    pub line_content: String,
+    /// The line with only this replacement made.
+    pub replaced_line: String,
+}
+
+/// Replacements picked from a file's preview, by index.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceSelection {
+    pub file_path: String,
+    pub hash: String,
+    pub indexes: Vec<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceSummary {
+    pub files: usize,
+    pub replacements: usize,
+}
+
+/// Files changed by one replace, with their contents from before.
+pub struct ReplaceBatch {
+    files: Vec<(PathBuf, String)>,
+    /// Hashes of the files as the replace left them.
+    hashes: Vec<String>,
+}
+
+/// One match of the query, as a byte range into the file.
+struct Edit {
+    start: usize,
+    end: usize,
+    line_number: usize,
+    line_start: usize,
+    line: String,
+    text: String,
+}
+
+/// Every replacement `replace_in_files` would make under `folder`, file by
+/// file. Files are matched line by line, exactly like `search_in_files`.
+#[tauri::command(async)]
+pub fn preview_replace(
+    folder: String,
+    query: String,
+    replacement: String,
+    options: SearchOptions,
+    state: State<'_, AppState>,
+) -> Result<Vec<ReplacePreview>, String> {
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    let folder_path = Path::new(&folder);
+    if !folder_path.is_dir() {
+        return Err(format!("Not a directory: {}", folder));
+    }
+    let matcher = options.matcher(&query)?;
+    let filter = PathFilter::new(&options)?;
+    let scan_options = state.scan_options.lock().unwrap().clone();
+
+    let mut previews = Vec::new();
+    for path in searched_files(folder_path, &filter, &scan_options) {
+        let Ok(content) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let edits = find_edits(&content, &matcher, &replacement, options.regex);
+        if edits.is_empty() {
+            continue;
+        }
+        let replaced = apply_edits(&content, edits.iter());
+        previews.push(ReplacePreview {
+            file_path: path.to_string_lossy().to_string(),
+            file_name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            hash: hex_digest(content.as_bytes()),
+            replacements: edits
+                .iter()
+                .map(|edit| ReplacementPreview {
+                    line_number: edit.line_number,
+                    line_content: edit.line.clone(),
+                    replaced_line: format!(
+                        "{}{}{}",
+                        &edit.line[..edit.start - edit.line_start],
+                        edit.text,
+                        &edit.line[edit.end - edit.line_start..]
+                    ),
+                })
+                .collect(),
+            diff: trim_diff(compute_diff(&content, &replaced), DIFF_CONTEXT),
+        });
+    }
+    Ok(previews)
+}
+
+/// Make the selected replacements from `preview_replace`. Files changed since
+/// the preview are refused, and either every file is written or none is.
+#[tauri::command(async)]
+pub fn replace_in_files(
+    query: String,
+    replacement: String,
+    options: SearchOptions,
+    selections: Vec<ReplaceSelection>,
+    state: State<'_, AppState>,
+) -> Result<ReplaceSummary, String> {
+    let matcher = options.matcher(&query)?;
+    let mut writes = Vec::new();
+    let mut originals = Vec::new();
+    let mut replacements = 0;
+    for selection in &selections {
+        if selection.indexes.is_empty() {
+            continue;
+        }
+        let path = PathBuf::from(&selection.file_path);
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))?;
+        if hex_digest(content.as_bytes()) != selection.hash {
+            return Err(format!(
+                "{} changed since the preview; preview again",
+                path.display()
+            ));
+        }
+        let edits = find_edits(&content, &matcher, &replacement, options.regex);
+        let picked: Vec<&Edit> = edits
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| selection.indexes.contains(i))
+            .map(|(_, edit)| edit)
+            .collect();
+        // Indexes past the file's matches select nothing
+        if picked.is_empty() {
+            continue;
+        }
+        replacements += picked.len();
+        let replaced = apply_edits(&content, picked.into_iter());
+        writes.push((path.clone(), replaced));
+        originals.push((path, content));
+    }
+
+    if writes.is_empty() {
+        return Ok(ReplaceSummary {
+            files: 0,
+            replacements: 0,
+        });
+    }
+    write_all(&writes, &originals)?;
+    let batch = ReplaceBatch {
+        hashes: writes
+            .iter()
+            .map(|(_, content)| hex_digest(content.as_bytes()))
+            .collect(),
+        files: originals,
+    };
+    let mut journal = state.replace_journal.lock().unwrap();
+    journal.push(batch);
+    if journal.len() > UNDO_LIMIT {
+        journal.remove(0);
+    }
+    Ok(ReplaceSummary {
+        files: writes.len(),
+        replacements,
+    })
+}
+
+/// Revert the last `replace_in_files` batch as a whole. Refused if any of its
+/// files was edited since. Returns the number of files restored.
+#[tauri::command(async)]
+pub fn undo_replace(state: State<'_, AppState>) -> Result<usize, String> {
+    let mut journal = state.replace_journal.lock().unwrap();
+    let Some(batch) = journal.last() else {
+        return Err("Nothing to undo".to_string());
+    };
+    let mut current = Vec::new();
+    for ((path, _), hash) in batch.files.iter().zip(&batch.hashes) {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
+        if hex_digest(content.as_bytes()) != *hash {
+            return Err(format!("{} changed since the replace", path.display()));
+        }
+        current.push((path.clone(), content));
+    }
+    write_all(&batch.files, &current)?;
+    let restored = batch.files.len();
+    journal.pop();
+    Ok(restored)
+}
+
+fn find_edits(content: &str, matcher: &Matcher, replacement: &str, expand: bool) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut line_start = 0;
+    for (line_idx, raw) in content.split_inclusive('\n').enumerate() {
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        for caps in matcher.captures_iter(line) {
+            let Some(found) = caps.get(0).filter(|m| !m.is_empty()) else {
+                continue;
+            };
+            // `$1` and `${name}` refer to capture groups in regex mode only
+            let mut text = String::new();
+            if expand {
+                caps.expand(replacement, &mut text);
+            } else {
+                text.push_str(replacement);
+            }
+            edits.push(Edit {
+                start: line_start + found.start(),
+                end: line_start + found.end(),
+                line_number: line_idx + 1,
+                line_start,
+                line: line.to_string(),
+                text,
+            });
+        }
+        line_start += raw.len();
+    }
+    edits
+}
+
+fn apply_edits<'a>(content: &str, edits: impl Iterator<Item = &'a Edit>) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut pos = 0;
+    for edit in edits {
+        out.push_str(&content[pos..edit.start]);
+        out.push_str(&edit.text);
+        pos = edit.end;
+    }
+    out.push_str(&content[pos..]);
+    out
+}
+
+/// Keep changed lines and `context` unchanged lines around each.
+fn trim_diff(diff: DiffResult, context: usize) -> DiffResult {
+    let changed: Vec<usize> = diff
+        .lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.tag != "equal")
+        .map(|(i, _)| i)
+        .collect();
+    let near_change = |i: usize| {
+        let next = changed.partition_point(|&c| c < i);
+        let after = changed.get(next).is_some_and(|&c| c - i <= context);
+        let before = next > 0 && i - changed[next - 1] <= context;
+        after || before
+    };
+    DiffResult {
+        lines: diff
+            .lines
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| near_change(*i))
+            .map(|(_, line)| line)
+            .collect(),
+        additions: diff.additions,
+        deletions: diff.deletions,
+    }
+}
+
+/// Write every file or none. New contents go to temporary files beside their
+/// targets first; if moving one into place fails, files already replaced get
+/// their `originals` back.
+fn write_all(writes: &[(PathBuf, String)], originals: &[(PathBuf, String)]) -> Result<(), String> {
+    let mut temps = Vec::new();
+    for (path, content) in writes {
+        let temp = temp_path(path);
+        if let Err(e) = fs::write(&temp, content) {
+            for temp in &temps {
+                let _ = fs::remove_file(temp);
+            }
+            let _ = fs::remove_file(&temp);
+            return Err(format!("Failed to write {}: {}", path.display(), e));
+        }
+        if let Ok(meta) = fs::metadata(path) {
+            let _ = fs::set_permissions(&temp, meta.permissions());
+        }
+        temps.push(temp);
+    }
+    for (i, ((path, _), temp)) in writes.iter().zip(&temps).enumerate() {
+        if let Err(e) = fs::rename(temp, path) {
+            for (path, content) in &originals[..i] {
+                if let Err(e) = fs::write(path, content) {
+                    eprintln!("Warning: Could not restore {}: {}", path.display(), e);
+                }
+            }
+            for temp in &temps[i..] {
+                let _ = fs::remove_file(temp);
+            }
+            return Err(format!("Failed to write {}: {}", path.display(), e));
+        }
+    }
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.mre-replace", name))
+}
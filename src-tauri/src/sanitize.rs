@@ -0,0 +1,92 @@
+use ammonia::Builder;
+
+/// Tags the renderer emits beyond ammonia's defaults: table-of-contents
+/// navs, footnote sections, task-list checkboxes and MathML.
+const EXTRA_TAGS: &[&str] = &["nav", "section", "input", "mark"];
+
+const MATHML_TAGS: &[&str] = &[
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "mtext",
+    "mspace",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mtable",
+    "mtr",
+    "mtd",
+];
+
+/// `data:` URLs allowed as image sources. Images can't run scripts, SVG
+/// included; anything else with a `data:` URL is dropped.
+const DATA_IMAGE_PREFIXES: &[&str] = &[
+    "data:image/svg+xml;base64,",
+    "data:image/png;base64,",
+    "data:image/jpeg;base64,",
+    "data:image/gif;base64,",
+    "data:image/webp;base64,",
+];
+
+const MATHML_ATTRIBUTES: &[&str] = &[
+    "display",
+    "encoding",
+    "columnalign",
+    "fence",
+    "form",
+    "largeop",
+    "linethickness",
+    "mathvariant",
+    "movablelimits",
+    "stretchy",
+    "width",
+];
+
+/// Strip scripts, event handlers, styles and unknown tags from rendered HTML,
+/// keeping everything the markdown renderer itself produces: classes for
+/// highlighting, alerts and in-app links, heading ids, `data-*` attributes
+/// and MathML.
+pub fn sanitize_html(html: &str) -> String {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(EXTRA_TAGS)
+        .add_tags(MATHML_TAGS)
+        .add_generic_attributes(&["class", "id", "lang", "title"])
+        .add_generic_attribute_prefixes(&["data-", "aria-"])
+        .add_tag_attributes("input", &["type", "checked", "disabled"])
+        .add_tag_attributes("pre", &["lang"])
+        // Rendered diagrams are embedded as SVG data URLs; the filter keeps
+        // `data:` to image sources
+        .add_url_schemes(&["data"])
+        .attribute_filter(|element, attribute, value| {
+            let is_data = value
+                .trim_start()
+                .get(..5)
+                .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"));
+            if is_data && !(element == "img" && attribute == "src" && is_data_image(value)) {
+                return None;
+            }
+            Some(value.into())
+        });
+    for tag in MATHML_TAGS {
+        builder.add_tag_attributes(tag, MATHML_ATTRIBUTES);
+    }
+    builder.clean(html).to_string()
+}
+
+fn is_data_image(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    DATA_IMAGE_PREFIXES
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+}
@@ -0,0 +1,112 @@
+use crate::state::AppState;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+const SCAN_OPTIONS_FILE: &str = "scan_options.json";
+/// Per-folder ignore file, in `.gitignore` syntax.
+pub const MRE_IGNORE_FILE: &str = ".mreignore";
+/// Deepest folder level walked.
+const MAX_DEPTH: usize = 20;
+
+/// Which files the folder tree and search walk over. `.gitignore`, `.ignore`
+/// and `.mreignore` files are always honored, in repositories or not.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Include dot-folders such as `.github/` and dotfiles.
+    pub show_hidden: bool,
+    /// Gitignore-style patterns applied to every folder.
+    pub ignore_patterns: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            show_hidden: false,
+            ignore_patterns: vec!["node_modules/".to_string(), "__pycache__/".to_string()],
+        }
+    }
+}
+
+impl ScanOptions {
+    /// A walker over `root` that skips ignored entries and, unless shown,
+    /// hidden ones. `.git` itself is never walked.
+    pub fn walker(&self, root: &Path) -> WalkBuilder {
+        self.walker_at(root, root)
+    }
+
+    /// Like `walker`, but starting at `dir` inside `root`. Configured
+    /// patterns stay relative to `root`.
+    pub fn walker_at(&self, root: &Path, dir: &Path) -> WalkBuilder {
+        let patterns = self.ignore_matcher(root);
+        let mut builder = WalkBuilder::new(dir);
+        builder
+            .hidden(!self.show_hidden)
+            .require_git(false)
+            .add_custom_ignore_filename(MRE_IGNORE_FILE)
+            .follow_links(true)
+            .max_depth(Some(MAX_DEPTH))
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                if entry.depth() == 0 {
+                    return true;
+                }
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                entry.file_name() != ".git" && !patterns.matched(entry.path(), is_dir).is_ignore()
+            });
+        builder
+    }
+
+    fn ignore_matcher(&self, root: &Path) -> Gitignore {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in &self.ignore_patterns {
+            let pattern = pattern.trim();
+            if pattern.is_empty() {
+                continue;
+            }
+            if let Err(e) = builder.add_line(None, pattern) {
+                eprintln!("Warning: Invalid ignore pattern `{}`: {}", pattern, e);
+            }
+        }
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    }
+}
+
+pub fn load_scan_options(state: &AppState) {
+    let data_dir = state.app_data_dir.lock().unwrap();
+    if let Some(ref dir) = *data_dir {
+        if let Ok(contents) = fs::read_to_string(dir.join(SCAN_OPTIONS_FILE)) {
+            if let Ok(options) = serde_json::from_str::<ScanOptions>(&contents) {
+                *state.scan_options.lock().unwrap() = options;
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_scan_options(state: State<'_, AppState>) -> ScanOptions {
+    state.scan_options.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_scan_options(options: ScanOptions, state: State<'_, AppState>) -> Result<(), String> {
+    {
+        let data_dir = state.app_data_dir.lock().unwrap();
+        if let Some(ref dir) = *data_dir {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create data directory: {}", e))?;
+            let json = serde_json::to_string_pretty(&options)
+                .map_err(|e| format!("Failed to serialize scan options: {}", e))?;
+            fs::write(dir.join(SCAN_OPTIONS_FILE), json)
+                .map_err(|e| format!("Failed to save scan options: {}", e))?;
+        }
+    }
+    *state.scan_options.lock().unwrap() = options;
+    // Wiki-link targets follow what the tree shows
+    *state.page_index.lock().unwrap() = None;
+    Ok(())
+}
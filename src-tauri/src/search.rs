@@ -0,0 +1,305 @@
+use crate::commands::is_markdown_file;
+use crate::scan_options::ScanOptions;
+use crate::state::AppState;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::{Captures, Match, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// Matches returned by one search, to avoid overwhelming the UI.
+const MAX_RESULTS: usize = 500;
+/// Most lines of context returned on each side of a match.
+const MAX_CONTEXT_LINES: usize = 10;
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub file_path: String,
+    pub file_name: String,
+    pub line_number: usize,
+    pub line_content: String,
+    /// Byte offsets of the match in `line_content`.
+    pub match_start: usize,
+    pub match_end: usize,
+    /// Character offsets of the match in `line_content`.
+    pub char_start: usize,
+    pub char_end: usize,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+}
+
+/// How `search_in_files` matches and which files it reads.
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    /// Treat the query as a regular expression.
+    pub regex: bool,
+    pub whole_word: bool,
+    /// Globs of files to search; all markdown files when empty. Globs without
+    /// a `/` match file names, others the path relative to the folder.
+    pub include: Vec<String>,
+    /// Globs of files and folders to skip.
+    pub exclude: Vec<String>,
+    /// Lines of context returned before and after each match.
+    pub context_lines: usize,
+}
+
+impl SearchOptions {
+    /// The query as a matcher honoring the mode, case and whole-word options.
+    pub fn matcher(&self, query: &str) -> Result<Matcher, String> {
+        let pattern = if self.regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid regex: {}", e))?;
+        Ok(Matcher {
+            regex,
+            whole_word: self.whole_word,
+        })
+    }
+}
+
+/// A search query. Whole words are checked at the edges of each match
+/// rather than with `\b`, which never matches next to a query that starts
+/// or ends with punctuation such as `C++` or `.env`.
+pub struct Matcher {
+    regex: Regex,
+    whole_word: bool,
+}
+
+impl Matcher {
+    /// Successive non-overlapping matches in `line`.
+    pub fn find_iter<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Match<'a>> + 'a {
+        self.captures_iter(line).filter_map(|caps| caps.get(0))
+    }
+
+    /// Like `find_iter`, with the capture groups of each match.
+    pub fn captures_iter<'a>(&'a self, line: &'a str) -> impl Iterator<Item = Captures<'a>> + 'a {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            while pos <= line.len() {
+                let caps = self.regex.captures_at(line, pos)?;
+                let found = caps.get(0)?;
+                if !self.whole_word || is_whole_word(line, found.start(), found.end()) {
+                    pos = if found.is_empty() {
+                        next_char(line, found.end())
+                    } else {
+                        found.end()
+                    };
+                    return Some(caps);
+                }
+                // Look again from the next character, which may start a word
+                pos = next_char(line, found.start());
+            }
+            None
+        })
+    }
+}
+
+/// Whether `line[start..end]` is not part of a longer word. An edge that is
+/// itself punctuation or space needs nothing from its neighbour.
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let found = &line[start..end];
+    let starts_clear = !found.chars().next().is_some_and(is_word)
+        || !line[..start].chars().next_back().is_some_and(is_word);
+    let ends_clear = !found.chars().next_back().is_some_and(is_word)
+        || !line[end..].chars().next().is_some_and(is_word);
+    starts_clear && ends_clear
+}
+
+/// Byte offset just past the character at `pos`, or past the end.
+fn next_char(line: &str, pos: usize) -> usize {
+    pos + line[pos..].chars().next().map_or(1, char::len_utf8)
+}
+
+/// Include and exclude globs for searched files.
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(options: &SearchOptions) -> Result<Self, String> {
+        let include = glob_set(&options.include)?;
+        Ok(PathFilter {
+            include: (!include.is_empty()).then_some(include),
+            exclude: glob_set(&options.exclude)?,
+        })
+    }
+
+    /// Whether a file, by its path relative to the searched folder, is searched.
+    pub fn allows(&self, relative: &Path) -> bool {
+        let matches = |set: &GlobSet, path: &Path| {
+            set.is_match(path) || path.file_name().is_some_and(|name| set.is_match(name))
+        };
+        let included = self
+            .include
+            .as_ref()
+            .map_or(true, |set| matches(set, relative));
+        // Excluding a folder excludes everything in it
+        let excluded = relative
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .any(|path| matches(&self.exclude, path));
+        included && !excluded
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            continue;
+        }
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid glob `{}`: {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| format!("Invalid globs: {}", e))
+}
+
+/// Markdown files under `folder` that pass `filter`, in walk order.
+pub fn searched_files<'a>(
+    folder: &'a Path,
+    filter: &'a PathFilter,
+    scan_options: &ScanOptions,
+) -> impl Iterator<Item = PathBuf> + 'a {
+    scan_options
+        .walker(folder)
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter(|entry| is_markdown_file(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.into_path())
+        .filter(move |path| filter.allows(path.strip_prefix(folder).unwrap_or(path)))
+}
+
+#[tauri::command(async)]
+pub fn search_in_files(
+    folder: String,
+    query: String,
+    options: SearchOptions,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchMatch>, String> {
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    let folder_path = Path::new(&folder);
+    if !folder_path.is_dir() {
+        return Err(format!("Not a directory: {}", folder));
+    }
+    let matcher = options.matcher(&query)?;
+    let filter = PathFilter::new(&options)?;
+    let context = options.context_lines.min(MAX_CONTEXT_LINES);
+    let scan_options = state.scan_options.lock().unwrap().clone();
+
+    let mut results = Vec::new();
+    for path in searched_files(folder_path, &filter, &scan_options) {
+        let path = path.as_path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Ok(content) = fs::read_to_string(path) else {
+            continue;
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        for (line_idx, line) in lines.iter().enumerate() {
+            for found in matcher.find_iter(line) {
+                // Patterns such as `a*` also match nothing everywhere
+                if found.start() == found.end() {
+                    continue;
+                }
+                let char_start = line[..found.start()].chars().count();
+                results.push(SearchMatch {
+                    file_path: path.to_string_lossy().to_string(),
+                    file_name: name.clone(),
+                    line_number: line_idx + 1,
+                    line_content: line.to_string(),
+                    match_start: found.start(),
+                    match_end: found.end(),
+                    char_start,
+                    char_end: char_start + found.as_str().chars().count(),
+                    context_before: lines[line_idx.saturating_sub(context)..line_idx]
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect(),
+                    context_after: lines[line_idx + 1..(line_idx + 1 + context).min(lines.len())]
+                        .iter()
+                        .map(|l| l.to_string())
+                        .collect(),
+                });
+                if results.len() >= MAX_RESULTS {
+                    return Ok(results);
+                }
+            }
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(query: &str, line: &str) -> Vec<(usize, usize)> {
+        let options = SearchOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        let matcher = options.matcher(query).unwrap();
+        matcher
+            .find_iter(line)
+            .map(|found| (found.start(), found.end()))
+            .collect()
+    }
+
+    #[test]
+    fn whole_word_skips_matches_inside_words() {
+        assert_eq!(
+            words("cat", "cat concat cats cat_ cat."),
+            vec![(0, 3), (21, 24)]
+        );
+        assert_eq!(words("cat", "xcat cat"), vec![(5, 8)]);
+    }
+
+    #[test]
+    fn whole_word_allows_punctuation_at_the_edges() {
+        assert_eq!(
+            words("C++", "I like C++, not C++x"),
+            vec![(7, 10), (16, 19)]
+        );
+        assert_eq!(
+            words(".env", "copy .env to a.env or .envrc"),
+            vec![(5, 9), (14, 18)]
+        );
+    }
+
+    #[test]
+    fn whole_word_handles_unicode() {
+        assert_eq!(words("café", "un café, cafés"), vec![(3, 8)]);
+    }
+
+    #[test]
+    fn whole_word_regex_retries_after_a_rejected_match() {
+        let options = SearchOptions {
+            regex: true,
+            whole_word: true,
+            ..Default::default()
+        };
+        let matcher = options.matcher(r"a+").unwrap();
+        let found: Vec<&str> = matcher.find_iter("baaa aa").map(|m| m.as_str()).collect();
+        assert_eq!(found, vec!["aa"]);
+    }
+}
@@ -0,0 +1,623 @@
+use crate::commands::is_markdown_file;
+use crate::folder_tree::first_heading;
+use crate::front_matter::{extract_metadata, strip_front_matter};
+use crate::recent::load_recents;
+use crate::render_cache::hex_digest;
+use crate::scan_options::ScanOptions;
+use crate::search::{PathFilter, SearchOptions};
+use crate::state::AppState;
+use crate::watcher::TreeDelta;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+pub const SEARCH_INDEX_DIR: &str = "search_index";
+/// Modification times of indexed files, next to the index itself.
+const MANIFEST_FILE: &str = "manifest.json";
+const WRITER_MEMORY: usize = 50_000_000;
+/// Hits returned by one query.
+const MAX_HITS: usize = 200;
+const SNIPPET_CHARS: usize = 160;
+
+#[derive(Clone, Copy)]
+struct Fields {
+    path: Field,
+    title: Field,
+    tag: Field,
+    heading: Field,
+    body: Field,
+}
+
+/// On-disk full-text index of one folder's markdown files, kept in the app
+/// data directory and updated by the folder watcher.
+pub struct SearchIndex {
+    root: PathBuf,
+    dir: PathBuf,
+    index: Index,
+    reader: IndexReader,
+    writer: IndexWriter,
+    fields: Fields,
+    /// Modification time of every indexed file, in milliseconds, to find what
+    /// changed while the folder wasn't open.
+    manifest: HashMap<PathBuf, u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexHit {
+    pub file_path: String,
+    pub file_name: String,
+    pub title: Option<String>,
+    pub score: f32,
+    pub snippet: String,
+    /// Character ranges of matched terms in `snippet`.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+/// The open folder's search index, or the folder changes seen while it is
+/// still being opened and caught up.
+pub enum IndexSlot {
+    Opening {
+        root: PathBuf,
+        queued: Vec<(TreeDelta, Vec<PathBuf>)>,
+    },
+    Ready(Box<SearchIndex>),
+}
+
+impl IndexSlot {
+    pub fn root(&self) -> &Path {
+        match self {
+            IndexSlot::Opening { root, .. } => root,
+            IndexSlot::Ready(index) => index.root(),
+        }
+    }
+
+    /// Apply a batch of folder changes, or hold it until the index is open.
+    pub fn apply(&mut self, delta: &TreeDelta, edited: &[PathBuf]) -> Result<(), String> {
+        match self {
+            IndexSlot::Opening { queued, .. } => {
+                queued.push((delta.clone(), edited.to_vec()));
+                Ok(())
+            }
+            IndexSlot::Ready(index) => index.apply(delta, edited),
+        }
+    }
+}
+
+impl SearchIndex {
+    /// Open the index of `root`, creating it if needed. One that can't be
+    /// opened, such as one with an older schema, is rebuilt from scratch.
+    fn open(data_dir: &Path, root: &Path) -> Result<Self, String> {
+        let dir = index_dir(data_dir, root);
+        let (schema, fields) = schema();
+        let index = match open_index(&dir, schema.clone()) {
+            Ok(index) => index,
+            Err(_) => {
+                let _ = fs::remove_dir_all(&dir);
+                open_index(&dir, schema)?
+            }
+        };
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(|e| format!("Failed to read search index: {}", e))?;
+        let writer = index
+            .writer(WRITER_MEMORY)
+            .map_err(|e| format!("Failed to open search index for writing: {}", e))?;
+        let manifest = fs::read(dir.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        if manifest.is_none() {
+            // Without a manifest there's no telling what the index holds
+            writer
+                .delete_all_documents()
+                .map_err(|e| format!("Failed to clear search index: {}", e))?;
+        }
+        Ok(SearchIndex {
+            root: root.to_path_buf(),
+            dir,
+            index,
+            reader,
+            writer,
+            fields,
+            manifest: manifest.unwrap_or_default(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Bring the index in line with `files`, the folder's markdown files.
+    /// Only files whose modification time changed are read.
+    fn sync(&mut self, files: Vec<PathBuf>) -> Result<(), String> {
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        let stale: Vec<PathBuf> = self
+            .manifest
+            .keys()
+            .filter(|path| !present.contains(path))
+            .cloned()
+            .collect();
+        for path in stale {
+            self.remove(&path);
+        }
+        for path in &files {
+            if self.manifest.get(path) != Some(&modified_millis(path)) {
+                self.add(path);
+            }
+        }
+        self.commit()
+    }
+
+    /// Apply a batch of folder changes: tree changes from the watcher plus
+    /// files whose contents were edited.
+    pub fn apply(&mut self, delta: &TreeDelta, edited: &[PathBuf]) -> Result<(), String> {
+        for path in &delta.removed {
+            self.remove(Path::new(path));
+        }
+        for renamed in &delta.renamed {
+            self.remove(Path::new(&renamed.from));
+            self.add(Path::new(&renamed.to));
+        }
+        for path in &delta.added {
+            self.add(Path::new(path));
+        }
+        // New files arrive through the delta; only known files are re-read
+        for path in edited {
+            if self.manifest.contains_key(path) {
+                self.add(path);
+            }
+        }
+        self.commit()
+    }
+
+    /// Index `path`, replacing any earlier version of it.
+    fn add(&mut self, path: &Path) {
+        let path_text = path.to_string_lossy().to_string();
+        self.writer
+            .delete_term(Term::from_field_text(self.fields.path, &path_text));
+        let Ok(content) = fs::read_to_string(path) else {
+            self.manifest.remove(path);
+            return;
+        };
+        let body = strip_front_matter(&content);
+        let metadata = extract_metadata(&content);
+        let mut doc = TantivyDocument::default();
+        doc.add_text(self.fields.path, &path_text);
+        let title = metadata
+            .as_ref()
+            .and_then(|meta| meta.title.clone())
+            .or_else(|| first_heading(&body));
+        if let Some(title) = title {
+            doc.add_text(self.fields.title, title);
+        }
+        for tag in metadata.map(|meta| meta.tags).unwrap_or_default() {
+            doc.add_text(self.fields.tag, tag);
+        }
+        for heading in headings(&body) {
+            doc.add_text(self.fields.heading, heading);
+        }
+        doc.add_text(self.fields.body, &body);
+        if let Err(e) = self.writer.add_document(doc) {
+            eprintln!("Warning: Could not index {}: {}", path.display(), e);
+            return;
+        }
+        self.manifest
+            .insert(path.to_path_buf(), modified_millis(path));
+    }
+
+    /// Drop `path`, or everything under it if it was a folder.
+    fn remove(&mut self, path: &Path) {
+        let gone: Vec<PathBuf> = self
+            .manifest
+            .keys()
+            .filter(|file| file.starts_with(path))
+            .cloned()
+            .collect();
+        for file in gone {
+            let text = file.to_string_lossy().to_string();
+            self.writer
+                .delete_term(Term::from_field_text(self.fields.path, &text));
+            self.manifest.remove(&file);
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.writer
+            .commit()
+            .map_err(|e| format!("Failed to update search index: {}", e))?;
+        self.reader
+            .reload()
+            .map_err(|e| format!("Failed to reload search index: {}", e))?;
+        let json = serde_json::to_vec(&self.manifest)
+            .map_err(|e| format!("Failed to serialize search manifest: {}", e))?;
+        fs::write(self.dir.join(MANIFEST_FILE), json)
+            .map_err(|e| format!("Failed to save search manifest: {}", e))
+    }
+
+    /// Files matching `query`, most relevant first. Titles weigh most, then
+    /// headings. Supports `"phrases"` and `title:`, `tag:` and `heading:`.
+    fn search(&self, query: &str, filter: &PathFilter) -> Result<Vec<IndexHit>, String> {
+        let fields = self.fields;
+        let searcher = self.reader.searcher();
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![fields.title, fields.heading, fields.tag, fields.body],
+        );
+        parser.set_field_boost(fields.title, 3.0);
+        parser.set_field_boost(fields.heading, 2.0);
+        // Every word must match, as in the plain search
+        parser.set_conjunction_by_default();
+        // Half-typed syntax such as `title:` still searches what it can
+        let (query, _) = parser.parse_query_lenient(query);
+        let top = searcher
+            .search(&query, &TopDocs::with_limit(MAX_HITS))
+            .map_err(|e| format!("Failed to search index: {}", e))?;
+        let mut snippets = SnippetGenerator::create(&searcher, &*query, fields.body)
+            .map_err(|e| format!("Failed to search index: {}", e))?;
+        snippets.set_max_num_chars(SNIPPET_CHARS);
+
+        let mut hits = Vec::new();
+        for (score, address) in top {
+            let doc: TantivyDocument = searcher
+                .doc(address)
+                .map_err(|e| format!("Failed to read search index: {}", e))?;
+            let Some(path) = doc.get_first(fields.path).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let path = PathBuf::from(path);
+            if !filter.allows(path.strip_prefix(&self.root).unwrap_or(&path)) {
+                continue;
+            }
+            let snippet = snippets.snippet_from_doc(&doc);
+            let fragment = snippet.fragment();
+            let char_offset = |byte: usize| fragment[..byte].chars().count();
+            hits.push(IndexHit {
+                file_path: path.to_string_lossy().to_string(),
+                file_name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                title: doc
+                    .get_first(fields.title)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+                score,
+                snippet: fragment.to_string(),
+                highlights: snippet
+                    .highlighted()
+                    .iter()
+                    .map(|range| (char_offset(range.start), char_offset(range.end)))
+                    .collect(),
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Where the index of `root` is kept.
+fn index_dir(data_dir: &Path, root: &Path) -> PathBuf {
+    let key = hex_digest(root.to_string_lossy().as_bytes());
+    data_dir.join(SEARCH_INDEX_DIR).join(&key[..16])
+}
+
+/// Delete the indexes of folders other than `keep`, so only recent folders
+/// take up space.
+fn prune_indexes<'a>(data_dir: &Path, keep: impl IntoIterator<Item = &'a Path>) {
+    let kept: HashSet<PathBuf> = keep
+        .into_iter()
+        .map(|root| index_dir(data_dir, root))
+        .collect();
+    let Ok(entries) = fs::read_dir(data_dir.join(SEARCH_INDEX_DIR)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && !kept.contains(&path) {
+            if let Err(e) = fs::remove_dir_all(&path) {
+                eprintln!("Warning: Could not remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Make `index` the ready index if `slot` is still opening its folder,
+/// applying the changes queued meanwhile. Returns the number of documents,
+/// or `None` if another folder was opened since.
+fn install(slot: &mut Option<IndexSlot>, mut index: SearchIndex) -> Option<usize> {
+    let Some(IndexSlot::Opening { root, queued }) = slot.as_mut() else {
+        return None;
+    };
+    if *root != index.root {
+        return None;
+    }
+    for (delta, edited) in queued.drain(..) {
+        if let Err(e) = index.apply(&delta, &edited) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    let documents = index.manifest.len();
+    *slot = Some(IndexSlot::Ready(Box::new(index)));
+    Some(documents)
+}
+
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        path: builder.add_text_field("path", STRING | STORED),
+        title: builder.add_text_field("title", TEXT | STORED),
+        tag: builder.add_text_field("tag", TEXT | STORED),
+        heading: builder.add_text_field("heading", TEXT),
+        body: builder.add_text_field("body", TEXT | STORED),
+    };
+    (builder.build(), fields)
+}
+
+fn open_index(dir: &Path, schema: Schema) -> Result<Index, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create search index: {}", e))?;
+    let directory =
+        MmapDirectory::open(dir).map_err(|e| format!("Failed to open search index: {}", e))?;
+    Index::open_or_create(directory, schema)
+        .map_err(|e| format!("Failed to open search index: {}", e))
+}
+
+fn modified_millis(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Text of every ATX heading outside fenced code.
+fn headings(body: &str) -> Vec<String> {
+    let mut headings = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in body.lines() {
+        let line = line.trim_start();
+        if let Some(marker) = fence {
+            if line.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if line.starts_with("```") {
+            fence = Some("```");
+        } else if line.starts_with("~~~") {
+            fence = Some("~~~");
+        } else if line.starts_with('#') {
+            let text = line.trim_start_matches('#');
+            if text.starts_with(' ') {
+                headings.push(text.trim().trim_end_matches('#').trim().to_string());
+            }
+        }
+    }
+    headings
+}
+
+/// Open and catch up the index of `root` in the background, then make it
+/// available to `query_search_index` and emit `search-index-ready`. Folder
+/// changes seen meanwhile are applied once it is open. Indexes of folders
+/// no longer in the recent list are deleted.
+pub fn open_in_background(app_handle: AppHandle, root: PathBuf, scan_options: ScanOptions) {
+    let state = app_handle.state::<AppState>();
+    // Drop the previous folder's index, releasing its writer lock
+    *state.search_index.lock().unwrap() = Some(IndexSlot::Opening {
+        root: root.clone(),
+        queued: Vec::new(),
+    });
+    let Some(data_dir) = state.app_data_dir.lock().unwrap().clone() else {
+        return;
+    };
+    let recents = load_recents(&state);
+    std::thread::spawn(move || {
+        let keep = recents.recent_folders.iter().map(Path::new);
+        prune_indexes(&data_dir, keep.chain([root.as_path()]));
+        let opened = SearchIndex::open(&data_dir, &root).and_then(|mut index| {
+            let files = scan_options
+                .walker(&root)
+                .build()
+                .flatten()
+                .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+                .filter(|e| is_markdown_file(&e.file_name().to_string_lossy()))
+                .map(|e| e.into_path())
+                .collect();
+            index.sync(files)?;
+            Ok(index)
+        });
+        let state = app_handle.state::<AppState>();
+        let mut slot = state.search_index.lock().unwrap();
+        match opened {
+            Ok(index) => {
+                if let Some(documents) = install(&mut slot, index) {
+                    let _ = app_handle.emit("search-index-ready", documents);
+                }
+            }
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                // Stop queueing changes for an index that won't open
+                if slot.as_ref().is_some_and(|slot| slot.root() == root) {
+                    *slot = None;
+                }
+            }
+        }
+    });
+}
+
+/// Ranked full-text search over the open folder. Only the include and
+/// exclude globs of `options` apply. Fails while the index is being built.
+#[tauri::command(async)]
+pub fn query_search_index(
+    query: String,
+    options: SearchOptions,
+    state: State<'_, AppState>,
+) -> Result<Vec<IndexHit>, String> {
+    if query.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let filter = PathFilter::new(&options)?;
+    let slot = state.search_index.lock().unwrap();
+    let Some(IndexSlot::Ready(index)) = slot.as_ref() else {
+        return Err("The search index is not ready yet".to_string());
+    };
+    index.search(&query, &filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use crate::watcher::RenamedPath;
+
+    /// A scratch `notes/` folder with `alpha.md` and `beta.md`, and an app
+    /// data directory next to it.
+    struct Fixture {
+        dir: TempDir,
+    }
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let dir = TempDir::new(&format!("index-{}", name));
+            dir.write("notes/alpha.md", "# Alpha\n\nApples here.");
+            dir.write("notes/beta.md", "# Beta\n\nBananas here.");
+            fs::create_dir_all(dir.path().join("data")).unwrap();
+            Fixture { dir }
+        }
+
+        fn notes(&self) -> PathBuf {
+            self.dir.path().join("notes")
+        }
+
+        fn data(&self) -> PathBuf {
+            self.dir.path().join("data")
+        }
+
+        fn files(&self) -> Vec<PathBuf> {
+            let mut files: Vec<PathBuf> = fs::read_dir(self.notes())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            files.sort();
+            files
+        }
+
+        fn open(&self) -> SearchIndex {
+            let mut index = SearchIndex::open(&self.data(), &self.notes()).unwrap();
+            index.sync(self.files()).unwrap();
+            index
+        }
+    }
+
+    fn hits(index: &SearchIndex, query: &str) -> Vec<String> {
+        let filter = PathFilter::new(&SearchOptions::default()).unwrap();
+        let mut names: Vec<String> = index
+            .search(query, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.file_name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn path_text(path: PathBuf) -> String {
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn sync_catches_up_with_changes_made_while_closed() {
+        let fixture = Fixture::new("sync");
+        let index = fixture.open();
+        assert_eq!(hits(&index, "apples"), vec!["alpha.md"]);
+        assert_eq!(hits(&index, "title:beta"), vec!["beta.md"]);
+        drop(index);
+
+        fs::remove_file(fixture.notes().join("alpha.md")).unwrap();
+        fs::write(fixture.notes().join("gamma.md"), "Apples again.").unwrap();
+        let index = fixture.open();
+        assert_eq!(hits(&index, "apples"), vec!["gamma.md"]);
+        assert_eq!(index.manifest.len(), 2);
+    }
+
+    #[test]
+    fn apply_follows_watcher_changes() {
+        let fixture = Fixture::new("apply");
+        let mut index = fixture.open();
+        let notes = fixture.notes();
+        fs::rename(notes.join("alpha.md"), notes.join("apple.md")).unwrap();
+        fs::write(notes.join("beta.md"), "Cherries now.").unwrap();
+        let delta = TreeDelta {
+            renamed: vec![RenamedPath {
+                from: path_text(notes.join("alpha.md")),
+                to: path_text(notes.join("apple.md")),
+            }],
+            ..Default::default()
+        };
+        index.apply(&delta, &[notes.join("beta.md")]).unwrap();
+        assert_eq!(hits(&index, "apples"), vec!["apple.md"]);
+        assert_eq!(hits(&index, "cherries"), vec!["beta.md"]);
+        assert!(hits(&index, "bananas").is_empty());
+    }
+
+    #[test]
+    fn changes_while_opening_are_applied_on_install() {
+        let fixture = Fixture::new("queue");
+        let notes = fixture.notes();
+        let mut slot = Some(IndexSlot::Opening {
+            root: notes.clone(),
+            queued: Vec::new(),
+        });
+        // The watcher reports a file created during the initial sync
+        fs::write(notes.join("late.md"), "Dates arrived late.").unwrap();
+        let delta = TreeDelta {
+            added: vec![path_text(notes.join("late.md"))],
+            ..Default::default()
+        };
+        slot.as_mut().unwrap().apply(&delta, &[]).unwrap();
+
+        let mut index = SearchIndex::open(&fixture.data(), &notes).unwrap();
+        index
+            .sync(vec![notes.join("alpha.md"), notes.join("beta.md")])
+            .unwrap();
+        assert_eq!(install(&mut slot, index), Some(3));
+        let Some(IndexSlot::Ready(index)) = &slot else {
+            panic!("index not installed");
+        };
+        assert_eq!(hits(index, "dates"), vec!["late.md"]);
+    }
+
+    #[test]
+    fn install_skips_an_index_for_another_folder() {
+        let fixture = Fixture::new("other");
+        let index = fixture.open();
+        let mut slot = Some(IndexSlot::Opening {
+            root: fixture.dir.path().to_path_buf(),
+            queued: Vec::new(),
+        });
+        assert_eq!(install(&mut slot, index), None);
+        assert!(matches!(slot, Some(IndexSlot::Opening { .. })));
+    }
+
+    #[test]
+    fn prune_keeps_only_listed_folders() {
+        let fixture = Fixture::new("prune");
+        let data = fixture.data();
+        let kept = Path::new("/kept");
+        let gone = Path::new("/gone");
+        fs::create_dir_all(index_dir(&data, kept)).unwrap();
+        fs::create_dir_all(index_dir(&data, gone)).unwrap();
+        prune_indexes(&data, [kept]);
+        assert!(index_dir(&data, kept).is_dir());
+        assert!(!index_dir(&data, gone).exists());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::render_options::RenderOptions;
+use comrak::nodes::{AstNode, NodeValue, Sourcepos};
+use comrak::{parse_document, Arena};
+use serde::Serialize;
+
+/// A source range as emitted in the preview's `data-sourcepos` attributes.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceBlock {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    /// The attribute value, `start_line:start_col-end_line:end_col`.
+    pub sourcepos: String,
+    pub kind: String,
+}
+
+impl SourceBlock {
+    fn from_sourcepos(sp: Sourcepos, kind: &str) -> Self {
+        SourceBlock {
+            start_line: sp.start.line,
+            start_column: sp.start.column,
+            end_line: sp.end.line,
+            end_column: sp.end.column,
+            sourcepos: sp.to_string(),
+            kind: kind.to_string(),
+        }
+    }
+}
+
+/// Find the rendered blocks that contain a 1-based source `line`, innermost
+/// first. Not every block gets its own element (tight list paragraphs don't,
+/// for example), so callers should use the first one present in the DOM.
+/// Lines between blocks map to the next block below them.
+pub fn blocks_for_line(
+    markdown: &str,
+    line: usize,
+    render_options: &RenderOptions,
+) -> Vec<SourceBlock> {
+    let input = render_options.prepare(markdown);
+    let options = render_options.comrak_options();
+    let arena = Arena::new();
+    let root = parse_document(&arena, &input, &options);
+
+    let mut blocks = containing_blocks(root, line);
+    if blocks.is_empty() {
+        // Blank line or front matter: snap to the next top-level block.
+        let next = root.children().find(|child| {
+            let sp = child.data.borrow().sourcepos;
+            sp.start.line >= line
+        });
+        if let Some(next) = next {
+            blocks = containing_blocks(root, next.data.borrow().sourcepos.start.line);
+        }
+    }
+    blocks
+}
+
+fn containing_blocks<'a>(root: &'a AstNode<'a>, line: usize) -> Vec<SourceBlock> {
+    let mut blocks = Vec::new();
+    let mut node = root;
+    // Descend through the block tree, always into the child containing the line.
+    loop {
+        let next = node.children().find(|child| {
+            let ast = child.data.borrow();
+            ast.value.block() && ast.sourcepos.start.line <= line && ast.sourcepos.end.line >= line
+        });
+        match next {
+            Some(child) => {
+                let ast = child.data.borrow();
+                blocks.push(SourceBlock::from_sourcepos(
+                    ast.sourcepos,
+                    kind_of(&ast.value),
+                ));
+                drop(ast);
+                node = child;
+            }
+            None => break,
+        }
+    }
+    blocks.reverse();
+    blocks
+}
+
+/// Parse a `data-sourcepos` attribute back into a source range.
+pub fn parse_sourcepos(sourcepos: &str) -> Option<SourceBlock> {
+    let (start, end) = sourcepos.split_once('-')?;
+    let (start_line, start_column) = start.split_once(':')?;
+    let (end_line, end_column) = end.split_once(':')?;
+    Some(SourceBlock {
+        start_line: start_line.trim().parse().ok()?,
+        start_column: start_column.trim().parse().ok()?,
+        end_line: end_line.trim().parse().ok()?,
+        end_column: end_column.trim().parse().ok()?,
+        sourcepos: sourcepos.trim().to_string(),
+        kind: String::new(),
+    })
+}
+
+fn kind_of(value: &NodeValue) -> &'static str {
+    match value {
+        NodeValue::Heading(_) => "heading",
+        NodeValue::Paragraph => "paragraph",
+        NodeValue::List(_) => "list",
+        NodeValue::Item(_) | NodeValue::TaskItem(_) => "item",
+        NodeValue::BlockQuote => "blockquote",
+        NodeValue::CodeBlock(_) => "code",
+        NodeValue::HtmlBlock(_) => "html",
+        NodeValue::ThematicBreak => "rule",
+        NodeValue::Table(_) => "table",
+        NodeValue::TableRow(_) => "row",
+        NodeValue::TableCell => "cell",
+        NodeValue::FootnoteDefinition(_) => "footnote",
+        _ => "block",
+    }
+}
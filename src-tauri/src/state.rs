@@ -1,3 +1,13 @@
+use crate::backlinks::BacklinkIndex;
+use crate::file_ops::FileOp;
+use crate::folder_tree::{FolderScan, GitStatusCache, PartialScan};
+use crate::links::PageIndex;
+use crate::quick_open::TitleCache;
+use crate::render_cache::RenderCache;
+use crate::render_options::RenderOptions;
+use crate::replace::ReplaceBatch;
+use crate::scan_options::ScanOptions;
+use crate::search_index::IndexSlot;
 use notify::RecommendedWatcher;
 use portable_pty::MasterPty;
 use std::collections::HashMap;
@@ -13,7 +23,36 @@ pub struct TerminalSession {
 pub struct AppState {
     pub current_file: Mutex<Option<PathBuf>>,
     pub current_folder: Mutex<Option<PathBuf>>,
+    /// Markdown files of the current folder, counted in the background.
+    pub folder_scan: Arc<Mutex<Option<FolderScan>>>,
+    /// Files the running folder scan has found so far.
+    pub partial_scan: Arc<Mutex<Option<PartialScan>>>,
+    /// Bumped when a folder scan starts or is cancelled.
+    pub folder_scan_generation: Arc<Mutex<u64>>,
+    /// Git statuses of folders being listed a page at a time.
+    pub git_statuses: Mutex<GitStatusCache>,
+    /// Link graph of the current folder, built in the background.
+    pub backlinks: Arc<Mutex<Option<BacklinkIndex>>>,
+    /// Wiki-link targets of the current folder, dropped by the folder watcher
+    /// when files come and go.
+    pub page_index: Arc<Mutex<Option<Arc<PageIndex>>>>,
     pub watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Recursive watcher on the current folder.
+    pub folder_watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Sidebar file operations that can be undone, most recent last.
+    pub file_ops: Mutex<Vec<FileOp>>,
+    pub render_options: Mutex<RenderOptions>,
+    pub render_cache: Mutex<RenderCache>,
+    /// Bumped on every document render; streaming stops when it changes.
+    pub render_generation: Arc<Mutex<u64>>,
+    pub scan_options: Mutex<ScanOptions>,
+    /// Full-text index of the current folder, or the changes queued while it
+    /// is opened and caught up.
+    pub search_index: Mutex<Option<IndexSlot>>,
+    /// Front-matter titles for quick open, kept current by the folder watcher.
+    pub title_cache: Mutex<TitleCache>,
+    /// Undo journal of replace-in-files batches, most recent last.
+    pub replace_journal: Mutex<Vec<ReplaceBatch>>,
     pub github_token: Mutex<Option<String>>,
     pub app_data_dir: Mutex<Option<PathBuf>>,
     pub tts_openai_key: Mutex<Option<String>>,
@@ -29,7 +68,22 @@ impl AppState {
         Self {
             current_file: Mutex::new(None),
             current_folder: Mutex::new(None),
+            folder_scan: Arc::new(Mutex::new(None)),
+            partial_scan: Arc::new(Mutex::new(None)),
+            folder_scan_generation: Arc::new(Mutex::new(0)),
+            git_statuses: Mutex::new(HashMap::new()),
+            backlinks: Arc::new(Mutex::new(None)),
+            page_index: Arc::new(Mutex::new(None)),
             watcher: Mutex::new(None),
+            folder_watcher: Mutex::new(None),
+            file_ops: Mutex::new(Vec::new()),
+            render_options: Mutex::new(RenderOptions::default()),
+            render_cache: Mutex::new(RenderCache::default()),
+            render_generation: Arc::new(Mutex::new(0)),
+            scan_options: Mutex::new(ScanOptions::default()),
+            search_index: Mutex::new(None),
+            title_cache: Mutex::new(TitleCache::default()),
+            replace_journal: Mutex::new(Vec::new()),
             github_token: Mutex::new(None),
             app_data_dir: Mutex::new(None),
             tts_openai_key: Mutex::new(None),
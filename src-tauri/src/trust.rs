@@ -0,0 +1,96 @@
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+const TRUST_FILE: &str = "trusted_folders.json";
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+struct TrustData {
+    trusted_folders: Vec<String>,
+}
+
+/// Whether a path is covered by a trusted folder.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustStatus {
+    pub trusted: bool,
+    /// The trusted folder containing the path, if any.
+    pub folder: Option<String>,
+}
+
+fn load_trust(state: &AppState) -> TrustData {
+    let data_dir = state.app_data_dir.lock().unwrap();
+    if let Some(ref dir) = *data_dir {
+        if let Ok(contents) = fs::read_to_string(dir.join(TRUST_FILE)) {
+            if let Ok(data) = serde_json::from_str::<TrustData>(&contents) {
+                return data;
+            }
+        }
+    }
+    TrustData::default()
+}
+
+fn save_trust(state: &AppState, data: &TrustData) -> Result<(), String> {
+    let data_dir = state.app_data_dir.lock().unwrap();
+    let dir = data_dir
+        .as_ref()
+        .ok_or("App data directory is not available")?;
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create data directory: {}", e))?;
+    let json = serde_json::to_string_pretty(data)
+        .map_err(|e| format!("Failed to serialize trusted folders: {}", e))?;
+    fs::write(dir.join(TRUST_FILE), json)
+        .map_err(|e| format!("Failed to save trusted folders: {}", e))
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The trusted folder containing `path`. Documents there render their raw
+/// HTML as written; everything else is sanitized.
+fn trusted_folder(state: &AppState, path: &Path) -> Option<String> {
+    let path = canonical(path);
+    load_trust(state)
+        .trusted_folders
+        .into_iter()
+        .find(|folder| path.starts_with(folder))
+}
+
+pub fn is_trusted(state: &AppState, path: &Path) -> bool {
+    trusted_folder(state, path).is_some()
+}
+
+#[tauri::command]
+pub fn get_trust_status(path: String, state: State<'_, AppState>) -> TrustStatus {
+    let folder = trusted_folder(&state, Path::new(&path));
+    TrustStatus {
+        trusted: folder.is_some(),
+        folder,
+    }
+}
+
+#[tauri::command]
+pub fn trust_folder(path: String, state: State<'_, AppState>) -> Result<(), String> {
+    let folder = canonical(Path::new(&path));
+    if !folder.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    let folder = folder.to_string_lossy().to_string();
+    let mut data = load_trust(&state);
+    if !data.trusted_folders.contains(&folder) {
+        data.trusted_folders.push(folder);
+    }
+    save_trust(&state, &data)
+}
+
+/// Stop trusting `path`, and any trusted folder that contains it.
+#[tauri::command]
+pub fn untrust_folder(path: String, state: State<'_, AppState>) -> Result<(), String> {
+    let path = canonical(Path::new(&path));
+    let mut data = load_trust(&state);
+    data.trusted_folders
+        .retain(|folder| !path.starts_with(folder));
+    save_trust(&state, &data)
+}
@@ -1,7 +1,9 @@
+use crate::include::{include_scope, IncludeScope};
+use crate::render_options::RenderOptions;
 use crate::state::AppState;
 use base64::Engine;
 use comrak::nodes::NodeValue;
-use comrak::{parse_document, Arena, Options};
+use comrak::{parse_document, Arena};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
@@ -128,18 +130,21 @@ pub async fn tts_remove_key(
 
 // ===== Markdown to Speech Text =====
 
-fn markdown_to_speech_text(markdown: &str, read_code_blocks: bool) -> String {
+fn markdown_to_speech_text(
+    markdown: &str,
+    render_options: &RenderOptions,
+    includes: Option<&IncludeScope>,
+    read_code_blocks: bool,
+) -> String {
     let arena = Arena::new();
-    let mut options = Options::default();
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.footnotes = true;
-    options.extension.shortcodes = true;
-    options.parse.smart = true;
-
-    let root = parse_document(&arena, markdown, &options);
+    let options = render_options.comrak_options();
+
+    let markdown = render_options.prepare(markdown);
+    let root = parse_document(&arena, &markdown, &options);
+    if let Some(scope) = includes {
+        crate::include::expand_includes(&arena, root, &markdown, scope, render_options);
+    }
+    crate::markdown::render_highlights(&arena, root);
     let mut output = String::new();
     collect_speech_text(root, &mut output, read_code_blocks);
     // Clean up excessive newlines
@@ -176,6 +181,8 @@ fn collect_speech_text<'a>(
             }
             output.push_str("\n\n");
         }
+        // `[TOC]` markers expand to a table of contents; nothing to read
+        NodeValue::Paragraph if crate::outline::is_toc_marker(node) => {}
         NodeValue::Paragraph => {
             for child in node.children() {
                 collect_speech_text(child, output, read_code_blocks);
@@ -194,6 +201,12 @@ fn collect_speech_text<'a>(
         NodeValue::Code(code) => {
             output.push_str(&code.literal);
         }
+        NodeValue::Math(math) => {
+            output.push_str(&math.literal);
+        }
+        NodeValue::CodeBlock(cb) if crate::diagrams::DiagramKind::from_info(&cb.info).is_some() => {
+            output.push_str("Diagram.\n\n");
+        }
         NodeValue::CodeBlock(cb) => {
             if read_code_blocks {
                 output.push_str(&cb.literal);
@@ -203,7 +216,10 @@ fn collect_speech_text<'a>(
             output.push_str("\n\n");
         }
         NodeValue::BlockQuote => {
-            output.push_str("Quote: ");
+            match crate::alerts::take_alert_marker(node) {
+                Some(alert) => output.push_str(&format!("{}: ", alert.title)),
+                None => output.push_str("Quote: "),
+            }
             for child in node.children() {
                 collect_speech_text(child, output, read_code_blocks);
             }
@@ -252,6 +268,26 @@ fn collect_speech_text<'a>(
                 collect_speech_text(child, output, read_code_blocks);
             }
         }
+        NodeValue::Superscript | NodeValue::Subscript => {
+            for child in node.children() {
+                collect_speech_text(child, output, read_code_blocks);
+            }
+        }
+        NodeValue::DescriptionList => {
+            for child in node.children() {
+                collect_speech_text(child, output, read_code_blocks);
+            }
+            output.push('\n');
+        }
+        NodeValue::DescriptionTerm => {
+            // Read the term as its own sentence before its details
+            let mut term = String::new();
+            for child in node.children() {
+                collect_speech_text(child, &mut term, read_code_blocks);
+            }
+            output.push_str(term.trim_end());
+            output.push_str(".\n\n");
+        }
         NodeValue::ThematicBreak => {
             output.push_str("\n\n");
         }
@@ -540,6 +576,7 @@ pub struct TtsGenerateResult {
 #[tauri::command]
 pub async fn tts_generate(
     markdown: String,
+    path: Option<String>,
     config: TtsConfig,
     app_handle: AppHandle,
     state: State<'_, AppState>,
@@ -548,7 +585,16 @@ pub async fn tts_generate(
     *state.tts_cancel_flag.lock().unwrap() = false;
 
     let read_code_blocks = config.read_code_blocks.unwrap_or(false);
-    let speech_text = markdown_to_speech_text(&markdown, read_code_blocks);
+    let render_options = state.render_options.lock().unwrap().clone();
+    let includes = path
+        .as_deref()
+        .and_then(|path| include_scope(&state, Path::new(path)));
+    let speech_text = markdown_to_speech_text(
+        &markdown,
+        &render_options,
+        includes.as_ref(),
+        read_code_blocks,
+    );
 
     if speech_text.is_empty() {
         return Err("No text to speak.".into());
@@ -1,22 +1,46 @@
-use comrak::nodes::{ListType, NodeShortCode, NodeValue, TableAlignment};
-use comrak::{parse_document, Arena, Options};
+use crate::diagrams::{render_diagram, Diagram, DiagramKind};
+use crate::front_matter::DocumentMetadata;
+use crate::highlight::TokenClass;
+use crate::include::IncludeScope;
+use crate::render_options::RenderOptions;
+use comrak::nodes::{AstNode, ListType, NodeShortCode, NodeValue, TableAlignment};
+use comrak::{parse_document, Arena};
 use std::collections::HashMap;
 use std::path::Path;
 
-/// Convert markdown text to Typst markup.
-pub fn markdown_to_typst(markdown: &str, base_dir: &Path) -> String {
-    let markdown = crate::markdown::preprocess_markdown(markdown);
+/// Convert markdown text to Typst markup, expanding includes when an include
+/// scope is given. Images resolve relative to `source_path`. Diagrams only go
+/// to external renderers for `trusted` documents.
+pub fn markdown_to_typst(
+    markdown: &str,
+    source_path: &Path,
+    includes: Option<&IncludeScope>,
+    trusted: bool,
+    render_options: &RenderOptions,
+) -> String {
+    let base_dir = source_path.parent().unwrap_or(Path::new("/"));
+    let markdown = render_options.prepare(markdown);
     let arena = Arena::new();
-    let mut options = Options::default();
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.footnotes = true;
-    options.extension.shortcodes = true;
-    options.parse.smart = true;
+    let options = render_options.comrak_options();
 
     let root = parse_document(&arena, &markdown, &options);
+    if let Some(scope) = includes {
+        crate::include::expand_includes(&arena, root, &markdown, scope, render_options);
+    }
+    if !render_options.raw_html {
+        crate::markdown::strip_raw_html(root);
+    }
+    crate::markdown::render_highlights(&arena, root);
+    render_diagrams(root, trusted);
+    if !render_options.hard_breaks {
+        // CommonMark soft breaks are spaces
+        for node in root.descendants() {
+            let mut ast = node.data.borrow_mut();
+            if matches!(ast.value, NodeValue::SoftBreak) {
+                ast.value = NodeValue::Text(" ".to_string());
+            }
+        }
+    }
 
     // First pass: collect footnote definitions
     let mut footnotes: HashMap<String, String> = HashMap::new();
@@ -37,6 +61,52 @@ pub fn markdown_to_typst(markdown: &str, base_dir: &Path) -> String {
     out
 }
 
+/// Build the document metadata and a title page from front matter.
+/// Returns an empty string when there is no title to show.
+pub fn metadata_to_typst(meta: &DocumentMetadata) -> String {
+    let title = match &meta.title {
+        Some(title) => title,
+        None => return String::new(),
+    };
+
+    let mut out = format!("#set document(title: {}", typst_string(title));
+    if let Some(author) = &meta.author {
+        out.push_str(&format!(", author: {}", typst_string(author)));
+    }
+    if !meta.tags.is_empty() {
+        let keywords: Vec<String> = meta.tags.iter().map(|t| typst_string(t)).collect();
+        out.push_str(&format!(", keywords: ({},)", keywords.join(", ")));
+    }
+    out.push_str(")\n\n");
+
+    // Values go in as strings, so brackets and markup in them stay literal
+    out.push_str("#align(center + horizon)[\n");
+    out.push_str(&format!(
+        "  #text(size: 2.2em, weight: \"bold\", {})\n",
+        typst_string(title)
+    ));
+    if let Some(author) = &meta.author {
+        out.push_str(&format!(
+            "  #v(1em)\n  #text(size: 1.2em, {})\n",
+            typst_string(author)
+        ));
+    }
+    if let Some(date) = &meta.date {
+        out.push_str(&format!(
+            "  #v(0.4em)\n  #text(fill: luma(100), {})\n",
+            typst_string(date)
+        ));
+    }
+    if !meta.tags.is_empty() {
+        out.push_str(&format!(
+            "  #v(0.8em)\n  #text(size: 0.9em, fill: luma(120), {})\n",
+            typst_string(&meta.tags.join(" · "))
+        ));
+    }
+    out.push_str("]\n#pagebreak()\n\n");
+    out
+}
+
 fn render_children<'a>(
     node: &'a comrak::arena_tree::Node<'a, std::cell::RefCell<comrak::nodes::Ast>>,
     out: &mut String,
@@ -69,6 +139,9 @@ fn render_node<'a>(
             render_children(node, out, base_dir, footnotes);
             out.push_str("\n\n");
         }
+        NodeValue::Paragraph if crate::outline::is_toc_marker(node) => {
+            out.push_str("#outline()\n\n");
+        }
         NodeValue::Paragraph => {
             drop(val);
             // Check if parent is a list item — don't add extra blank line
@@ -101,6 +174,18 @@ fn render_node<'a>(
             render_children(node, out, base_dir, footnotes);
             out.push(']');
         }
+        NodeValue::Superscript => {
+            drop(val);
+            out.push_str("#super[");
+            render_children(node, out, base_dir, footnotes);
+            out.push(']');
+        }
+        NodeValue::Subscript => {
+            drop(val);
+            out.push_str("#sub[");
+            render_children(node, out, base_dir, footnotes);
+            out.push(']');
+        }
         NodeValue::Code(code) => {
             let literal = &code.literal;
             // Use enough backticks to not conflict with content
@@ -109,16 +194,28 @@ fn render_node<'a>(
             out.push_str(literal);
             out.push_str(ticks);
         }
+        NodeValue::CodeBlock(cb) if cb.info.split_whitespace().next() == Some("math") => {
+            out.push_str(&crate::math::latex_to_typst(&cb.literal, true));
+            out.push_str("\n\n");
+        }
         NodeValue::CodeBlock(cb) => {
             let lang = cb.info.split_whitespace().next().unwrap_or("");
-            out.push_str("```");
-            out.push_str(lang);
-            out.push('\n');
-            out.push_str(&cb.literal);
-            if !cb.literal.ends_with('\n') {
+            if let Some(lines) = crate::highlight::highlight_tokens(&cb.literal, lang) {
+                out.push_str(&highlighted_codeblock(&lines));
+                out.push_str("\n\n");
+            } else {
+                out.push_str("```");
+                out.push_str(lang);
                 out.push('\n');
+                out.push_str(&cb.literal);
+                if !cb.literal.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str("```\n\n");
             }
-            out.push_str("```\n\n");
+        }
+        NodeValue::Math(math) => {
+            out.push_str(&crate::math::latex_to_typst(&math.literal, math.display_math));
         }
         NodeValue::Link(link) => {
             let url = link.url.clone();
@@ -179,12 +276,39 @@ fn render_node<'a>(
             let indented = indent_continuation(body, "  ");
             out.push_str(&format!("#task({checked_str})[{indented}]\n"));
         }
+        NodeValue::DescriptionList => {
+            drop(val);
+            render_children(node, out, base_dir, footnotes);
+            if !out.ends_with("\n\n") {
+                out.push('\n');
+            }
+        }
+        NodeValue::DescriptionItem(_) => {
+            drop(val);
+            let mut term = String::new();
+            let mut details = String::new();
+            for child in node.children() {
+                let is_term = matches!(child.data.borrow().value, NodeValue::DescriptionTerm);
+                let target = if is_term { &mut term } else { &mut details };
+                render_children(child, target, base_dir, footnotes);
+            }
+            let details = indent_continuation(details.trim(), "  ");
+            out.push_str(&format!("/ {}: {details}\n", term.trim()));
+        }
         NodeValue::BlockQuote => {
             drop(val);
+            let alert = crate::alerts::take_alert_marker(node);
             let mut body = String::new();
             render_children(node, &mut body, base_dir, footnotes);
             let body = body.trim();
-            out.push_str(&format!("#blockquote[{body}]\n\n"));
+            match alert {
+                Some(alert) => out.push_str(&format!(
+                    "#callout(\"{}\", {})[{body}]\n\n",
+                    alert.kind.name(),
+                    typst_string(&alert.title)
+                )),
+                None => out.push_str(&format!("#blockquote[{body}]\n\n")),
+            }
         }
         NodeValue::Table(table) => {
             let num_cols = table.num_columns;
@@ -243,8 +367,8 @@ fn render_node<'a>(
             out.push_str("#hrule()\n\n");
         }
         NodeValue::SoftBreak => {
-            // Match the HTML renderer's `hardbreaks = true` setting so a single
-            // newline becomes a real line break in the PDF too.
+            // Soft breaks only survive to here with `hard_breaks` on, so a
+            // single newline becomes a real line break as in the preview.
             out.push_str("\\\n");
         }
         NodeValue::LineBreak => {
@@ -259,6 +383,8 @@ fn render_node<'a>(
                 out.push_str(&format!("#footnote[{content}]"));
             }
         }
+        // Typst produced by earlier passes, such as rendered diagrams
+        NodeValue::Raw(raw) => out.push_str(raw),
         NodeValue::HtmlBlock(html) => {
             let literal = html.literal.trim().to_lowercase();
             if literal == "<br>" || literal == "<br/>" || literal == "<br />" {
@@ -280,6 +406,10 @@ fn render_node<'a>(
                 out.push_str("#sub[");
             } else if h == "</sub>" {
                 out.push(']');
+            } else if h == "<mark>" {
+                out.push_str("#highlight[");
+            } else if h == "</mark>" {
+                out.push(']');
             }
             // Otherwise skip inline HTML
         }
@@ -322,6 +452,54 @@ fn indent_continuation(text: &str, prefix: &str) -> String {
     result
 }
 
+/// Emit a highlighted code block as `#codeblock(...)`: an array of lines,
+/// each an array of `(text, class)` tokens coloured by the preamble.
+fn highlighted_codeblock(lines: &[Vec<(Option<TokenClass>, String)>]) -> String {
+    let mut out = String::from("#codeblock((");
+    for line in lines {
+        out.push('(');
+        for (class, text) in line {
+            let class = class.map(TokenClass::css_class).unwrap_or("");
+            out.push_str(&format!("({}, \"{}\"), ", typst_string(text), class));
+        }
+        out.push_str("), ");
+    }
+    out.push_str("))");
+    out
+}
+
+/// Quote a string literal for Typst code.
+pub(crate) fn typst_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Replace diagram fences that render with raw Typst embedding them; the
+/// rest stay code blocks.
+fn render_diagrams<'a>(root: &'a AstNode<'a>, trusted: bool) {
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let diagram = match &ast.value {
+            NodeValue::CodeBlock(cb) => DiagramKind::from_info(&cb.info)
+                .and_then(|kind| render_diagram(kind, &cb.literal, trusted)),
+            _ => None,
+        };
+        if let Some(diagram) = diagram {
+            ast.value = NodeValue::Raw(format!("{}\n\n", typst_diagram(&diagram)));
+        }
+    }
+}
+
+/// Embed a rendered diagram at its natural size, shrunk to fit the page.
+fn typst_diagram(diagram: &Diagram) -> String {
+    // SVG sizes are CSS pixels: 96 per inch, Typst points are 72 per inch.
+    let width = diagram.width * 0.75;
+    format!(
+        "#align(center, layout(size => image(bytes({}), format: \"svg\", \
+         width: calc.min({width}pt, size.width))))",
+        typst_string(&diagram.svg)
+    )
+}
+
 /// Escape characters that have special meaning in Typst.
 fn escape_typst(text: &str) -> String {
     let mut out = String::with_capacity(text.len());
@@ -336,3 +514,26 @@ fn escape_typst(text: &str) -> String {
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_page_passes_metadata_as_strings() {
+        let meta = DocumentMetadata {
+            title: Some("Notes ] [draft \"v2\"".to_string()),
+            author: Some("A. [Writer]".to_string()),
+            date: Some("2024-01-02".to_string()),
+            tags: vec!["a]".to_string(), "b".to_string()],
+            ..Default::default()
+        };
+        let out = metadata_to_typst(&meta);
+        assert!(out.contains(r#"#text(size: 2.2em, weight: "bold", "Notes ] [draft \"v2\"")"#));
+        assert!(out.contains(r#"#text(size: 1.2em, "A. [Writer]")"#));
+        assert!(out.contains(r#"#text(fill: luma(100), "2024-01-02")"#));
+        assert!(out.contains(r#"#text(size: 0.9em, fill: luma(120), "a] · b")"#));
+        // The only content block is the one around the title page
+        assert_eq!(out.matches("[\n").count(), 1);
+    }
+}
@@ -1,10 +1,57 @@
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc;
+use crate::commands::is_markdown_file;
+use crate::folder_tree::FolderScan;
+use crate::links::shared_pages;
+use crate::scan_options::ScanOptions;
+use crate::state::AppState;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 
-pub fn watch_file(app_handle: AppHandle, path: &Path) -> Result<RecommendedWatcher, String> {
+/// Quiet time after which a batch of folder changes is reported.
+const FOLDER_DEBOUNCE: Duration = Duration::from_millis(300);
+/// Longest a batch is held back while changes keep coming.
+const FOLDER_BATCH_LIMIT: Duration = Duration::from_secs(2);
+
+/// Changes to the open folder's tree, emitted as `folder-changed`.
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeDelta {
+    /// New markdown files.
+    pub added: Vec<String>,
+    /// Markdown files and folders that are gone.
+    pub removed: Vec<String>,
+    /// Markdown files that were moved or renamed.
+    pub renamed: Vec<RenamedPath>,
+    /// New markdown count of every folder whose count changed; 0 means the
+    /// folder no longer has any. Empty until the folder scan has finished.
+    pub counts: HashMap<String, usize>,
+}
+
+impl TreeDelta {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct RenamedPath {
+    pub from: String,
+    pub to: String,
+}
+
+/// Watch a document and the files it includes. Changes to any of them emit
+/// `file-changed` with the document's path so the whole document re-renders.
+pub fn watch_file(
+    app_handle: AppHandle,
+    path: &Path,
+    includes: &[PathBuf],
+) -> Result<RecommendedWatcher, String> {
     let (tx, rx) = mpsc::channel();
 
     let mut watcher = RecommendedWatcher::new(tx, Config::default())
@@ -13,6 +60,11 @@ pub fn watch_file(app_handle: AppHandle, path: &Path) -> Result<RecommendedWatch
     watcher
         .watch(path, RecursiveMode::NonRecursive)
         .map_err(|e| format!("Failed to watch file: {}", e))?;
+    for include in includes {
+        if let Err(e) = watcher.watch(include, RecursiveMode::NonRecursive) {
+            eprintln!("Warning: Could not watch {}: {}", include.display(), e);
+        }
+    }
 
     let path_owned = path.to_path_buf();
     std::thread::spawn(move || {
@@ -42,3 +94,290 @@ pub fn watch_file(app_handle: AppHandle, path: &Path) -> Result<RecommendedWatch
 
     Ok(watcher)
 }
+
+/// Watch the open folder recursively. Markdown files and folders that appear,
+/// disappear or are renamed are collected until changes settle and emitted as
+/// one `folder-changed` event. Ignored and hidden paths are left out. The
+/// folder scan's file list and counts, the search index, quick open titles
+/// and the backlink index are updated to match, and the folder's page index
+/// is rebuilt.
+pub fn watch_folder(
+    app_handle: AppHandle,
+    root: &Path,
+    scan_options: ScanOptions,
+    scan: Arc<Mutex<Option<FolderScan>>>,
+) -> Result<RecommendedWatcher, String> {
+    let (tx, rx) = mpsc::channel();
+
+    let mut watcher = RecommendedWatcher::new(tx, Config::default())
+        .map_err(|e| format!("Failed to create watcher: {}", e))?;
+
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch folder: {}", e))?;
+
+    let root = root.to_path_buf();
+    std::thread::spawn(move || loop {
+        let Ok(first) = rx.recv() else {
+            break; // Channel closed, watcher dropped
+        };
+        let started = Instant::now();
+        let mut events = vec![first];
+        while started.elapsed() < FOLDER_BATCH_LIMIT {
+            match rx.recv_timeout(FOLDER_DEBOUNCE) {
+                Ok(event) => events.push(event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let events: Vec<Event> = events.into_iter().flatten().collect();
+        let edited = edited_files(&root, &scan_options, &events);
+        let delta = tree_delta(&root, &scan_options, &scan, events.into_iter());
+        let state = app_handle.state::<AppState>();
+        let mut index = state.search_index.lock().unwrap();
+        if let Some(index) = index.as_mut().filter(|index| index.root() == root) {
+            if let Err(e) = index.apply(&delta, &edited) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+        drop(index);
+        state.title_cache.lock().unwrap().apply(&delta, &edited);
+        if !delta.is_empty() {
+            // Wiki-link targets may have come or gone
+            let mut pages = state.page_index.lock().unwrap();
+            if pages.as_ref().is_some_and(|pages| pages.root() == root) {
+                *pages = None;
+            }
+        }
+        if !delta.is_empty() || !edited.is_empty() {
+            let has_backlinks = state
+                .backlinks
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|index| index.root() == root);
+            if has_backlinks {
+                let pages = shared_pages(&state.page_index, &root, &scan_options);
+                let mut backlinks = state.backlinks.lock().unwrap();
+                if let Some(index) = backlinks.as_mut().filter(|index| index.root() == root) {
+                    index.apply(pages, &delta, &edited);
+                }
+            }
+        }
+        if !delta.is_empty() {
+            let _ = app_handle.emit("folder-changed", delta);
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// Visible markdown files written to in a batch of raw events, including ones
+/// replaced by renaming another file over them.
+fn edited_files(root: &Path, scan_options: &ScanOptions, events: &[Event]) -> Vec<PathBuf> {
+    let mut visible = Visibility::new(root, scan_options);
+    let edited: BTreeSet<&PathBuf> = events
+        .iter()
+        .flat_map(|event| match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => {
+                &event.paths[..]
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => &[],
+            // A rename's destination is its last path
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                &event.paths[event.paths.len().saturating_sub(1)..]
+            }
+            _ => &[],
+        })
+        .filter(|path| is_markdown_file(&path.to_string_lossy()) && path.is_file())
+        .filter(|path| visible.contains(path))
+        .collect();
+    edited.into_iter().cloned().collect()
+}
+
+/// Turn a batch of raw events into tree changes. What happened in between is
+/// settled by whether each path exists once the batch is over.
+fn tree_delta(
+    root: &Path,
+    scan_options: &ScanOptions,
+    scan: &Mutex<Option<FolderScan>>,
+    events: impl Iterator<Item = Event>,
+) -> TreeDelta {
+    let mut touched = BTreeSet::new();
+    let mut moves = Vec::new();
+    for event in events {
+        let in_git = event.paths.iter().any(|path| {
+            path.strip_prefix(root)
+                .is_ok_and(|p| p.iter().any(|c| c == ".git"))
+        });
+        if in_git {
+            continue;
+        }
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                let mut paths = event.paths.into_iter();
+                moves.extend(paths.next().zip(paths.next()));
+            }
+            EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Name(_)) => {
+                touched.extend(event.paths);
+            }
+            _ => {}
+        }
+    }
+
+    let mut visible = Visibility::new(root, scan_options);
+    let mut renamed = Vec::new();
+    for (from, to) in moves {
+        let is_file_rename = to.is_file()
+            && !from.exists()
+            && is_markdown_file(&from.to_string_lossy())
+            && is_markdown_file(&to.to_string_lossy())
+            && visible.contains(&to);
+        if is_file_rename {
+            renamed.push((from, to));
+        } else {
+            touched.insert(from);
+            touched.insert(to);
+        }
+    }
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for path in touched {
+        if !path.exists() {
+            removed.push(path);
+        } else if !visible.contains(&path) {
+            continue;
+        } else if path.is_dir() {
+            // A folder moved in or created with files already in it
+            let files = scan_options.walker_at(root, &path).build().flatten();
+            added.extend(
+                files
+                    .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+                    .filter(|e| is_markdown_file(&e.file_name().to_string_lossy()))
+                    .map(|e| e.into_path()),
+            );
+        } else if is_markdown_file(&path.to_string_lossy()) {
+            added.push(path);
+        }
+    }
+
+    let mut counts = HashMap::new();
+    let mut scan = scan.lock().unwrap();
+    if let Some(scan) = scan.as_mut().filter(|scan| scan.root == root) {
+        // Only report what the tree could have shown or could be missing
+        added.retain(|path| !scan.files.contains(path));
+        removed.retain(|path| scan.contains(path));
+        renamed.retain(|(from, _)| scan.files.contains(from));
+        let mut gone = removed.clone();
+        let mut new = added.clone();
+        for (from, to) in &renamed {
+            gone.push(from.clone());
+            new.push(to.clone());
+        }
+        counts = scan.apply(&new, &gone);
+    }
+
+    let to_string = |path: &Path| path.to_string_lossy().to_string();
+    TreeDelta {
+        added: added.iter().map(|p| to_string(p)).collect(),
+        removed: removed.iter().map(|p| to_string(p)).collect(),
+        renamed: renamed
+            .iter()
+            .map(|(from, to)| RenamedPath {
+                from: to_string(from),
+                to: to_string(to),
+            })
+            .collect(),
+        counts: counts
+            .iter()
+            .map(|(dir, count)| (to_string(dir), *count))
+            .collect(),
+    }
+}
+
+/// Whether paths would be walked by the folder tree, honoring the ignore
+/// files on the way down from the root. Each folder is listed at most once.
+struct Visibility<'a> {
+    root: &'a Path,
+    scan_options: &'a ScanOptions,
+    listed: HashMap<PathBuf, HashSet<PathBuf>>,
+}
+
+impl<'a> Visibility<'a> {
+    fn new(root: &'a Path, scan_options: &'a ScanOptions) -> Self {
+        Visibility {
+            root,
+            scan_options,
+            listed: HashMap::new(),
+        }
+    }
+
+    fn contains(&mut self, path: &Path) -> bool {
+        if path == self.root {
+            return true;
+        }
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        if !path.starts_with(self.root) || !self.contains(parent) {
+            return false;
+        }
+        if !self.listed.contains_key(parent) {
+            let mut walker = self.scan_options.walker_at(self.root, parent);
+            walker.max_depth(Some(1));
+            let children = walker
+                .build()
+                .flatten()
+                .filter(|entry| entry.depth() == 1)
+                .map(|entry| entry.into_path())
+                .collect();
+            self.listed.insert(parent.to_path_buf(), children);
+        }
+        self.listed[parent].contains(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+    use notify::event::{CreateKind, DataChange};
+
+    /// The events of writing a temporary file and renaming it over `target`,
+    /// as `replace::write_all` saves.
+    fn rename_over(target: &Path) -> Vec<Event> {
+        let name = target.file_name().unwrap().to_string_lossy();
+        let temp = target.with_file_name(format!(".{}.mre-replace", name));
+        let rename = |mode| EventKind::Modify(ModifyKind::Name(mode));
+        vec![
+            Event::new(EventKind::Create(CreateKind::File)).add_path(temp.clone()),
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(temp.clone()),
+            Event::new(rename(RenameMode::From)).add_path(temp.clone()),
+            Event::new(rename(RenameMode::To)).add_path(target.to_path_buf()),
+            Event::new(rename(RenameMode::Both))
+                .add_path(temp)
+                .add_path(target.to_path_buf()),
+        ]
+    }
+
+    #[test]
+    fn renaming_over_a_file_edits_it() {
+        let dir = TempDir::new("watch-rename");
+        let target = dir.write("notes/plan.md", "# Plan");
+        let events = rename_over(&target);
+        let edited = edited_files(dir.path(), &ScanOptions::default(), &events);
+        assert_eq!(edited, vec![target]);
+    }
+
+    #[test]
+    fn hidden_files_are_not_edits() {
+        let dir = TempDir::new("watch-hidden");
+        let target = dir.write(".drafts/plan.md", "# Plan");
+        let events = rename_over(&target);
+        let edited = edited_files(dir.path(), &ScanOptions::default(), &events);
+        assert!(edited.is_empty());
+    }
+}
@@ -91,6 +91,12 @@ let editorFindCurrentIndex = -1;
 
 // Search in Files state
 let searchInFilesMode = false;
+// Toggles of the search-in-files options bar
+let searchOptions = { caseSensitive: false, wholeWord: false, regex: false, ranked: true };
+// Hits from the search index, or null when showing line matches
+let searchIndexHits = null;
+let linkAuditMode = false;
+let linkAuditRemote = false;
 let searchInFilesResults = [];
 
 // Terminal state
@@ -117,17 +123,29 @@ let settings = {
   lineColor: localStorage.getItem("md-line-color") || null,
   bgColor: localStorage.getItem("md-bg-color") || null,
   labelColor: localStorage.getItem("md-label-color") || null,
+  treeSort: localStorage.getItem("md-tree-sort") || "name",
 };
 
 // Folder tree data
-let folderTree = [];
+let folderTree = []; // first page of the open folder's top level
+let folderTreeNext = null; // offset of the next top-level page, if any
+let folderScanId = null;
+let folderCounts = null; // markdown files per folder, once the scan is done
+let treeRenderToken = 0;
 let currentFolderPath = null;
 
+// Document outline
+let outlineOpen = localStorage.getItem("md-outline") === "true";
+
 // Navigation history
 let navHistory = [];
 let navIndex = -1;
 let navNavigating = false; // flag to prevent pushing during back/forward
 
+// Large documents stream in as chunks after open_and_render returns
+let streamingRender = null; // { id, remaining, resolve }
+let earlyRenderChunks = []; // chunks that arrived before their render's result
+
 // ===== DOM References =====
 let els = {};
 
@@ -138,6 +156,8 @@ window.addEventListener("DOMContentLoaded", async () => {
     toolbarTitle: document.getElementById("toolbar-title"),
     sidebar: document.getElementById("sidebar"),
     fileTree: document.getElementById("file-tree"),
+    folderScanStatus: document.getElementById("folder-scan-status"),
+    folderScanText: document.getElementById("folder-scan-text"),
     filterInput: document.getElementById("filter-input"),
     contentArea: document.getElementById("content-area"),
     contentScroll: document.getElementById("content-scroll"),
@@ -147,6 +167,14 @@ window.addEventListener("DOMContentLoaded", async () => {
     btnNavTop: document.getElementById("btn-nav-top"),
     btnNavBottom: document.getElementById("btn-nav-bottom"),
     btnNavRefresh: document.getElementById("btn-nav-refresh"),
+    btnNavOutline: document.getElementById("btn-nav-outline"),
+    metadataPanel: document.getElementById("metadata-panel"),
+    metadataList: document.getElementById("metadata-list"),
+    outlinePanel: document.getElementById("outline-panel"),
+    outlineTree: document.getElementById("outline-tree"),
+    backlinksPanel: document.getElementById("backlinks-panel"),
+    backlinksHeader: document.getElementById("backlinks-header"),
+    backlinksList: document.getElementById("backlinks-list"),
     content: document.getElementById("content"),
     editorContainer: document.getElementById("editor-container"),
     editorLineNumbers: document.getElementById("editor-line-numbers"),
@@ -160,6 +188,7 @@ window.addEventListener("DOMContentLoaded", async () => {
     settingFontValue: document.getElementById("setting-font-value"),
     settingFontFamily: document.getElementById("setting-font-family"),
     settingContentWidth: document.getElementById("setting-content-width"),
+    settingTreeSort: document.getElementById("setting-tree-sort"),
     // Color pickers
     settingHeadingColor: document.getElementById("setting-heading-color"),
     settingParagraphColor: document.getElementById("setting-paragraph-color"),
@@ -202,6 +231,10 @@ window.addEventListener("DOMContentLoaded", async () => {
     gitAuthDot: document.getElementById("git-auth-dot"),
     settingsGithubLabel: document.getElementById("settings-github-label"),
     btnSettingsGithub: document.getElementById("btn-settings-github"),
+    settingsTrustLabel: document.getElementById("settings-trust-label"),
+    btnSettingsTrust: document.getElementById("btn-settings-trust"),
+    sanitizeNotice: document.getElementById("sanitize-notice"),
+    btnTrustFolder: document.getElementById("btn-trust-folder"),
     // TTS
     btnTts: document.getElementById("btn-tts"),
     ttsPlayer: document.getElementById("tts-player"),
@@ -217,6 +250,9 @@ window.addEventListener("DOMContentLoaded", async () => {
     settingTtsSpeed: document.getElementById("setting-tts-speed"),
     settingTtsSpeedValue: document.getElementById("setting-tts-speed-value"),
     settingTtsReadCode: document.getElementById("setting-tts-read-code"),
+    quickOpenOverlay: document.getElementById("quick-open-overlay"),
+    quickOpenInput: document.getElementById("quick-open-input"),
+    quickOpenResults: document.getElementById("quick-open-results"),
     ttsKeyOverlay: document.getElementById("tts-key-overlay"),
     ttsKeyTitle: document.getElementById("tts-key-title"),
     ttsKeyInstructions: document.getElementById("tts-key-instructions"),
@@ -247,6 +283,10 @@ window.addEventListener("DOMContentLoaded", async () => {
     btnEditorReplaceAll: document.getElementById("btn-editor-replace-all"),
     // Search in Files
     btnSearchContents: document.getElementById("btn-search-contents"),
+    searchOptions: document.getElementById("search-options"),
+    searchInclude: document.getElementById("search-include"),
+    searchExclude: document.getElementById("search-exclude"),
+    btnCheckLinks: document.getElementById("btn-check-links"),
   };
 
   // Apply all settings
@@ -300,6 +340,13 @@ window.addEventListener("DOMContentLoaded", async () => {
     applyContentWidth();
   });
 
+  els.settingTreeSort.value = settings.treeSort;
+  els.settingTreeSort.addEventListener("change", (e) => {
+    settings.treeSort = e.target.value;
+    localStorage.setItem("md-tree-sort", settings.treeSort);
+    refreshFolder();
+  });
+
   // Color pickers
   setupColorPicker("setting-heading-color", "headingColor", "md-heading-color");
   setupColorPicker("setting-paragraph-color", "paragraphColor", "md-paragraph-color");
@@ -364,7 +411,15 @@ window.addEventListener("DOMContentLoaded", async () => {
   els.diffVsWorkingCb.addEventListener("change", () => {
     if (diffMode && selectedCommitOid) renderDiff();
   });
-  els.btnEdit.addEventListener("click", enterEditMode);
+  els.btnEdit.addEventListener("click", () => enterEditMode());
+  // Alt+click a rendered block to jump to its source line
+  els.content.addEventListener("click", async (e) => {
+    if (!e.altKey || !currentPath || editMode) return;
+    const el = e.target.closest("[data-sourcepos]");
+    if (!el) return;
+    e.preventDefault();
+    enterEditMode(await previewElementToLine(el));
+  });
   els.btnCancelEdit.addEventListener("click", cancelEdit);
   els.btnSave.addEventListener("click", saveFile);
   els.editor.addEventListener("input", updateEditorLineNumbers);
@@ -393,6 +448,8 @@ window.addEventListener("DOMContentLoaded", async () => {
     if (e.key === "Enter") doSaveToken();
   });
   document.getElementById("btn-auth-logout").addEventListener("click", doLogout);
+  els.btnSettingsTrust.addEventListener("click", toggleFolderTrust);
+  els.btnTrustFolder.addEventListener("click", toggleFolderTrust);
   els.btnSettingsGithub.addEventListener("click", () => {
     closeSettings();
     openAuthModal();
@@ -438,11 +495,39 @@ window.addEventListener("DOMContentLoaded", async () => {
     localStorage.setItem("md-tts-read-code", ttsSettings.readCodeBlocks.toString());
   });
 
+  // Markdown render options live in the backend so every pipeline shares them
+  loadRenderOptions();
+  loadScanOptions();
+
   // TTS key buttons
   document.getElementById("btn-tts-key-openai").addEventListener("click", () => openTtsKeyModal("openai"));
   document.getElementById("btn-tts-key-google").addEventListener("click", () => openTtsKeyModal("google"));
   document.getElementById("btn-tts-key-elevenlabs").addEventListener("click", () => openTtsKeyModal("elevenlabs"));
   document.getElementById("btn-close-tts-key").addEventListener("click", closeTtsKeyModal);
+  els.quickOpenOverlay.addEventListener("click", (e) => {
+    if (e.target === els.quickOpenOverlay) closeQuickOpen();
+  });
+  let quickOpenDebounce = null;
+  els.quickOpenInput.addEventListener("input", () => {
+    clearTimeout(quickOpenDebounce);
+    quickOpenDebounce = setTimeout(runQuickOpen, 80);
+  });
+  els.quickOpenInput.addEventListener("keydown", (e) => {
+    if (e.key === "ArrowDown" || e.key === "ArrowUp") {
+      e.preventDefault();
+      const step = e.key === "ArrowDown" ? 1 : -1;
+      selectQuickOpen(quickOpenSelected + step);
+    } else if (e.key === "Enter") {
+      e.preventDefault();
+      const hit = quickOpenHits[quickOpenSelected];
+      if (hit) { closeQuickOpen(); openFile(hit.filePath); }
+    } else if (e.key === "Escape") {
+      e.preventDefault();
+      e.stopPropagation();
+      closeQuickOpen();
+    }
+  });
+
   els.ttsKeyOverlay.addEventListener("click", (e) => {
     if (e.target === els.ttsKeyOverlay) closeTtsKeyModal();
   });
@@ -465,6 +550,7 @@ window.addEventListener("DOMContentLoaded", async () => {
   document.getElementById("btn-open-folder").addEventListener("click", openFolderDialog);
   document.getElementById("btn-open-file").addEventListener("click", openFileDialog);
   document.getElementById("btn-refresh-folder").addEventListener("click", refreshFolder);
+  document.getElementById("btn-cancel-folder-scan").addEventListener("click", cancelFolderScan);
   document.getElementById("btn-favorites-filter").addEventListener("click", toggleFavoritesFilter);
 
   // ===== Nav bar buttons =====
@@ -472,6 +558,8 @@ window.addEventListener("DOMContentLoaded", async () => {
   els.btnNavForward.addEventListener("click", navForward);
   els.btnNavHome.addEventListener("click", navHome);
   els.btnNavTop.addEventListener("click", () => { els.contentScroll.scrollTop = 0; });
+  els.btnNavOutline.addEventListener("click", toggleOutline);
+  els.btnNavOutline.classList.toggle("active", outlineOpen);
   els.btnNavBottom.addEventListener("click", () => { els.contentScroll.scrollTop = els.contentScroll.scrollHeight; });
   els.btnNavRefresh.addEventListener("click", navRefresh);
 
@@ -522,6 +610,17 @@ window.addEventListener("DOMContentLoaded", async () => {
 
   // ===== Search in Files =====
   els.btnSearchContents.addEventListener("click", toggleSearchInFiles);
+  for (const [id, key] of [["btn-search-case", "caseSensitive"], ["btn-search-word", "wholeWord"], ["btn-search-regex", "regex"], ["btn-search-ranked", "ranked"]]) {
+    const btn = document.getElementById(id);
+    btn.addEventListener("click", () => {
+      searchOptions[key] = !searchOptions[key];
+      btn.classList.toggle("active", searchOptions[key]);
+      rerunSearchInFiles();
+    });
+  }
+  els.searchInclude.addEventListener("change", rerunSearchInFiles);
+  els.searchExclude.addEventListener("change", rerunSearchInFiles);
+  els.btnCheckLinks.addEventListener("click", toggleLinkAudit);
 
   // ===== Filter input =====
   let searchDebounce = null;
@@ -531,7 +630,7 @@ window.addEventListener("DOMContentLoaded", async () => {
       const q = e.target.value;
       searchDebounce = setTimeout(() => {
         if (q.trim()) performSearchInFiles(q.trim());
-        else { searchInFilesResults = []; renderFileTree(); }
+        else { searchInFilesResults = []; searchIndexHits = null; renderFileTree(); }
       }, 300);
     } else {
       filterText = e.target.value.toLowerCase();
@@ -561,6 +660,9 @@ window.addEventListener("DOMContentLoaded", async () => {
     } else if (mod && e.key === "t") {
       e.preventDefault();
       toggleTts();
+    } else if (mod && e.key === "p") {
+      e.preventDefault();
+      openQuickOpen();
     } else if (mod && e.key === "b") {
       e.preventDefault();
       toggleSidebar();
@@ -621,6 +723,33 @@ window.addEventListener("DOMContentLoaded", async () => {
     }
   });
 
+  await listen("folder-scan-progress", (event) => {
+    if (event.payload.scanId !== folderScanId) return;
+    setFolderScanStatus(`Scanning… ${event.payload.found} files found`);
+  });
+  await listen("folder-scan-done", (event) => {
+    if (event.payload.scanId !== folderScanId) return;
+    setFolderScanStatus(null);
+    applyFolderCounts(event.payload.counts);
+    // Quick open only saw the files found so far
+    if (els.quickOpenOverlay.style.display === "flex") runQuickOpen();
+  });
+
+  await listen("folder-changed", (event) => applyTreeDelta(event.payload));
+  // Results read from the files before the index was ready become ranked
+  await listen("search-index-ready", () => {
+    if (searchOptions.ranked) rerunSearchInFiles();
+  });
+
+  await listen("render-chunk", (event) => {
+    const chunk = event.payload;
+    if (streamingRender && chunk.renderId === streamingRender.id) {
+      appendRenderChunk(chunk);
+    } else if (!streamingRender || chunk.renderId > streamingRender.id) {
+      earlyRenderChunks.push(chunk);
+    }
+  });
+
   await listen("open-file", async (event) => {
     if (event.payload) {
       await openFile(event.payload);
@@ -787,25 +916,56 @@ function toHex(color) {
   return "#000000";
 }
 
-// ===== Path Helpers =====
-function resolvePath(path) {
-  const parts = path.split("/");
-  const resolved = [];
-  for (const part of parts) {
-    if (part === "." || part === "") continue;
-    if (part === "..") { resolved.pop(); }
-    else { resolved.push(part); }
+// ===== File Operations =====
+function scrollToAnchor(fragment) {
+  if (!fragment) return;
+  const target = document.getElementById(fragment);
+  if (target) target.scrollIntoView({ block: "start" });
+}
+
+// Chunks of a large document after the first, which open_and_render returns.
+// Resolves true once all have arrived, or false if another render replaced it.
+function streamRenderChunks(result) {
+  cancelRenderStream();
+  if (result.chunkCount <= 1) return Promise.resolve(true);
+  return new Promise((resolve) => {
+    streamingRender = { id: result.renderId, remaining: result.chunkCount - 1, resolve };
+    const early = earlyRenderChunks.filter((c) => c.renderId === result.renderId);
+    earlyRenderChunks = earlyRenderChunks.filter((c) => c.renderId > result.renderId);
+    early.forEach(appendRenderChunk);
+  });
+}
+
+function cancelRenderStream() {
+  if (streamingRender) streamingRender.resolve(false);
+  streamingRender = null;
+}
+
+async function appendRenderChunk(chunk) {
+  const render = streamingRender;
+  // Chunks hold complete blocks; the wrapper is display: contents
+  const wrapper = document.createElement("div");
+  wrapper.className = "render-chunk";
+  wrapper.innerHTML = chunk.html;
+  els.content.appendChild(wrapper);
+  wireContentLinks(wrapper);
+  render.remaining--;
+  if (render.remaining <= 0) {
+    streamingRender = null;
+    render.resolve(true);
   }
-  return "/" + resolved.join("/");
+  await renderMermaidBlocks(wrapper);
+  await highlightCodeBlocks(wrapper);
 }
 
-// ===== File Operations =====
-async function openFile(path) {
+async function openFile(path, fragment = null) {
   try {
     if (findBarOpen) closeFindBar();
     if (editorFindOpen) closeEditorFindBar();
     if (editMode) exitEditMode();
     const wasInHistory = historyMode;
+    // Chunks still arriving for the previous document are dropped
+    cancelRenderStream();
 
     const result = await invoke("open_and_render", { path });
     currentPath = result.filePath;
@@ -825,10 +985,14 @@ async function openFile(path) {
       openFolder(parentDir);
     }
 
-    els.toolbarTitle.textContent = result.fileName;
-    document.title = `MRE - ${result.fileName}`;
+    // Prefer the front matter title when the document has one
+    const displayTitle = result.metadata?.title || result.fileName;
+    els.toolbarTitle.textContent = displayTitle;
+    document.title = `MRE - ${displayTitle}`;
+    renderMetadata(result.metadata);
     els.content.innerHTML = result.html;
     els.emptyState.style.display = "none";
+    els.sanitizeNotice.style.display = result.sanitized && !wasInHistory ? "flex" : "none";
 
     if (wasInHistory) {
       // Keep history open but hide rendered content behind it
@@ -846,34 +1010,16 @@ async function openFile(path) {
     // Mark active in sidebar and expand parent folders
     revealInTree(result.filePath);
 
-    // Handle links: .md links navigate in-app, external/mailto open externally with confirmation
-    els.content.querySelectorAll("a[href]").forEach((a) => {
-      const href = a.getAttribute("href");
-      if (!href) return;
-      if (href.match(/\.(?:md|markdown|mdown|mkd|mkdn|mdx)(?:#.*)?$/i)) {
-        a.addEventListener("click", (e) => {
-          e.preventDefault();
-          const linkPath = href.replace(/#.*$/, "");
-          const parentDir = currentPath.substring(0, currentPath.lastIndexOf("/"));
-          const resolved = resolvePath(parentDir + "/" + linkPath);
-          openFile(resolved);
-        });
-      } else if (href.startsWith("http://") || href.startsWith("https://") || href.startsWith("mailto:")) {
-        a.removeAttribute("target");
-        a.addEventListener("click", async (e) => {
-          e.preventDefault();
-          e.stopImmediatePropagation();
-          const { ask } = await import("@tauri-apps/plugin-dialog");
-          const label = href.startsWith("mailto:") ? "Send email to " + href.slice(7) + "?" : "Open this link in your browser?";
-          const confirmed = await ask(href, { title: label, kind: "info", okLabel: "Open", cancelLabel: "Cancel" });
-          if (confirmed) invoke("open_path", { path: href });
-        });
-      }
-    });
+    wireContentLinks(els.content);
 
     await renderMermaidBlocks(els.content);
     await highlightCodeBlocks();
     refreshGitStatus(result.filePath);
+    loadOutline();
+    loadBacklinks();
+    // Stop if another file was opened while the rest was streaming in
+    if (!(await streamRenderChunks(result))) return;
+    scrollToAnchor(fragment);
 
     // Reload history for the new file if panel is open
     if (wasInHistory) {
@@ -886,15 +1032,50 @@ async function openFile(path) {
   }
 }
 
+function wireContentLinks(container) {
+  // Links the backend resolved to markdown files (including
+  // wiki-links) navigate in-app, external/mailto open externally with confirmation
+  container.querySelectorAll("a[href]").forEach((a) => {
+    const href = a.getAttribute("href");
+    if (!href) return;
+    if (a.dataset.mdPath) {
+      a.addEventListener("click", (e) => {
+        e.preventDefault();
+        const fragment = a.dataset.mdFragment || null;
+        if (a.dataset.mdPath === currentPath) scrollToAnchor(fragment);
+        else openFile(a.dataset.mdPath, fragment);
+      });
+    } else if (a.classList.contains("md-link-unresolved")) {
+      a.addEventListener("click", (e) => e.preventDefault());
+    } else if (href.startsWith("http://") || href.startsWith("https://") || href.startsWith("mailto:")) {
+      a.removeAttribute("target");
+      a.addEventListener("click", async (e) => {
+        e.preventDefault();
+        e.stopImmediatePropagation();
+        const { ask } = await import("@tauri-apps/plugin-dialog");
+        const label = href.startsWith("mailto:") ? "Send email to " + href.slice(7) + "?" : "Open this link in your browser?";
+        const confirmed = await ask(href, { title: label, kind: "info", okLabel: "Open", cancelLabel: "Cancel" });
+        if (confirmed) invoke("open_path", { path: href });
+      });
+    }
+  });
+}
+
 let folderLoading = false;
 async function openFolder(path) {
   if (folderLoading) return;
   folderLoading = true;
   try {
-    folderTree = await invoke("scan_folder", { path });
+    // Markdown counts arrive later through folder-scan-done
+    folderCounts = null;
+    folderScanId = await invoke("start_folder_scan", { path });
+    setFolderScanStatus("Scanning…");
+    const page = await invoke("list_folder", { path, sort: settings.treeSort });
+    folderTree = page.entries;
+    folderTreeNext = page.nextOffset;
     currentFolderPath = path;
     invoke("add_recent_folder", { path }).catch(() => {});
-    renderFileTree();
+    await renderFileTree();
   } catch (err) {
     console.error("Failed to scan folder:", err);
   } finally {
@@ -902,6 +1083,124 @@ async function openFolder(path) {
   }
 }
 
+function setFolderScanStatus(text) {
+  els.folderScanStatus.style.display = text ? "flex" : "none";
+  els.folderScanText.textContent = text || "";
+}
+
+function cancelFolderScan() {
+  folderScanId = null;
+  setFolderScanStatus(null);
+  invoke("cancel_folder_scan").catch(() => {});
+}
+
+// Fill in folder badges and hide folders without markdown once counted
+function applyFolderCounts(counts) {
+  folderCounts = counts;
+  folderTree = folderTree.filter((e) => !e.isDir || counts[e.path]);
+  folderTree.forEach((e) => { if (e.isDir) e.mdCount = counts[e.path]; });
+  if (filterText || showFavoritesOnly) return;
+  els.fileTree.querySelectorAll(".tree-item.dir").forEach((item) => {
+    const count = counts[item.dataset.path];
+    if (count) {
+      item.querySelector(".tree-badge").textContent = count;
+    } else {
+      item.nextElementSibling?.remove();
+      item.remove();
+    }
+  });
+}
+
+// Apply files added, removed or renamed in the open folder to the loaded
+// parts of the tree; folders that haven't been expanded list fresh on expand
+function applyTreeDelta(delta) {
+  if (!currentFolderPath) return;
+  const counts = Object.entries(delta.counts);
+  if (folderCounts) {
+    for (const [dir, count] of counts) {
+      if (count) folderCounts[dir] = count;
+      else delete folderCounts[dir];
+    }
+  }
+  for (const path of delta.removed) removeTreePath(path);
+  for (const { from, to } of delta.renamed) {
+    removeTreePath(from);
+    addTreeFile(to);
+    if (from === currentPath) openFile(to);
+  }
+  for (const path of delta.added) addTreeFile(path);
+  for (const [dir, count] of counts) {
+    if (!count) {
+      removeTreePath(dir);
+      continue;
+    }
+    const entry = folderTree.find((e) => e.path === dir);
+    if (entry) entry.mdCount = count;
+    const item = treeItemFor(dir);
+    if (item) item.querySelector(".tree-badge").textContent = count;
+  }
+  if (searchInFilesMode || linkAuditMode) return;
+  // Filtered trees come from the backend, so query it again
+  if (filterText || showFavoritesOnly) renderFileTree();
+}
+
+function treeItemFor(path) {
+  return els.fileTree.querySelector(`.tree-item[data-path="${CSS.escape(path)}"]`);
+}
+
+function removeTreePath(path) {
+  folderTree = folderTree.filter((e) => e.path !== path);
+  if (filterText || showFavoritesOnly) return;
+  const item = treeItemFor(path);
+  if (!item) return;
+  if (item.classList.contains("dir")) item.nextElementSibling?.remove();
+  item.remove();
+}
+
+// Show a new file under the deepest loaded folder on its way from the root:
+// the file itself when its folder is loaded, otherwise the first missing folder
+function addTreeFile(path) {
+  const sep = currentFolderPath.includes("\\") ? "\\" : "/";
+  if (!path.startsWith(currentFolderPath + sep)) return;
+  const parts = path.slice(currentFolderPath.length).split(/[\\/]/).filter(Boolean);
+  let dirPath = currentFolderPath;
+  let container = filterText || showFavoritesOnly ? null : els.fileTree;
+  let depth = 0;
+  for (const [i, name] of parts.entries()) {
+    const entryPath = dirPath + sep + name;
+    const isDir = i < parts.length - 1;
+    const item = container?.querySelector(`:scope > .tree-item[data-path="${CSS.escape(entryPath)}"]`);
+    if (!item) {
+      const mdCount = isDir ? folderCounts?.[entryPath] ?? null : null;
+      const entry = { name, path: entryPath, isDir, children: null, mdCount };
+      if (dirPath === currentFolderPath && !folderTree.some((e) => e.path === entryPath)) {
+        folderTree.push(entry);
+      }
+      if (container) insertTreeEntry(entry, container, depth);
+      return;
+    }
+    if (!isDir) return;
+    container = item.nextElementSibling;
+    if (!container.dataset.loaded) return;
+    dirPath = entryPath;
+    depth++;
+  }
+}
+
+// Insert an entry among a container's children in tree order
+function insertTreeEntry(entry, container, depth) {
+  const fragment = document.createDocumentFragment();
+  renderEntries([entry], fragment, depth);
+  const name = entry.name.toLowerCase();
+  const before = [...container.querySelectorAll(":scope > .tree-item")].find((item) => {
+    if (item.classList.contains("tree-show-more")) return true;
+    const isDir = item.classList.contains("dir");
+    if (isDir !== entry.isDir) return entry.isDir;
+    return item.querySelector(".tree-name").textContent.toLowerCase() > name;
+  });
+  container.insertBefore(fragment, before || null);
+}
+
 async function refreshFolder() {
   if (!currentFolderPath) return;
   await openFolder(currentFolderPath);
@@ -979,9 +1278,22 @@ async function openFolderDialog() {
   }
 }
 
-function revealInTree(filePath) {
+async function revealInTree(filePath) {
   document.querySelectorAll(".tree-item.active").forEach((el) => el.classList.remove("active"));
-  const activeItem = document.querySelector(`.tree-item[data-path="${CSS.escape(filePath)}"]`);
+  let activeItem = els.fileTree.querySelector(`.tree-item[data-path="${CSS.escape(filePath)}"]`);
+  // Folders load on demand; open the ones leading to the file
+  if (!activeItem && currentFolderPath && filePath.startsWith(currentFolderPath + "/")) {
+    const parts = filePath.slice(currentFolderPath.length + 1).split("/");
+    let container = els.fileTree;
+    let dir = currentFolderPath;
+    for (const part of parts.slice(0, -1)) {
+      dir += "/" + part;
+      const item = await findTreeItem(container, dir);
+      if (!item) break;
+      container = await expandTreeDir(item);
+    }
+    activeItem = await findTreeItem(container, filePath);
+  }
   if (!activeItem) return;
   activeItem.classList.add("active");
   // Expand all parent .tree-children containers so the file is visible
@@ -1001,16 +1313,51 @@ function revealInTree(filePath) {
   activeItem.scrollIntoView({ block: "nearest" });
 }
 
+// The item for `path` among a container's direct children, loading further
+// pages of the folder until it turns up
+async function findTreeItem(container, path) {
+  for (;;) {
+    const item = container.querySelector(`:scope > .tree-item[data-path="${CSS.escape(path)}"]`);
+    if (item) return item;
+    const more = container.querySelector(":scope > .tree-show-more");
+    if (!more) return null;
+    await more.loadMore();
+  }
+}
+
 // ===== File Tree Rendering =====
-function renderFileTree() {
+async function renderFileTree() {
+  const token = ++treeRenderToken;
   els.fileTree.innerHTML = "";
   if (searchInFilesMode && searchInFilesResults.length > 0) {
     // Search results are rendered by renderSearchResults, not here
     return;
   }
-  if (folderTree.length === 0) return;
+  if (linkAuditMode) {
+    // Link problems are rendered by runLinkAudit, not here
+    return;
+  }
+  if (!currentFolderPath) return;
   const fragment = document.createDocumentFragment();
-  renderEntries(folderTree, fragment, 0);
+  if (filterText || showFavoritesOnly) {
+    // Filtering covers the whole folder, so the backend does it
+    let entries;
+    try {
+      entries = await invoke("filter_folder", {
+        path: currentFolderPath,
+        query: filterText,
+        only: showFavoritesOnly ? favorites : null,
+      });
+    } catch (err) {
+      console.error("Failed to filter folder:", err);
+      return;
+    }
+    if (token !== treeRenderToken) return;
+    renderEntries(entries, fragment, 0);
+  } else {
+    renderEntries(folderTree, fragment, 0);
+    if (folderTreeNext != null) renderShowMore(fragment, currentFolderPath, folderTreeNext, 0);
+  }
   els.fileTree.appendChild(fragment);
   // Mark currently open file as active and expand its parent folders
   if (currentPath) revealInTree(currentPath);
@@ -1018,16 +1365,11 @@ function renderFileTree() {
 
 function renderEntries(entries, container, depth) {
   for (const entry of entries) {
-    if (filterText) {
-      if (!entryMatchesFilter(entry, filterText)) continue;
-    }
-    if (showFavoritesOnly && !entry.isDir) {
-      if (!favorites.includes(entry.path)) continue;
+    // Counts can arrive while a page is still loading
+    if (entry.isDir && entry.mdCount == null && folderCounts) {
+      entry.mdCount = folderCounts[entry.path] || 0;
+      if (!entry.mdCount) continue;
     }
-    if (showFavoritesOnly && entry.isDir) {
-      if (!entryHasFavorites(entry)) continue;
-    }
-
     if (entry.isDir) {
       renderDirEntry(entry, container, depth);
     } else {
@@ -1036,6 +1378,54 @@ function renderEntries(entries, container, depth) {
   }
 }
 
+// Load one page of a folder's children into `container`
+async function loadFolderPage(dirPath, container, depth, offset = 0) {
+  const page = await invoke("list_folder", { path: dirPath, offset, sort: settings.treeSort });
+  const fragment = document.createDocumentFragment();
+  renderEntries(page.entries, fragment, depth);
+  if (page.nextOffset != null) renderShowMore(fragment, dirPath, page.nextOffset, depth);
+  container.appendChild(fragment);
+  if (dirPath === currentFolderPath) {
+    folderTree = folderTree.concat(page.entries);
+    folderTreeNext = page.nextOffset;
+  }
+}
+
+function renderShowMore(container, dirPath, offset, depth) {
+  const item = document.createElement("div");
+  item.className = "tree-item tree-show-more";
+  item.dataset.depth = depth;
+  item.textContent = "Show more…";
+  let loading = null;
+  item.loadMore = () => {
+    loading ??= (async () => {
+      const holder = document.createElement("div");
+      await loadFolderPage(dirPath, holder, depth, offset);
+      item.replaceWith(...holder.childNodes);
+    })().catch((err) => console.error("Failed to list folder:", err));
+    return loading;
+  };
+  item.addEventListener("click", () => item.loadMore());
+  container.appendChild(item);
+}
+
+// Expand a folder item, loading its children the first time
+async function expandTreeDir(item) {
+  const childContainer = item.nextElementSibling;
+  if (!childContainer.dataset.loaded) {
+    childContainer.dataset.loaded = "true";
+    try {
+      await loadFolderPage(item.dataset.path, childContainer, Number(item.dataset.depth) + 1);
+    } catch (err) {
+      delete childContainer.dataset.loaded;
+      console.error("Failed to list folder:", err);
+    }
+  }
+  item.querySelector(".tree-chevron").classList.add("expanded");
+  childContainer.classList.add("expanded");
+  return childContainer;
+}
+
 function renderDirEntry(entry, container, depth) {
   const item = document.createElement("div");
   item.className = "tree-item dir";
@@ -1054,9 +1444,10 @@ function renderDirEntry(entry, container, depth) {
   name.className = "tree-name";
   name.textContent = entry.name;
 
+  // Empty until the background scan has counted the folder
   const badge = document.createElement("span");
   badge.className = "tree-badge";
-  badge.textContent = entry.mdCount;
+  badge.textContent = entry.mdCount ?? "";
 
   item.appendChild(chevron);
   item.appendChild(icon);
@@ -1068,21 +1459,35 @@ function renderDirEntry(entry, container, depth) {
   childContainer.className = "tree-children";
   container.appendChild(childContainer);
 
+  // Filtered trees arrive fully loaded and expanded
   if (entry.children) {
+    childContainer.dataset.loaded = "true";
     renderEntries(entry.children, childContainer, depth + 1);
-  }
-
-  if (filterText || showFavoritesOnly) {
     chevron.classList.add("expanded");
     childContainer.classList.add("expanded");
   }
 
   item.addEventListener("click", () => {
-    chevron.classList.toggle("expanded");
-    childContainer.classList.toggle("expanded");
+    if (childContainer.classList.contains("expanded")) {
+      chevron.classList.remove("expanded");
+      childContainer.classList.remove("expanded");
+    } else {
+      expandTreeDir(item);
+    }
   });
 }
 
+// Tooltip for a file: name, title, word count and last modified
+function fileEntryDetails(entry) {
+  const lines = [entry.name];
+  if (entry.title && entry.title !== entry.name) lines.push(entry.title);
+  const facts = [];
+  if (entry.wordCount != null) facts.push(`${entry.wordCount.toLocaleString()} words`);
+  if (entry.modified != null) facts.push(`modified ${new Date(entry.modified).toLocaleString()}`);
+  if (facts.length) lines.push(facts.join(", "));
+  return lines.join("\n");
+}
+
 function renderFileEntry(entry, container, depth) {
   const item = document.createElement("div");
   item.className = "tree-item file";
@@ -1103,7 +1508,10 @@ function renderFileEntry(entry, container, depth) {
 
   const name = document.createElement("span");
   name.className = "tree-name";
-  name.textContent = entry.name;
+  // Titles are what the tree is ordered by when sorting by title
+  name.textContent = settings.treeSort === "title" && entry.title ? entry.title : entry.name;
+  item.title = fileEntryDetails(entry);
+  if (entry.gitStatus && entry.gitStatus !== "clean") item.classList.add(`git-${entry.gitStatus}`);
 
   const star = document.createElement("span");
   star.className = "tree-star" + (favorites.includes(entry.path) ? " favorited" : "");
@@ -1203,22 +1611,6 @@ function showFileContextMenu(x, y, filePath) {
   }, 0);
 }
 
-function entryMatchesFilter(entry, text) {
-  if (entry.name.toLowerCase().includes(text)) return true;
-  if (entry.isDir && entry.children) {
-    return entry.children.some((child) => entryMatchesFilter(child, text));
-  }
-  return false;
-}
-
-function entryHasFavorites(entry) {
-  if (!entry.isDir) return favorites.includes(entry.path);
-  if (entry.children) {
-    return entry.children.some((child) => entryHasFavorites(child));
-  }
-  return false;
-}
-
 // ===== Favorites =====
 function toggleFavorite(path) {
   const idx = favorites.indexOf(path);
@@ -1233,8 +1625,119 @@ function toggleFavoritesFilter() {
   renderFileTree();
 }
 
+// ===== Outline =====
+function toggleOutline() {
+  outlineOpen = !outlineOpen;
+  localStorage.setItem("md-outline", outlineOpen);
+  els.btnNavOutline.classList.toggle("active", outlineOpen);
+  loadOutline();
+}
+
+async function loadOutline() {
+  if (!outlineOpen || !currentPath) {
+    els.outlinePanel.style.display = "none";
+    return;
+  }
+  try {
+    const outline = await invoke("get_outline", { path: currentPath });
+    els.outlineTree.innerHTML = "";
+    renderOutlineEntries(outline, els.outlineTree, 0);
+    els.outlinePanel.style.display = outline.length > 0 ? "flex" : "none";
+  } catch (err) {
+    console.error("Failed to load outline:", err);
+    els.outlinePanel.style.display = "none";
+  }
+}
+
+function renderOutlineEntries(entries, container, depth) {
+  for (const entry of entries) {
+    const item = document.createElement("div");
+    item.className = "outline-item";
+    item.dataset.depth = Math.min(depth, 5);
+    item.textContent = entry.text;
+    item.title = entry.text;
+    item.addEventListener("click", () => {
+      if (editMode) {
+        scrollEditorToLine(entry.line);
+        return;
+      }
+      const target = document.getElementById(entry.anchor);
+      if (target) target.scrollIntoView({ block: "start" });
+    });
+    container.appendChild(item);
+    renderOutlineEntries(entry.children, container, depth + 1);
+  }
+}
+
+// ===== Document metadata =====
+// Title, author, date and tags from the front matter, in the sidebar
+function renderMetadata(metadata) {
+  els.metadataList.innerHTML = "";
+  const rows = [
+    ["Title", metadata?.title],
+    ["Author", metadata?.author],
+    ["Date", metadata?.date],
+  ];
+  for (const [label, value] of rows) {
+    if (!value) continue;
+    const dt = document.createElement("dt");
+    dt.textContent = label;
+    const dd = document.createElement("dd");
+    dd.textContent = value;
+    els.metadataList.append(dt, dd);
+  }
+  const tags = metadata?.tags || [];
+  if (tags.length > 0) {
+    const dt = document.createElement("dt");
+    dt.textContent = "Tags";
+    const dd = document.createElement("dd");
+    for (const tag of tags) {
+      const chip = document.createElement("span");
+      chip.className = "metadata-tag";
+      chip.textContent = tag;
+      dd.appendChild(chip);
+    }
+    els.metadataList.append(dt, dd);
+  }
+  els.metadataPanel.style.display = els.metadataList.children.length > 0 ? "flex" : "none";
+}
+
+// ===== Backlinks =====
+async function loadBacklinks() {
+  if (!currentPath || !currentFolderPath) {
+    els.backlinksPanel.style.display = "none";
+    return;
+  }
+  try {
+    const backlinks = await invoke("get_backlinks", { path: currentPath });
+    els.backlinksList.innerHTML = "";
+    els.backlinksHeader.textContent = `Linked from (${backlinks.length})`;
+    for (const link of backlinks) {
+      const item = document.createElement("div");
+      item.className = "backlink-item";
+      item.title = link.sourcePath;
+      const name = document.createElement("div");
+      name.className = "backlink-name";
+      name.textContent = link.sourceName;
+      const snippet = document.createElement("div");
+      snippet.className = "backlink-snippet";
+      snippet.textContent = link.snippet;
+      item.append(name, snippet);
+      item.addEventListener("click", async () => {
+        await openFile(link.sourcePath);
+        scrollPreviewToSourceLine(link.line);
+      });
+      els.backlinksList.appendChild(item);
+    }
+    els.backlinksPanel.style.display = backlinks.length > 0 ? "flex" : "none";
+  } catch (err) {
+    console.error("Failed to load backlinks:", err);
+    els.backlinksPanel.style.display = "none";
+  }
+}
+
 // ===== Edit Mode =====
-async function enterEditMode() {
+async function enterEditMode(sourceLine = null) {
   if (!currentPath || editMode) return;
   if (findBarOpen) closeFindBar();
   try {
@@ -1242,6 +1745,12 @@ async function enterEditMode() {
     const scrollRatio = els.contentScroll.scrollHeight > els.contentScroll.clientHeight
       ? els.contentScroll.scrollTop / (els.contentScroll.scrollHeight - els.contentScroll.clientHeight)
       : 0;
+    // Prefer the source line of the topmost visible block
+    let line = sourceLine;
+    if (!line) {
+      const el = topVisiblePreviewBlock();
+      if (el) line = await previewElementToLine(el);
+    }
 
     const raw = await invoke("read_file_content", { path: currentPath });
     els.editor.value = raw;
@@ -1255,8 +1764,12 @@ async function enterEditMode() {
     editMode = true;
     updateEditorLineNumbers();
 
-    // Restore scroll position proportionally in the editor
+    // Restore scroll position in the editor, proportionally if no line is known
     requestAnimationFrame(() => {
+      if (line) {
+        scrollEditorToLine(line);
+        return;
+      }
       const editorMaxScroll = els.editor.scrollHeight - els.editor.clientHeight;
       els.editor.scrollTop = Math.round(scrollRatio * editorMaxScroll);
       els.editorLineNumbers.scrollTop = els.editor.scrollTop;
@@ -1272,6 +1785,8 @@ function exitEditMode() {
   const scrollRatio = els.editor.scrollHeight > els.editor.clientHeight
     ? els.editor.scrollTop / (els.editor.scrollHeight - els.editor.clientHeight)
     : 0;
+  const topLine = Math.floor(els.editor.scrollTop / EDITOR_LINE_HEIGHT) + 1;
+  const source = els.editor.value;
 
   els.editorContainer.style.display = "none";
   els.content.style.display = "block";
@@ -1282,13 +1797,69 @@ function exitEditMode() {
   els.btnTts.style.display = "";
   editMode = false;
 
-  // Restore scroll position proportionally in the preview
-  requestAnimationFrame(() => {
+  // Scroll the preview to the block rendered from the top editor line,
+  // falling back to the proportional position
+  requestAnimationFrame(async () => {
+    if (await scrollPreviewToLine(source, topLine)) return;
     const maxScroll = els.contentScroll.scrollHeight - els.contentScroll.clientHeight;
     els.contentScroll.scrollTop = Math.round(scrollRatio * maxScroll);
   });
 }
 
+// ===== Source Mapping =====
+const EDITOR_LINE_HEIGHT = 14 * 1.6; // matches editor font-size * line-height
+
+// First rendered block that is at least partly below the top of the preview
+function topVisiblePreviewBlock() {
+  const top = els.contentScroll.getBoundingClientRect().top;
+  for (const el of els.content.querySelectorAll("[data-sourcepos]")) {
+    if (el.getBoundingClientRect().bottom > top) return el;
+  }
+  return null;
+}
+
+async function previewElementToLine(el) {
+  try {
+    const block = await invoke("block_to_source", { sourcepos: el.dataset.sourcepos });
+    return block.startLine;
+  } catch (e) {
+    console.error("Failed to map block to source:", e);
+    return null;
+  }
+}
+
+async function scrollPreviewToLine(content, line) {
+  try {
+    const blocks = await invoke("source_line_to_blocks", { content, line });
+    for (const block of blocks) {
+      const el = els.content.querySelector(`[data-sourcepos="${block.sourcepos}"]`);
+      if (el) {
+        el.scrollIntoView({ block: "start" });
+        return true;
+      }
+    }
+  } catch (e) {
+    console.error("Failed to map source line:", e);
+  }
+  return false;
+}
+
+// Scroll the preview to the innermost element rendered from a source line
+function scrollPreviewToSourceLine(line) {
+  let best = null;
+  for (const el of els.content.querySelectorAll("[data-sourcepos]")) {
+    const [start, end] = el.dataset.sourcepos.split("-").map((p) => parseInt(p));
+    if (start <= line && line <= end) best = el;
+    else if (start > line) break;
+  }
+  if (best) best.scrollIntoView({ block: "center" });
+}
+
+function scrollEditorToLine(line) {
+  els.editor.scrollTop = Math.max(0, (line - 1) * EDITOR_LINE_HEIGHT);
+  els.editorLineNumbers.scrollTop = els.editor.scrollTop;
+}
+
 async function cancelEdit() {
   exitEditMode();
   // Re-render the file to discard any unsaved changes
@@ -1351,7 +1922,12 @@ async function exportPdf() {
     const prevTitle = els.toolbarTitle.textContent;
     els.toolbarTitle.textContent = "Exporting PDF...";
     try {
-      await invoke("export_pdf", { sourcePath: currentPath, outputPath, fontSize: settings.fontSize });
+      await invoke("export_pdf", {
+        sourcePath: currentPath,
+        outputPath,
+        fontSize: settings.fontSize,
+        theme: settings.themeName,
+      });
       els.toolbarTitle.textContent = prevTitle;
       await invoke("open_path", { path: outputPath });
     } catch (err) {
@@ -1457,6 +2033,14 @@ function cleanSvgForExport(svgString) {
 }
 
 async function renderMermaidBlocks(container) {
+  // Diagrams the backend already rendered to SVG
+  container.querySelectorAll(".diagram-rendered img").forEach((img) => {
+    const base64 = img.getAttribute("src").split(",")[1] || "";
+    const bytes = Uint8Array.from(atob(base64), (c) => c.charCodeAt(0));
+    const svg = new TextDecoder().decode(bytes);
+    img.parentElement.addEventListener("click", () => openMermaidFullscreen(svg));
+  });
+
   const mermaidBlocks = container.querySelectorAll("pre code.language-mermaid");
   if (mermaidBlocks.length === 0) return;
 
@@ -1601,8 +2185,9 @@ function openMermaidFullscreen(svg) {
 }
 
 // ===== Code Highlighting =====
-async function highlightCodeBlocks() {
-  const codeBlocks = els.content.querySelectorAll("pre code");
+async function highlightCodeBlocks(container = els.content) {
+  // Blocks in languages the backend knows arrive pre-highlighted (class "hljs")
+  const codeBlocks = container.querySelectorAll("pre code:not(.hljs)");
   if (codeBlocks.length === 0) return;
 
   try {
@@ -1635,6 +2220,7 @@ function applyTheme() {
   const scheme = getCurrentScheme();
   const dataTheme = `${settings.themeName}-${scheme}`;
   document.body.setAttribute("data-theme", dataTheme);
+  applyCodeTheme(scheme);
   // Sync mermaid theme if loaded
   if (mermaidLib) {
     mermaidLib.initialize({
@@ -1650,6 +2236,25 @@ function applyTheme() {
   }
 }
 
+// Token colours for server-highlighted code come from the backend palettes
+async function applyCodeTheme(scheme) {
+  try {
+    const css = await invoke("highlight_theme_css", {
+      theme: settings.themeName,
+      dark: scheme === "dark",
+    });
+    let style = document.getElementById("code-theme");
+    if (!style) {
+      style = document.createElement("style");
+      style.id = "code-theme";
+      document.head.appendChild(style);
+    }
+    style.textContent = css;
+  } catch (e) {
+    console.error("Failed to load code theme:", e);
+  }
+}
+
 // Listen for system theme changes
 window.matchMedia("(prefers-color-scheme: dark)").addEventListener("change", () => {
   if (settings.colorScheme === "system") {
@@ -1793,6 +2398,7 @@ function openSettings() {
   els.settingsOverlay.style.display = "flex";
   // Sync UI with current state
   syncColorPickersToTheme();
+  updateTrustSettings();
 }
 
 function closeSettings() {
@@ -1801,6 +2407,117 @@ function closeSettings() {
   }
 }
 
+// ===== Render Options =====
+async function loadRenderOptions() {
+  let options;
+  try {
+    options = await invoke("get_render_options");
+  } catch (err) {
+    console.error("Failed to load render options:", err);
+    return;
+  }
+  document.querySelectorAll("[data-render-option]").forEach((input) => {
+    input.checked = !!options[input.dataset.renderOption];
+    input.addEventListener("change", async () => {
+      options[input.dataset.renderOption] = input.checked;
+      try {
+        await invoke("set_render_options", { options });
+      } catch (err) {
+        console.error("Failed to save render options:", err);
+        return;
+      }
+      if (currentPath && !editMode) {
+        const scrollTop = els.contentScroll.scrollTop;
+        await openFile(currentPath);
+        els.contentScroll.scrollTop = scrollTop;
+      }
+    });
+  });
+}
+
+// Which files the folder tree and search include
+async function loadScanOptions() {
+  let options;
+  try {
+    options = await invoke("get_scan_options");
+  } catch (err) {
+    console.error("Failed to load scan options:", err);
+    return;
+  }
+  const showHidden = document.getElementById("setting-show-hidden");
+  const patterns = document.getElementById("setting-ignore-patterns");
+  showHidden.checked = options.showHidden;
+  patterns.value = options.ignorePatterns.join(", ");
+
+  const save = async () => {
+    options.showHidden = showHidden.checked;
+    options.ignorePatterns = patterns.value.split(",").map((p) => p.trim()).filter(Boolean);
+    try {
+      await invoke("set_scan_options", { options });
+    } catch (err) {
+      console.error("Failed to save scan options:", err);
+      return;
+    }
+    refreshFolder();
+  };
+  showHidden.addEventListener("change", save);
+  patterns.addEventListener("change", save);
+}
+
+// ===== Folder Trust =====
+// The workspace a trust decision applies to: the open folder when it holds
+// the current file, otherwise the file's own folder.
+function trustFolderPath() {
+  if (currentPath) {
+    if (currentFolderPath && currentPath.startsWith(currentFolderPath + "/")) return currentFolderPath;
+    return parentDirOf(currentPath);
+  }
+  return currentFolderPath;
+}
+
+async function updateTrustSettings() {
+  const folder = trustFolderPath();
+  if (!folder) {
+    els.settingsTrustLabel.textContent = "No folder open";
+    els.btnSettingsTrust.disabled = true;
+    return;
+  }
+  els.btnSettingsTrust.disabled = false;
+  try {
+    const status = await invoke("get_trust_status", { path: folder });
+    const name = (status.folder || folder).split("/").pop();
+    els.settingsTrustLabel.textContent = status.trusted ? `Trusted: ${name}` : `Not trusted: ${name}`;
+    els.settingsTrustLabel.title = status.folder || folder;
+    els.btnSettingsTrust.textContent = status.trusted ? "Untrust" : "Trust Folder";
+  } catch (err) {
+    els.settingsTrustLabel.textContent = "Trust status unavailable";
+  }
+}
+
+async function toggleFolderTrust() {
+  const folder = trustFolderPath();
+  if (!folder) return;
+  try {
+    const status = await invoke("get_trust_status", { path: folder });
+    if (status.trusted) {
+      await invoke("untrust_folder", { path: folder });
+    } else {
+      const { ask } = await import("@tauri-apps/plugin-dialog");
+      const confirmed = await ask(
+        `Documents in ${folder} will be able to run scripts and embed arbitrary HTML. Only trust folders whose contents you wrote or reviewed.`,
+        { title: "Trust this folder?", kind: "warning", okLabel: "Trust", cancelLabel: "Cancel" }
+      );
+      if (!confirmed) return;
+      await invoke("trust_folder", { path: folder });
+    }
+  } catch (err) {
+    console.error("Failed to update folder trust:", err);
+    return;
+  }
+  updateTrustSettings();
+  if (currentPath && !editMode) openFile(currentPath);
+}
+
 // ===== Git Status =====
 async function refreshGitStatus(path) {
   try {
@@ -2341,7 +3058,7 @@ async function startTts() {
       model: ttsSettings.provider === "openai" ? "tts-1" : null,
     };
 
-    const result = await invoke("tts_generate", { markdown, config });
+    const result = await invoke("tts_generate", { markdown, path: currentPath, config });
     els.toolbarTitle.textContent = prevTitle;
 
     ttsTotalChunks = result.totalChunks;
@@ -2519,6 +3236,87 @@ function openTtsKeyModal(provider) {
   els.ttsKeyInput.focus();
 }
 
+// ===== Quick Open =====
+let quickOpenHits = [];
+let quickOpenSelected = 0;
+// Ignores responses to queries typed over since
+let quickOpenRequest = 0;
+
+function openQuickOpen() {
+  if (!currentFolderPath) return;
+  els.quickOpenInput.value = "";
+  els.quickOpenOverlay.style.display = "flex";
+  els.quickOpenInput.focus();
+  runQuickOpen();
+}
+
+function closeQuickOpen() {
+  els.quickOpenOverlay.style.display = "none";
+  quickOpenHits = [];
+  els.quickOpenResults.innerHTML = "";
+}
+
+async function runQuickOpen() {
+  const request = ++quickOpenRequest;
+  let hits;
+  try {
+    hits = await invoke("quick_open", { query: els.quickOpenInput.value });
+  } catch (err) {
+    console.warn("Quick open failed:", err);
+    hits = [];
+  }
+  if (request !== quickOpenRequest) return;
+  quickOpenHits = hits;
+  renderQuickOpen();
+}
+
+// Wrap the characters at `indexes` in <mark>; indexes count characters
+function markChars(text, indexes) {
+  const marked = new Set(indexes);
+  return Array.from(text)
+    .map((ch, i) => (marked.has(i) ? `<mark>${escapeHtml(ch)}</mark>` : escapeHtml(ch)))
+    .join("");
+}
+
+function renderQuickOpen() {
+  els.quickOpenResults.innerHTML = "";
+  quickOpenSelected = 0;
+  if (quickOpenHits.length === 0) {
+    els.quickOpenResults.innerHTML = `<div class="search-result-count">No matching files</div>`;
+    return;
+  }
+  const frag = document.createDocumentFragment();
+  quickOpenHits.forEach((hit, i) => {
+    const item = document.createElement("div");
+    item.className = "quick-open-item";
+    const titleEl = document.createElement("span");
+    titleEl.className = "quick-open-title";
+    const pathEl = document.createElement("span");
+    pathEl.className = "quick-open-path";
+    if (hit.title) {
+      titleEl.innerHTML = markChars(hit.title, hit.titleMatches);
+      pathEl.innerHTML = markChars(hit.relativePath, hit.pathMatches);
+      item.append(titleEl, pathEl);
+    } else {
+      titleEl.innerHTML = markChars(hit.relativePath, hit.pathMatches);
+      item.append(titleEl);
+    }
+    item.addEventListener("mousemove", () => selectQuickOpen(i));
+    item.addEventListener("click", () => { closeQuickOpen(); openFile(hit.filePath); });
+    frag.appendChild(item);
+  });
+  els.quickOpenResults.appendChild(frag);
+  selectQuickOpen(0);
+}
+
+function selectQuickOpen(index) {
+  const items = els.quickOpenResults.querySelectorAll(".quick-open-item");
+  if (items.length === 0) return;
+  quickOpenSelected = Math.max(0, Math.min(index, items.length - 1));
+  items.forEach((item, i) => item.classList.toggle("selected", i === quickOpenSelected));
+  items[quickOpenSelected].scrollIntoView({ block: "nearest" });
+}
+
 function closeTtsKeyModal() {
   els.ttsKeyOverlay.style.display = "none";
   ttsKeyModalProvider = null;
@@ -3061,8 +3859,7 @@ function scrollEditorToSelection() {
   const text = els.editor.value;
   const selStart = els.editor.selectionStart;
   const linesBefore = text.substring(0, selStart).split("\n").length;
-  const lineHeight = 14 * 1.6; // matches editor font-size * line-height
-  const targetScroll = (linesBefore - 1) * lineHeight - els.editor.clientHeight / 2;
+  const targetScroll = (linesBefore - 1) * EDITOR_LINE_HEIGHT - els.editor.clientHeight / 2;
   els.editor.scrollTop = Math.max(0, targetScroll);
   els.editorLineNumbers.scrollTop = els.editor.scrollTop;
 }
@@ -3113,10 +3910,96 @@ function editorReplaceAll() {
   findInEditor(els.editorFindInput.value);
 }
 
+// ===== Link Audit =====
+function toggleLinkAudit() {
+  linkAuditMode = !linkAuditMode;
+  els.btnCheckLinks.classList.toggle("active", linkAuditMode);
+  if (linkAuditMode) {
+    if (searchInFilesMode) toggleSearchInFiles();
+    runLinkAudit();
+  } else {
+    renderFileTree();
+  }
+}
+
+async function runLinkAudit() {
+  const target = currentFolderPath || currentPath;
+  if (!target) {
+    els.fileTree.innerHTML = `<div class="search-result-count">Open a folder or file to check its links</div>`;
+    return;
+  }
+  els.fileTree.innerHTML = `<div class="search-result-count">Checking links...</div>`;
+  let problems;
+  try {
+    problems = await invoke("audit_links", { path: target, checkRemote: linkAuditRemote });
+  } catch (err) {
+    els.fileTree.innerHTML = `<div class="search-result-count">Link check failed: ${escapeHtml(String(err))}</div>`;
+    return;
+  }
+  if (!linkAuditMode) return;
+
+  const frag = document.createDocumentFragment();
+  const header = document.createElement("div");
+  header.className = "search-result-count link-audit-header";
+  const count = document.createElement("span");
+  count.textContent = problems.length === 0
+    ? "No broken links found"
+    : `${problems.length} broken link${problems.length !== 1 ? "s" : ""}`;
+  const remoteLabel = document.createElement("label");
+  const remoteToggle = document.createElement("input");
+  remoteToggle.type = "checkbox";
+  remoteToggle.checked = linkAuditRemote;
+  remoteToggle.addEventListener("change", () => {
+    linkAuditRemote = remoteToggle.checked;
+    runLinkAudit();
+  });
+  remoteLabel.append(remoteToggle, " Check remote URLs");
+  header.append(count, remoteLabel);
+  frag.appendChild(header);
+
+  let group = null;
+  for (const problem of problems) {
+    if (!group || group.dataset.path !== problem.filePath) {
+      group = document.createElement("div");
+      group.className = "search-result-group";
+      group.dataset.path = problem.filePath;
+      const fileEl = document.createElement("div");
+      fileEl.className = "search-result-file";
+      fileEl.textContent = problem.fileName;
+      fileEl.title = problem.filePath;
+      fileEl.addEventListener("click", () => openFile(problem.filePath));
+      group.appendChild(fileEl);
+      frag.appendChild(group);
+    }
+    const lineEl = document.createElement("div");
+    lineEl.className = "search-result-line";
+    const lineNum = document.createElement("span");
+    lineNum.className = "search-result-linenum";
+    lineNum.textContent = problem.line;
+    const textEl = document.createElement("span");
+    textEl.className = "search-result-text";
+    textEl.textContent = problem.message;
+    textEl.title = problem.target;
+    lineEl.append(lineNum, textEl);
+    lineEl.addEventListener("click", async () => {
+      await openFile(problem.filePath);
+      scrollPreviewToSourceLine(problem.line);
+    });
+    group.appendChild(lineEl);
+  }
+  els.fileTree.innerHTML = "";
+  els.fileTree.appendChild(frag);
+}
+
 // ===== Search in Files =====
 function toggleSearchInFiles() {
+  if (!searchInFilesMode && linkAuditMode) {
+    linkAuditMode = false;
+    els.btnCheckLinks.classList.remove("active");
+  }
   searchInFilesMode = !searchInFilesMode;
   els.btnSearchContents.classList.toggle("active", searchInFilesMode);
+  els.searchOptions.style.display = searchInFilesMode ? "flex" : "none";
   els.filterInput.placeholder = searchInFilesMode ? "Search in files..." : "Filter files...";
   els.filterInput.value = "";
   filterText = "";
@@ -3125,21 +4008,48 @@ function toggleSearchInFiles() {
   els.filterInput.focus();
 }
 
+function rerunSearchInFiles() {
+  const query = els.filterInput.value.trim();
+  if (searchInFilesMode && query) performSearchInFiles(query);
+}
+
+const splitGlobs = (value) => value.split(",").map((g) => g.trim()).filter(Boolean);
+
 async function performSearchInFiles(query) {
   if (!currentFolderPath || !query) return;
+  const options = {
+    ...searchOptions,
+    include: splitGlobs(els.searchInclude.value),
+    exclude: splitGlobs(els.searchExclude.value),
+    contextLines: 2,
+  };
+  // The index can't match case, whole words or regexes
+  if (searchOptions.ranked && !searchOptions.caseSensitive && !searchOptions.wholeWord && !searchOptions.regex) {
+    try {
+      searchIndexHits = await invoke("query_search_index", { query, options });
+      renderIndexResults();
+      return;
+    } catch (err) {
+      // Still building: fall back to reading the files
+      console.warn("Search index unavailable:", err);
+    }
+  }
+  searchIndexHits = null;
   try {
-    searchInFilesResults = await invoke("search_in_files", {
-      folder: currentFolderPath,
-      query,
-      caseSensitive: false,
-    });
-    renderSearchResults(query);
+    searchInFilesResults = await invoke("search_in_files", { folder: currentFolderPath, query, options });
+    renderSearchResults();
   } catch (err) {
-    console.error("Search in files failed:", err);
+    // Invalid regexes and globs are reported in place of the results
+    searchInFilesResults = [];
+    els.fileTree.innerHTML = "";
+    const errorEl = document.createElement("div");
+    errorEl.className = "search-result-count";
+    errorEl.textContent = String(err);
+    els.fileTree.appendChild(errorEl);
   }
 }
 
-function renderSearchResults(query) {
+function renderSearchResults() {
   els.fileTree.innerHTML = "";
   if (searchInFilesResults.length === 0) {
     els.fileTree.innerHTML = `<div class="search-result-count">No results found</div>`;
@@ -3189,20 +4099,13 @@ function renderSearchResults(query) {
 
       const textEl = document.createElement("span");
       textEl.className = "search-result-text";
-      // Highlight the query in the line content
-      const line = match.lineContent.trim();
-      const lowerLine = line.toLowerCase();
-      const lowerQuery = query.toLowerCase();
-      let html = "";
-      let pos = 0;
-      let idx;
-      while ((idx = lowerLine.indexOf(lowerQuery, pos)) !== -1) {
-        html += escapeHtml(line.slice(pos, idx));
-        html += `<mark>${escapeHtml(line.slice(idx, idx + query.length))}</mark>`;
-        pos = idx + query.length;
-      }
-      html += escapeHtml(line.slice(pos));
-      textEl.innerHTML = html;
+      // Highlight the match; offsets count characters, not UTF-16 units
+      const chars = Array.from(match.lineContent);
+      const before = chars.slice(0, match.charStart).join("").trimStart();
+      const found = chars.slice(match.charStart, match.charEnd).join("");
+      const after = chars.slice(match.charEnd).join("").trimEnd();
+      textEl.innerHTML = `${escapeHtml(before)}<mark>${escapeHtml(found)}</mark>${escapeHtml(after)}`;
+      textEl.title = [...match.contextBefore, match.lineContent, ...match.contextAfter].join("\n");
 
       lineEl.appendChild(lineNum);
       lineEl.appendChild(textEl);
@@ -3237,6 +4140,57 @@ function renderSearchResults(query) {
   els.fileTree.appendChild(frag);
 }
 
+function renderIndexResults() {
+  els.fileTree.innerHTML = "";
+  if (searchIndexHits.length === 0) {
+    els.fileTree.innerHTML = `<div class="search-result-count">No results found</div>`;
+    return;
+  }
+
+  const frag = document.createDocumentFragment();
+  const countEl = document.createElement("div");
+  countEl.className = "search-result-count";
+  const fileCount = searchIndexHits.length;
+  countEl.textContent = `${fileCount} file${fileCount !== 1 ? "s" : ""}, most relevant first`;
+  frag.appendChild(countEl);
+
+  for (const hit of searchIndexHits) {
+    const groupEl = document.createElement("div");
+    groupEl.className = "search-result-group";
+
+    const fileEl = document.createElement("div");
+    fileEl.className = "search-result-file";
+    fileEl.textContent = hit.title || hit.fileName;
+    fileEl.title = hit.filePath;
+    fileEl.addEventListener("click", () => openFile(hit.filePath));
+    groupEl.appendChild(fileEl);
+
+    if (hit.snippet) {
+      const lineEl = document.createElement("div");
+      lineEl.className = "search-result-line";
+      const textEl = document.createElement("span");
+      textEl.className = "search-result-text";
+      // Highlight offsets count characters, not UTF-16 units
+      const chars = Array.from(hit.snippet.replace(/\s/g, " "));
+      let html = "";
+      let pos = 0;
+      for (const [start, end] of hit.highlights) {
+        html += escapeHtml(chars.slice(pos, start).join(""));
+        html += `<mark>${escapeHtml(chars.slice(start, end).join(""))}</mark>`;
+        pos = end;
+      }
+      textEl.innerHTML = html + escapeHtml(chars.slice(pos).join(""));
+      lineEl.appendChild(textEl);
+      lineEl.addEventListener("click", () => openFile(hit.filePath));
+      groupEl.appendChild(lineEl);
+    }
+
+    frag.appendChild(groupEl);
+  }
+
+  els.fileTree.appendChild(frag);
+}
+
 // ===== Helpers =====
 function escapeHtml(text) {
   const div = document.createElement("div");